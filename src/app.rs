@@ -12,7 +12,12 @@ impl App {
     }
 
     pub fn go(mut self) {
-        self.controller.run();
+        let errors = self.controller.run();
+        // Dropping the interface gives the terminal back before we print.
+        drop(self);
+        for e in errors {
+            eprintln!("letcom: {}", e);
+        }
     }
 }
 
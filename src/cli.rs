@@ -0,0 +1,518 @@
+use std::{env, fmt, fs, io, path::Path, sync::Arc};
+
+use crate::{
+    controller::{
+        mailer::{self, MailError, Mailing, Outbox, Recipients, SmtpOutbox},
+        secrets::{secrets_path, SecretError, SecretStore},
+        settings::{load_settings, SettingsError, SettingsRepr},
+        worker::{SendProgress, SendTask},
+    },
+    data_handler::{
+        backup,
+        handler::{DataFile, DataHandler, LoadError},
+        letter::{new_letter, parse_date, Letter, LetterFilter, LetterState},
+        lock::LockError,
+        make_mut, make_ref,
+        persona::{export_persona, import_persona_file},
+        storage::StorageKind,
+        Represent,
+    },
+};
+
+/// Environment variable with the master passphrase for the secret store.
+const PASSPHRASE_VAR: &str = "LETCOM_PASSPHRASE";
+
+const USAGE: &str = "\
+Использование: letcom [КОМАНДА]
+Без команды запускается интерактивный интерфейс.
+
+  persona list                    список персон
+  persona import ФАЙЛ.tsv         импорт персон из таблицы
+  persona export [ФАЙЛ.tsv]       экспорт персон (по умолчанию в stdout)
+  tag list                        список меток
+  tag members МЕТКА               персоны с меткой и вложенными метками
+  letter list [--state СОСТОЯНИЕ] [--since ДД.ММ.ГГГГ] [--until ДД.ММ.ГГГГ]
+                                  список писем с состоянием: draft, scheduled,
+                                  queued, sent, partially_failed или failed
+  letter new ТЕКСТ [--topic ТЕМА] [--attach ФАЙЛ]...
+                                  создать письмо из текстового файла
+  send ПИСЬМО [--tag МЕТКА]... [--to АДРЕС]... [--cc АДРЕС]... [--bcc АДРЕС]...
+       [--profile ПРОФИЛЬ]        отправить письмо
+  report [ФАЙЛ.csv]               статистика рассылок по персонам и меткам
+                                  (по умолчанию в stdout)
+  storage convert json|journal|database
+                                  перевести данные в другой формат хранения
+  help                            эта справка
+
+SMTP-пароль берётся из хранилища, мастер-пароль задаётся переменной LETCOM_PASSPHRASE.";
+
+#[derive(Debug)]
+pub enum CliError {
+    Usage(String),
+    NotFound(String),
+    Io(io::Error),
+    Settings(SettingsError),
+    Secrets(SecretError),
+    Mail(MailError),
+    Incomplete(usize),
+    Load(LoadError),
+    Locked(LockError),
+    /// The profile's SMTP password is in the store, but no passphrase was given.
+    NoPassphrase(String),
+}
+
+impl fmt::Display for CliError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CliError::Usage(info) => write!(f, "{}\n\n{}", info, USAGE),
+            CliError::NotFound(info) => write!(f, "не найдено: {}", info),
+            CliError::Io(e) => write!(f, "ошибка ввода-вывода: {}", e),
+            CliError::Settings(e) => write!(f, "настройки: {}", e),
+            CliError::Secrets(e) => write!(f, "хранилище паролей: {}", e),
+            CliError::Mail(e) => write!(f, "отправка: {}", e),
+            CliError::Load(e) => write!(f, "{}", e),
+            CliError::Locked(e) => write!(f, "{}", e),
+            CliError::NoPassphrase(profile) => write!(
+                f,
+                "SMTP-пароль профиля {:?} лежит в хранилище: задайте мастер-пароль в {}",
+                profile, PASSPHRASE_VAR
+            ),
+            CliError::Incomplete(failed) => write!(f, "не доставлено писем: {}", failed),
+        }
+    }
+}
+
+impl std::error::Error for CliError {}
+
+impl From<io::Error> for CliError {
+    fn from(e: io::Error) -> Self {
+        CliError::Io(e)
+    }
+}
+
+pub fn run(args: &[String]) -> Result<(), CliError> {
+    let args = args.iter().map(|arg| arg.as_str()).collect::<Vec<_>>();
+    let mut data_handler = DataHandler::new();
+    data_handler.set_storage(
+        load_settings()
+            .map(|settings| make_ref(&settings).storage)
+            .unwrap_or_default(),
+    );
+    execute(&mut data_handler, &args)
+}
+
+/// Commands that change the data; they need the lock and only they save it.
+fn mutates(args: &[&str]) -> bool {
+    matches!(
+        args,
+        ["persona", "import", ..] | ["letter", "new", ..] | ["send", ..] | ["storage", ..]
+    )
+}
+
+fn execute(data_handler: &mut DataHandler, args: &[&str]) -> Result<(), CliError> {
+    if let Err(e) = data_handler.lock() {
+        if mutates(args) {
+            return Err(CliError::Locked(e));
+        }
+    }
+    data_handler.load_all();
+    if let Some(e) = data_handler.take_load_errors().into_iter().next() {
+        return Err(CliError::Load(e));
+    }
+    match args {
+        ["help"] | ["--help"] | ["-h"] => println!("{}", USAGE),
+        ["persona", "list"] => persona_list(data_handler),
+        ["persona", "import", path] => persona_import(data_handler, path)?,
+        ["persona", "export"] => println!("{}", persona_export(data_handler)),
+        ["persona", "export", path] => fs::write(path, persona_export(data_handler))?,
+        ["tag", "list"] => tag_list(data_handler),
+        ["tag", "members", label] => tag_members(data_handler, label)?,
+        ["letter", "list", options @ ..] => letter_list(data_handler, options)?,
+        ["letter", "new", text_path, options @ ..] => letter_new(data_handler, text_path, options)?,
+        ["send", letter_id, options @ ..] => send(data_handler, letter_id, options)?,
+        ["report"] => println!("{}", data_handler.contact_report().to_csv()),
+        ["report", path] => report_write(data_handler, path)?,
+        ["storage", "convert", kind] => storage_convert(data_handler, kind)?,
+        _ => return Err(CliError::Usage(format!("Неизвестная команда: {:?}", args))),
+    }
+    if mutates(args) {
+        data_handler.finalize()?;
+    }
+    if let Some(e) = data_handler.take_load_errors().into_iter().next() {
+        return Err(CliError::Load(e));
+    }
+    Ok(())
+}
+
+fn storage_convert(data_handler: &mut DataHandler, kind: &str) -> Result<(), CliError> {
+    let kind = StorageKind::parse(kind)
+        .ok_or_else(|| CliError::Usage(format!("Неизвестный формат хранения: {:?}", kind)))?;
+    if kind == data_handler.storage_kind() {
+        println!("Данные уже хранятся в формате {}.", kind.extension());
+        return Ok(());
+    }
+    let settings = load_settings().map_err(CliError::Settings)?;
+    let old_paths = DataFile::ALL
+        .into_iter()
+        .map(|file| data_handler.path(file))
+        .collect::<Vec<_>>();
+    data_handler.convert(kind)?;
+    make_mut(&settings).storage = kind;
+    make_ref(&settings).save()?;
+    println!("Данные переведены в формат {}.", kind.extension());
+    for path in old_paths.iter().filter(|path| path.exists()) {
+        println!("Прежний файл оставлен: {:?}", path);
+    }
+    Ok(())
+}
+
+fn persona_list(data_handler: &mut DataHandler) {
+    for persona in data_handler.get_people().all_representations() {
+        let persona = make_ref(&persona);
+        println!("{}\t{}", persona.identity(), persona.get_email());
+    }
+}
+
+fn persona_import(data_handler: &mut DataHandler, path: &str) -> Result<(), CliError> {
+    let import = import_persona_file(path)?;
+    let count = import.len();
+    for persona in import {
+        data_handler.get_people_mut().insert_or_update(persona);
+    }
+    println!(
+        "Импортировано {count} персон. Теперь у нас {} персон.",
+        data_handler.get_people().size()
+    );
+    Ok(())
+}
+
+fn persona_export(data_handler: &mut DataHandler) -> String {
+    let people = data_handler
+        .get_people()
+        .all_representations()
+        .collect::<Vec<_>>();
+    let people = people.iter().map(make_ref).collect::<Vec<_>>();
+    export_persona(people.iter().map(|persona| &**persona))
+}
+
+/// Nested tags are indented; the count includes members of nested tags.
+fn tag_list(data_handler: &mut DataHandler) {
+    let tree = data_handler.get_tags().tree();
+    let mut membership = data_handler.membership();
+    for (depth, tag) in tree {
+        let label = make_ref(&tag).label();
+        let members = membership.members(&label).len();
+        println!("{}{}\t{}", "  ".repeat(depth), label, members);
+    }
+}
+
+fn tag_members(data_handler: &mut DataHandler, label: &str) -> Result<(), CliError> {
+    for email in tag_emails(data_handler, label)? {
+        println!("{}", email);
+    }
+    Ok(())
+}
+
+fn letter_list(data_handler: &mut DataHandler, options: &[&str]) -> Result<(), CliError> {
+    let mut filter = LetterFilter::default();
+    for (option, value) in parse_options(options)? {
+        let unknown = || CliError::Usage(format!("Непонятное значение {} {}", option, value));
+        match option {
+            "--state" => filter.state = Some(LetterState::from_name(value).ok_or_else(unknown)?),
+            "--since" => filter.since = Some(parse_date(value).ok_or_else(unknown)?),
+            "--until" => filter.until = Some(parse_date(value).ok_or_else(unknown)?),
+            _ => return Err(CliError::Usage(format!("Неизвестный параметр {}", option))),
+        }
+    }
+    for letter in data_handler.get_letters().all_representations() {
+        let letter = make_ref(&letter);
+        if !filter.matches(&letter) {
+            continue;
+        }
+        println!(
+            "{}\t{}\t{}\t{}",
+            letter.identity(),
+            letter.state().name(),
+            letter.get_topic(),
+            letter.attachment_info()
+        );
+    }
+    Ok(())
+}
+
+fn letter_new(
+    data_handler: &mut DataHandler,
+    text_path: &str,
+    options: &[&str],
+) -> Result<(), CliError> {
+    let letter = new_letter();
+    make_mut(&letter).set_text(fs::read_to_string(text_path)?);
+    for (option, value) in parse_options(options)? {
+        match option {
+            "--topic" => make_mut(&letter).set_topic(value),
+            "--attach" => make_mut(&letter).add_attachment_from_path(value)?,
+            _ => return Err(CliError::Usage(format!("Неизвестный параметр {}", option))),
+        }
+    }
+    println!("{}", make_ref(&letter).identity());
+    data_handler.get_letters_mut().insert_or_update(letter);
+    Ok(())
+}
+
+fn send(data_handler: &mut DataHandler, letter_id: &str, options: &[&str]) -> Result<(), CliError> {
+    let letter = find_letter(data_handler, letter_id)?;
+    let settings = load_settings().map_err(CliError::Settings)?;
+    let mut profile = make_ref(&settings)
+        .effective()
+        .get_default_profile()
+        .map(|profile| profile.name.to_owned())
+        .unwrap_or_default();
+    let mut recipients = Recipients::default();
+    for (option, value) in parse_options(options)? {
+        match option {
+            "--tag" => recipients.to.extend(tag_emails(data_handler, value)?),
+            "--to" => recipients.to.push(value.to_owned()),
+            "--cc" => recipients.cc.push(value.to_owned()),
+            "--bcc" => recipients.bcc.push(value.to_owned()),
+            "--profile" => profile = value.to_owned(),
+            _ => return Err(CliError::Usage(format!("Неизвестный параметр {}", option))),
+        }
+    }
+    match env::var(PASSPHRASE_VAR) {
+        Ok(passphrase) => {
+            let secrets =
+                SecretStore::unlock(secrets_path(), &passphrase).map_err(CliError::Secrets)?;
+            make_mut(&settings).fill_passwords(&secrets);
+        }
+        Err(_) if needs_password(&make_ref(&settings), &profile) => {
+            return Err(CliError::NoPassphrase(profile));
+        }
+        Err(_) => {}
+    }
+    let addresses = recipients.addresses();
+    let mailing = mailer::prepare_mailing(
+        &make_ref(&settings),
+        &profile,
+        &make_ref(&letter),
+        recipients,
+    );
+    let mailing = match mailing {
+        Ok(mailing) => mailing,
+        Err(e) => {
+            make_mut(&letter).mark_failed();
+            data_handler.finalize()?;
+            return Err(CliError::Mail(e));
+        }
+    };
+    let progress = run_mailing(&letter, mailing, &addresses, Arc::new(SmtpOutbox));
+    println!("{}\n{}", progress.describe(), progress.summary());
+    if progress.failed() > 0 {
+        data_handler.finalize()?;
+        return Err(CliError::Incomplete(progress.failed()));
+    }
+    Ok(())
+}
+
+/// Keeps an earlier report: the new one gets a numbered name next to it.
+fn report_write(data_handler: &mut DataHandler, path: &str) -> Result<(), CliError> {
+    let table = data_handler.contact_report().to_csv();
+    let path = backup::write_new(Path::new(path), &table)?;
+    println!("Отчёт записан в {:?}.", path);
+    Ok(())
+}
+
+/// Sends the mailing to the end. As in the interface, the letter is queued
+/// first, which takes it off the schedule.
+fn run_mailing(
+    letter: &Letter,
+    mailing: Mailing,
+    addresses: &[String],
+    outbox: Arc<dyn Outbox>,
+) -> SendProgress {
+    make_mut(letter).mark_queued();
+    make_mut(letter).add_recipients(addresses);
+    let topic = make_ref(letter).get_topic().to_owned();
+    let mut task = SendTask::spawn(outbox, mailing, topic);
+    task.wait();
+    let progress = task.progress().clone();
+    progress.settle(&mut make_mut(letter));
+    progress
+}
+
+/// Whether the profile logs in to its relay without a password at hand,
+/// neither filled in nor given by the environment.
+fn needs_password(settings: &SettingsRepr, profile: &str) -> bool {
+    settings
+        .effective()
+        .get_profile(profile)
+        .is_some_and(|profile| !profile.smtp_user.is_empty() && profile.smtp_password.is_empty())
+}
+
+fn find_letter(data_handler: &mut DataHandler, letter_id: &str) -> Result<Letter, CliError> {
+    data_handler
+        .get_letters()
+        .get(letter_id)
+        .ok_or_else(|| CliError::NotFound(format!("письмо {}", letter_id)))
+}
+
+fn tag_emails(data_handler: &mut DataHandler, label: &str) -> Result<Vec<String>, CliError> {
+    if data_handler.get_tags().get(label).is_none() {
+        return Err(CliError::NotFound(format!("метка {}", label)));
+    }
+    let members = data_handler.membership().members(label);
+    let people = data_handler.get_people();
+    let emails = members
+        .iter()
+        .filter_map(|id| people.get(id))
+        .map(|persona| make_ref(&persona).get_email().to_owned())
+        .collect();
+    Ok(emails)
+}
+
+/// Splits `--option value` pairs.
+fn parse_options<'a>(options: &[&'a str]) -> Result<Vec<(&'a str, &'a str)>, CliError> {
+    options
+        .chunks(2)
+        .map(|pair| match pair {
+            [option, value] if option.starts_with("--") => Ok((*option, *value)),
+            _ => Err(CliError::Usage(format!(
+                "Ожидался параметр со значением: {:?}",
+                pair
+            ))),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        controller::settings::SenderProfile,
+        data_handler::{letter::Schedule, lock::DataLock},
+        test_utils::TempDir,
+    };
+
+    fn execute_in(dir: &TempDir, args: &[&str]) -> Result<(), CliError> {
+        execute(&mut DataHandler::with_dir(dir), args)
+    }
+
+    #[test]
+    fn test_parse_options() {
+        assert_eq!(
+            parse_options(&["--to", "a@x.ru", "--tag", "Совет"]).unwrap(),
+            vec![("--to", "a@x.ru"), ("--tag", "Совет")]
+        );
+        assert!(parse_options(&[]).unwrap().is_empty());
+        assert!(matches!(parse_options(&["--to"]), Err(CliError::Usage(_))));
+        assert!(matches!(
+            parse_options(&["a@x.ru", "--to"]),
+            Err(CliError::Usage(_))
+        ));
+    }
+
+    #[test]
+    fn test_only_changes_are_saved() {
+        let dir = TempDir::new("cli");
+        let tags = dir.join(DataFile::Tags.file_name());
+        let legacy = include_str!("data_handler/fixtures/tag.v0.json");
+        fs::write(&tags, legacy).unwrap();
+        for args in [["tag", "list"], ["letter", "list"], ["persona", "list"]] {
+            execute_in(&dir, &args).unwrap();
+        }
+        assert_eq!(fs::read_to_string(&tags).unwrap(), legacy);
+
+        let text = dir.join("letter.txt");
+        fs::write(&text, "Текст письма").unwrap();
+        let text = text.to_str().unwrap();
+        execute_in(&dir, &["letter", "new", text, "--topic", "Совет"]).unwrap();
+        let mut data_handler = DataHandler::with_dir(&dir);
+        let letters = data_handler.get_letters().all_representations();
+        let topics = letters
+            .map(|letter| make_ref(&letter).get_topic().to_owned())
+            .collect::<Vec<_>>();
+        assert_eq!(topics, vec!["Совет"]);
+        assert_ne!(fs::read_to_string(&tags).unwrap(), legacy);
+    }
+
+    #[test]
+    fn test_changes_need_the_lock() {
+        let dir = TempDir::new("cli");
+        let _lock = DataLock::acquire(&dir).unwrap();
+        for args in [&["send", "1"][..], &["letter", "new", "letter.txt"]] {
+            assert!(matches!(execute_in(&dir, args), Err(CliError::Locked(_))));
+        }
+        execute_in(&dir, &["letter", "list"]).unwrap();
+    }
+
+    struct NullOutbox;
+
+    impl Outbox for NullOutbox {
+        fn send(&self, _: &SenderProfile, _: &lettre::Message) -> Result<(), MailError> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_sending_takes_letter_off_schedule() {
+        let settings = SettingsRepr::default();
+        let profile = settings.get_default_profile().unwrap().name.clone();
+        let letter = new_letter();
+        let recipients = Recipients {
+            to: vec!["ivanov@mipt.ru".into()],
+            ..Default::default()
+        };
+        make_mut(&letter).set_schedule(Some(Schedule {
+            at: chrono::Local::now(),
+            profile: profile.clone(),
+            to: recipients.to.clone(),
+            cc: vec![],
+            bcc: vec![],
+        }));
+        let addresses = recipients.addresses();
+        let mailing =
+            mailer::prepare_mailing(&settings, &profile, &make_ref(&letter), recipients).unwrap();
+        let progress = run_mailing(&letter, mailing, &addresses, Arc::new(NullOutbox));
+        assert_eq!(progress.failed(), 0);
+        let letter = make_ref(&letter);
+        assert_eq!(letter.state(), LetterState::Sent);
+        assert!(letter.schedule().is_none());
+        assert!(!letter.is_due(&chrono::Local::now()));
+    }
+
+    #[test]
+    fn test_needs_password() {
+        let mut settings = SettingsRepr::default();
+        let mut profile = settings.get_default_profile().unwrap().clone();
+        let name = profile.name.clone();
+        profile.smtp_user = "ivanov".into();
+        settings.update_profile(&name, profile.clone());
+        assert!(needs_password(&settings, &name));
+        profile.smtp_password = "qwerty".into();
+        settings.update_profile(&name, profile);
+        assert!(!needs_password(&settings, &name));
+        assert!(!needs_password(&settings, "Удалённый"));
+    }
+
+    #[test]
+    fn test_report_keeps_earlier_file() {
+        let dir = TempDir::new("cli");
+        let report = dir.join("report.csv");
+        fs::write(&report, "старый отчёт").unwrap();
+        execute_in(&dir, &["report", report.to_str().unwrap()]).unwrap();
+        assert_eq!(fs::read_to_string(&report).unwrap(), "старый отчёт");
+        assert!(dir.join("report-2.csv").exists());
+    }
+
+    #[test]
+    fn test_unknown_command() {
+        let dir = TempDir::new("cli");
+        assert!(matches!(
+            execute_in(&dir, &["letter", "burn"]),
+            Err(CliError::Usage(_))
+        ));
+        assert!(matches!(
+            execute_in(&dir, &["letter", "new"]),
+            Err(CliError::Usage(_))
+        ));
+    }
+}
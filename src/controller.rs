@@ -1,52 +1,136 @@
 pub use signals::ControllerSignal;
-use std::sync::mpsc;
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    path::Path,
+    rc::Rc,
+    sync::{mpsc, Arc},
+};
 
 use crate::{
     data_handler::{
-        handler::DataHandler,
-        letter::{new_letter, Letter},
-        make_ref,
-        persona::Persona,
-        tag::{new_tag, Tag},
+        backup,
+        handler::{DataFile, DataHandler, LoadError, Stored},
+        letter::{new_letter, DateTime, Letter, LetterFilter, LetterRepr, LetterState, Schedule},
+        make_mut, make_ref,
+        persona::{export_persona, Persona, PersonaRepr},
+        search::SearchIndex,
+        tag::{new_tag, Tag, TagRepr},
+        template::{new_template, Template, TemplateRepr},
         Identity, Represent,
     },
     ui::Ui,
 };
 
-use self::settings::{load_settings, Settings};
+use self::{
+    mailer::{Outbox, Recipients, SmtpOutbox},
+    presenter::Presenter,
+    secrets::{secrets_path, SecretStore},
+    settings::{load_settings, scrub_legacy_passwords, settings_path, Settings, SettingsRepr},
+    worker::{SendProgress, SendTask},
+};
 
-pub struct Controller {
-    ui: Ui,
+pub struct Controller<P: Presenter = Ui> {
+    ui: P,
+    outbox: Arc<dyn Outbox>,
     settings: Settings,
+    settings_loaded: bool,
+    secrets: SecretStore,
     data_handler: DataHandler,
+    send_task: Option<SendTask>,
+    /// The letter of the running mailing.
+    sending: Option<Letter>,
+    /// Personas chosen in the persona list for a letter not sent yet.
+    addressees: HashMap<Identity, Vec<Identity>>,
+    /// Overdue letters not sent until the user says so.
+    held: Vec<Identity>,
     rx: mpsc::Receiver<ControllerSignal>,
     tx: mpsc::Sender<ControllerSignal>,
     stop: bool,
+    /// Failures of the last save on quitting, told once the interface is gone.
+    exit_errors: Vec<String>,
 }
 
 impl Controller {
     pub fn new() -> Self {
         let (tx, rx) = mpsc::channel();
         let ui = Ui::new(&tx);
-        let settings = load_settings();
+        let (settings, settings_error) = match load_settings() {
+            Ok(settings) => (settings, None),
+            Err(e) => (Rc::new(RefCell::new(SettingsRepr::default())), Some(e)),
+        };
+        let mut data_handler = DataHandler::new();
+        data_handler.set_storage(make_ref(&settings).storage);
+        let lock_error = data_handler.lock().err();
+        let mut controller =
+            Controller::with_parts(ui, Arc::new(SmtpOutbox), settings, data_handler, (tx, rx));
+        if let Some(e) = settings_error {
+            controller.settings_loaded = false;
+            controller.log(format!(
+                "Настройки из {:?} не прочитаны: {}\nИзменения настроек не будут сохранены.",
+                settings_path(),
+                e
+            ));
+        }
+        controller
+            .ui
+            .unlock_secrets_dialog(!SecretStore::exists(secrets_path()));
+        controller.check_data();
+        if let Some(e) = lock_error {
+            controller.ui.read_only_dialog(&format!(
+                "Не удалось открыть данные для записи: {}.\nМожно работать только для чтения: изменения не будут сохранены.",
+                e
+            ));
+        }
+        controller
+    }
+}
+
+impl<P: Presenter> Controller<P> {
+    /// Assembles a controller around any presenter; `channel` must be the one
+    /// the presenter was given to report user actions.
+    pub fn with_parts(
+        ui: P,
+        outbox: Arc<dyn Outbox>,
+        settings: Settings,
+        data_handler: DataHandler,
+        channel: (
+            mpsc::Sender<ControllerSignal>,
+            mpsc::Receiver<ControllerSignal>,
+        ),
+    ) -> Self {
+        let (tx, rx) = channel;
         Self {
             ui,
+            outbox,
             settings,
-            data_handler: DataHandler::new(),
+            settings_loaded: true,
+            secrets: SecretStore::session(),
+            data_handler,
+            send_task: None,
+            sending: None,
+            addressees: HashMap::new(),
+            held: vec![],
             rx,
             tx,
             stop: false,
+            exit_errors: vec![],
         }
     }
 
-    pub fn run(&mut self) {
+    /// Runs until Quit and returns what could not be saved on quitting.
+    pub fn run(&mut self) -> Vec<String> {
         loop {
             self.process_signals();
+            self.poll_sending();
+            self.send_due();
+            self.flush_drafts();
             self.ui.step_next();
             if self.stop {
                 break;
             }
         }
+        std::mem::take(&mut self.exit_errors)
     }
 
     fn get_people(&mut self) -> Vec<Persona> {
@@ -57,51 +141,281 @@ impl Controller {
     }
 }
 
-impl Controller {
+impl Default for Controller {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<P: Presenter> Controller<P> {
     fn process_signals(&mut self) {
+        while let Ok(signal) = self.rx.try_recv() {
+            self.handle_signal(signal);
+            if self.stop {
+                break;
+            }
+        }
+    }
+
+    fn handle_signal(&mut self, signal: ControllerSignal) {
         use ControllerSignal::*;
-        if let Ok(signal) = self.rx.try_recv() {
-            #[allow(unreachable_patterns)]
-            match signal {
-                Noop => {}
-                Log(info) => self.log(info),
-                OpenSettings => self.open_settings(),
-                SaveSettings => self.save_settings(),
-                NewTag => self.new_tag(),
-                EditTag(tag) => self.edit_tag(tag),
-                SelectTag => self.select_tag(),
-                CompleteEditTag { key, tag } => self.complete_edit_tag(key, tag),
-                RemoveTagAlert(tag) => self.remove_tag_alert(tag),
-                NewLetter => self.new_letter(),
-                EditLetter(letter) => self.edit_letter(letter),
-                CompleteEditLetter { key, letter } => self.complete_edit_letter(key, letter),
-                OpenLetterToSend(letter) => self.open_letter_to_send(letter),
-                SendEmail { letter, to } => self.send_email(letter, to),
-                ImportPersona(p) => self.import_persona(p),
-                SelectPersona => self.select_persona(),
-                EditPersona(p) => self.edit_persona(p),
-                CompleteEditPersona { key, persona } => self.complete_edit_persona(key, persona),
-                RemovePersonaAlert(p) => self.remove_persona_alert(p),
-                RemovePersona(p) => self.remove_persona(p),
-                Quit => {
-                    self.finalize();
-                    self.stop = true;
-                }
-                any => eprintln!("Unexpected controller signal: {:?}", any),
+        #[allow(unreachable_patterns)]
+        match signal {
+            Noop => {}
+            Log(info) => self.log(info),
+            OpenSettings => self.open_settings(),
+            SaveSettings => {
+                self.save_settings();
+            }
+            UnlockSecrets(passphrase) => self.unlock_secrets(passphrase),
+            RetryLoad(file) => self.retry_load(file),
+            LoadBackup { file, path } => self.load_backup(file, path),
+            StartEmpty(file) => self.start_empty(file),
+            OpenBackups => self.open_backups(),
+            NewTag => self.new_tag(),
+            EditTag(tag) => self.edit_tag(tag),
+            SelectTag => self.select_tag(),
+            CompleteEditTag { key, tag } => self.complete_edit_tag(key, tag),
+            RemoveTagAlert(tag) => self.remove_tag_alert(tag),
+            RemoveTag(tag) => self.remove::<TagRepr>(make_ref(&tag).identity()),
+            NewLetter => self.new_letter(),
+            EditLetter(letter) => self.edit_letter(letter),
+            CompleteEditLetter { key, letter } => self.complete_edit_letter(key, letter),
+            SaveDraft(draft) => self.data_handler.get_drafts_mut().update(draft),
+            DiscardDraft(key) => self.data_handler.get_drafts_mut().remove(key),
+            RecoverDrafts => self.recover_drafts(),
+            DiscardDrafts => self.data_handler.get_drafts_mut().clear(),
+            CancelEditLetter(key) => self.cancel_edit_letter(key),
+            RemoveLetter(letter) => self.remove_letter(letter),
+            SelectLetter(filter) => self.select_letter(filter),
+            SearchLetters => self.search_letters(),
+            OpenLetterToSend(letter) => self.open_letter_to_send(letter),
+            SelectTemplate => self.select_template(),
+            NewLetterFromTemplate(template) => self.new_letter_from_template(template),
+            SaveAsTemplate(letter) => self.save_as_template(letter),
+            EditTemplate(template) => self.edit_template(template),
+            CompleteEditTemplate { key, template } => self.complete_edit_template(key, template),
+            RemoveTemplate(template) => self.remove::<TemplateRepr>(make_ref(&template).identity()),
+            SendEmail {
+                letter,
+                recipients,
+                profile,
+            } => {
+                self.send_email(letter, recipients, profile);
             }
+            CancelSending => self.cancel_sending(),
+            ScheduleEmail {
+                letter,
+                recipients,
+                profile,
+                at,
+            } => self.schedule_email(letter, recipients, profile, at),
+            OpenSchedule => self.open_schedule(),
+            Reschedule { letter, at } => self.reschedule(letter, Some(at)),
+            Unschedule(letter) => self.reschedule(letter, None),
+            SendOverdue => self.held.clear(),
+            CancelOverdue => self.cancel_overdue(),
+            ImportPersona(p) => self.import_persona(p),
+            SelectPersona => self.select_persona(),
+            EditPersona(p) => self.edit_persona(p),
+            CompleteEditPersona { key, persona, tags } => {
+                self.complete_edit_persona(key, persona, tags)
+            }
+            RemovePersonaAlert(p) => self.remove_persona_alert(p),
+            RemovePersona(p) => self.remove_persona(p),
+            AddToTag { label, people } => self.change_tag_members(label, people, true),
+            RemoveFromTag { label, people } => self.change_tag_members(label, people, false),
+            ExportPersona(p) => self.export_persona(p),
+            WriteLetterTo(people) => self.write_letter_to(people),
+            OpenReport => {
+                let report = self.data_handler.contact_report();
+                self.ui.report_form(report);
+            }
+            ExportReport => self.export_report(),
+            OpenTrash => self.open_trash(),
+            RestoreFromTrash { file, index } => self.restore_from_trash(file, index),
+            PurgeFromTrash { file, index } => self.purge_from_trash(file, index),
+            EmptyTrash => self.empty_trash(),
+            Undo => self.undo(),
+            Quit => {
+                self.finish_sending();
+                self.finalize();
+                self.stop = true;
+            }
+            any => eprintln!("Unexpected controller signal: {:?}", any),
         }
     }
 
     fn log(&mut self, info: impl AsRef<str>) {
-        self.ui.present_info(info);
+        self.ui.present_info(info.as_ref());
     }
 
     fn open_settings(&mut self) {
         self.ui.settings_form(self.settings.clone()); //clone smart pointer to settings
     }
 
-    fn save_settings(&self) {
-        make_ref(&self.settings).save();
+    /// Whether both the passwords and the settings were written.
+    fn save_settings(&mut self) -> bool {
+        if !self.settings_loaded {
+            self.log("Настройки не сохранены: файл настроек не был прочитан.");
+            return false;
+        }
+        let settings = make_ref(&self.settings);
+        settings.store_passwords(&mut self.secrets);
+        let mut saved = true;
+        if let Err(e) = self.secrets.save() {
+            saved = false;
+            self.tx
+                .send(ControllerSignal::Log(format!(
+                    "Не удалось сохранить пароли: {}",
+                    e
+                )))
+                .unwrap();
+        }
+        if let Err(e) = settings.save() {
+            saved = false;
+            self.tx
+                .send(ControllerSignal::Log(format!(
+                    "Не удалось сохранить настройки: {}",
+                    e
+                )))
+                .unwrap();
+        }
+        saved
+    }
+
+    fn unlock_secrets(&mut self, passphrase: Option<String>) {
+        self.secrets = match passphrase {
+            Some(passphrase) => match SecretStore::unlock(secrets_path(), &passphrase) {
+                Ok(secrets) => secrets,
+                Err(e) => {
+                    self.log(format!("Хранилище паролей не открыто: {}", e));
+                    self.ui
+                        .unlock_secrets_dialog(!SecretStore::exists(secrets_path()));
+                    return;
+                }
+            },
+            None => SecretStore::session(),
+        };
+        make_mut(&self.settings).fill_passwords(&self.secrets);
+        if self.secrets.is_persistent()
+            && make_ref(&self.settings).has_plaintext_passwords()
+            && self.save_settings()
+        {
+            make_mut(&self.settings).forget_plaintext_passwords();
+            match scrub_legacy_passwords() {
+                Ok(()) => self.log(
+                    "SMTP-пароли перенесены в зашифрованное хранилище и удалены из .env и profiles.json.",
+                ),
+                Err(e) => self.log(format!(
+                    "ВНИМАНИЕ: SMTP-пароли перенесены в зашифрованное хранилище, но остались открытым текстом в .env или profiles.json: {}.\nУдалите их оттуда вручную.",
+                    e
+                )),
+            }
+        }
+    }
+
+    /// Loads all data files and asks the user about those that failed. The
+    /// overdue mailings are left to the instance that can write the data.
+    fn check_data(&mut self) {
+        self.data_handler.load_all();
+        for e in self.data_handler.take_load_errors() {
+            self.load_error_dialog(e);
+        }
+        let drafts = self.data_handler.get_drafts_mut();
+        if !drafts.is_empty() {
+            let drafts = drafts.drafts().cloned().collect();
+            self.ui.recover_drafts_dialog(drafts);
+        }
+        if self.data_handler.is_read_only() {
+            return;
+        }
+        self.fail_interrupted();
+        let overdue = self.scheduled(|letter| letter.is_due(&chrono::Local::now()));
+        if !overdue.is_empty() {
+            self.held = overdue
+                .iter()
+                .map(|letter| make_ref(letter).identity())
+                .collect();
+            self.ui.overdue_dialog(overdue);
+        }
+    }
+
+    /// A letter still "sending" on start was cut off by a crash; it failed.
+    fn fail_interrupted(&mut self) {
+        let interrupted = self
+            .data_handler
+            .get_letters()
+            .all_representations()
+            .filter(|letter| make_ref(letter).state() == LetterState::Queued)
+            .collect::<Vec<_>>();
+        if interrupted.is_empty() {
+            return;
+        }
+        let mut topics = vec![];
+        for letter in interrupted {
+            make_mut(&letter).mark_failed();
+            topics.push(format!("{:?}", make_ref(&letter).get_topic()));
+        }
+        self.autosave();
+        self.log(format!(
+            "Рассылка прервалась при прошлом запуске и не завершена: {}.",
+            topics.join(", ")
+        ));
+    }
+
+    /// Offers our own version after a conflict, the latest backup otherwise.
+    fn load_error_dialog(&mut self, e: LoadError) {
+        let backup = if e.conflict {
+            e.preserved.clone()
+        } else {
+            self.data_handler
+                .backups()
+                .unwrap_or_default()
+                .into_iter()
+                .find(|(file, _)| *file == e.file)
+                .map(|(_, backup)| backup)
+        };
+        self.ui.load_error_dialog(e.file, &e.to_string(), backup);
+    }
+
+    fn open_backups(&mut self) {
+        match self.data_handler.backups() {
+            Ok(backups) if backups.is_empty() => self.log("Резервных копий пока нет."),
+            Ok(backups) => self.ui.restore_backup_form(backups),
+            Err(e) => self.log(format!("Не удалось найти резервные копии: {}", e)),
+        }
+    }
+
+    fn retry_load(&mut self, file: DataFile) {
+        match self.data_handler.retry_load(file) {
+            Ok(()) => self.log(format!("Данные ({}) загружены.", file)),
+            Err(e) => self.load_error_dialog(e),
+        }
+    }
+
+    fn load_backup(&mut self, file: DataFile, path: String) {
+        match self.data_handler.load_backup(file, &path) {
+            Ok(()) => {
+                self.autosave();
+                self.log(format!(
+                    "Данные ({}) загружены из {:?} и сохранены в {}.",
+                    file,
+                    path,
+                    file.file_name()
+                ))
+            }
+            Err(e) => self.load_error_dialog(e),
+        }
+    }
+
+    fn start_empty(&mut self, file: DataFile) {
+        self.data_handler.start_empty(file);
+        self.log(format!(
+            "Данные ({}) начаты с чистого листа.\nФайл {} не будет перезаписан, изменения в нём не сохранятся.",
+            file,
+            file.file_name()
+        ));
     }
 
     fn new_tag(&mut self) {
@@ -116,17 +430,30 @@ impl Controller {
             .idendities()
             .cloned()
             .collect::<Vec<_>>();
+        let tags = self.data_handler.get_tags().all_representations().collect();
         self.ui
-            .tag_form(make_ref(&tag).identity(), tag.clone(), &people);
+            .tag_form(make_ref(&tag).identity(), tag.clone(), &people, tags);
     }
 
     fn select_tag(&mut self) {
-        self.ui
-            .select_tag_form(self.data_handler.get_tags().all_representations().collect());
+        let tree = self.data_handler.get_tags().tree();
+        let mut membership = self.data_handler.membership();
+        let tree = tree
+            .into_iter()
+            .map(|(depth, tag)| {
+                let members = membership.members(&make_ref(&tag).identity()).len();
+                (depth, tag, members)
+            })
+            .collect();
+        self.ui.select_tag_form(tree);
     }
 
     fn complete_edit_tag(&mut self, key: Identity, tag: Tag) {
-        self.data_handler.get_tags_mut().update_identity(key, tag);
+        let label = make_ref(&tag).identity();
+        let tags = self.data_handler.get_tags_mut();
+        tags.rename_references(&key, &label);
+        tags.update_identity(key, tag);
+        self.autosave();
     }
 
     fn remove_tag_alert(&mut self, tag: Tag) {
@@ -143,28 +470,340 @@ impl Controller {
         self.ui.letter_form(key, letter);
     }
 
-    fn complete_edit_letter(&mut self, key: Identity, _letter: Letter) {
+    fn complete_edit_letter(&mut self, key: Identity, letter: Letter) {
+        self.data_handler
+            .get_letters_mut()
+            .update_identity(key, letter);
+        self.autosave();
+    }
+
+    /// A letter never saved is gone, and so are the addressees kept for it.
+    fn cancel_edit_letter(&mut self, key: Identity) {
+        self.data_handler.get_drafts_mut().remove(&key);
+        if self.data_handler.get_letters().get(&key).is_none() {
+            self.addressees.remove(&key);
+        }
+    }
+
+    fn remove_letter(&mut self, letter: Letter) {
+        let identity = make_ref(&letter).identity();
+        self.addressees.remove(&identity);
+        self.remove::<LetterRepr>(identity);
+    }
+
+    fn select_letter(&mut self, filter: LetterFilter) {
+        let mut letters = self
+            .data_handler
+            .get_letters()
+            .all_representations()
+            .filter(|letter| filter.matches(&make_ref(letter)))
+            .collect::<Vec<_>>();
+        letters.reverse();
+        self.ui.select_letter_form(letters, filter);
+    }
+
+    fn search_letters(&mut self) {
+        let names = self
+            .get_people()
+            .iter()
+            .map(|persona| {
+                let persona = make_ref(persona);
+                (persona.get_email().to_owned(), persona.identity())
+            })
+            .collect();
+        let letters = self
+            .data_handler
+            .get_letters()
+            .all_representations()
+            .collect();
+        self.ui.search_form(SearchIndex::build(letters, &names));
+    }
+
+    fn select_template(&mut self) {
+        let templates = self
+            .data_handler
+            .get_templates()
+            .all_representations()
+            .collect::<Vec<_>>();
+        if templates.is_empty() {
+            self.log("Шаблонов пока нет: сохраните письмо как шаблон кнопкой \"Template\".");
+        } else {
+            self.ui.select_template_form(templates);
+        }
+    }
+
+    /// The letter starts addressed to the members of the template's tag.
+    fn new_letter_from_template(&mut self, template: Template) {
+        let template = make_ref(&template);
+        let letter = Rc::new(RefCell::new(template.to_letter()));
+        if let Some(label) = template.tag() {
+            let members = self.data_handler.membership().members(label);
+            self.addressees
+                .insert(make_ref(&letter).identity(), members);
+        }
+        self.tx.send(ControllerSignal::EditLetter(letter)).unwrap();
+    }
+
+    /// The template is named after the letter topic, numbered if a template
+    /// of that name exists.
+    fn save_as_template(&mut self, letter: Letter) {
+        let mut template = TemplateRepr::from_letter(&make_ref(&letter));
+        let templates = self.data_handler.get_templates();
+        let base = template.identity();
+        let mut name = base.clone();
+        for n in 2.. {
+            if templates.get(&name).is_none() {
+                break;
+            }
+            name = format!("{} ({})", base, n);
+        }
+        template.set_name(name);
+        let template = new_template(template);
         self.tx
-            .send(ControllerSignal::Log(format!("Complete window:\n{}", key)))
-            .unwrap()
+            .send(ControllerSignal::EditTemplate(template))
+            .unwrap();
+    }
+
+    fn edit_template(&mut self, template: Template) {
+        let key = make_ref(&template).identity();
+        let tags = self.data_handler.get_tags().idendities().cloned().collect();
+        self.ui.template_form(key, template, tags);
+    }
+
+    /// A template may not take the name of another one: the form comes back
+    /// to choose a different name, and the stored template keeps its own.
+    fn complete_edit_template(&mut self, key: Identity, template: Template) {
+        let name = make_ref(&template).identity();
+        if name != key && self.data_handler.get_templates().get(&name).is_some() {
+            let edited = new_template(make_ref(&template).clone());
+            make_mut(&template).set_name(&key);
+            let tags = self.data_handler.get_tags().idendities().cloned().collect();
+            self.ui.template_form(key, edited, tags);
+            self.log(format!("Шаблон {:?} уже есть, выберите другое имя.", name));
+            return;
+        }
+        self.data_handler
+            .get_templates_mut()
+            .update_identity(key, template);
+        self.autosave();
+    }
+
+    /// Reopens the journaled drafts over copies of their letters.
+    fn recover_drafts(&mut self) {
+        let drafts = self
+            .data_handler
+            .get_drafts_mut()
+            .drafts()
+            .cloned()
+            .collect::<Vec<_>>();
+        for draft in drafts {
+            let mut letter = self
+                .data_handler
+                .get_letters()
+                .get(&draft.key)
+                .map(|letter| make_ref(&letter).clone())
+                .unwrap_or_default();
+            letter.set_topic(draft.topic);
+            letter.set_text(draft.text);
+            self.ui
+                .letter_form(draft.key, Rc::new(RefCell::new(letter)));
+        }
     }
 
     fn open_letter_to_send(&mut self, letter: Letter) {
         let people = self.get_people();
-        self.ui.send_letter_form(letter, people);
+        let tags = self.data_handler.get_tags().all_representations().collect();
+        let settings = make_ref(&self.settings);
+        let profiles = settings.profile_names();
+        let default_profile = settings
+            .effective()
+            .get_default_profile()
+            .map(|profile| profile.name.to_owned())
+            .unwrap_or_default();
+        drop(settings);
+        let to = self
+            .addressees
+            .remove(&make_ref(&letter).identity())
+            .unwrap_or_default();
+        self.ui
+            .send_letter_form(letter, people, tags, profiles, default_profile, to);
     }
 
-    fn send_email(&mut self, letter: Letter, to: Vec<String>) {
-        let letter = make_ref(&letter);
-        self.tx
-            .send(ControllerSignal::Log(format!(
-                "To: {:?}\n{:?}\n{:?}\n{:?}",
-                to,
-                letter.get_topic(),
-                letter.get_text(),
-                letter.attachment_info()
-            )))
-            .unwrap();
+    /// Returns whether the mailing has started.
+    fn send_email(&mut self, letter: Letter, recipients: Recipients, profile: String) -> bool {
+        if self.send_task.is_some() {
+            self.log("Дождитесь окончания текущей рассылки.");
+            return false;
+        }
+        let addresses = recipients.addresses();
+        let result = mailer::prepare_mailing(
+            &make_ref(&self.settings),
+            &profile,
+            &make_ref(&letter),
+            recipients,
+        );
+        match result {
+            Ok(mailing) => {
+                let topic = make_ref(&letter).get_topic().to_owned();
+                let task = SendTask::spawn(self.outbox.clone(), mailing, topic);
+                self.ui.send_progress_dialog(task.progress());
+                self.send_task = Some(task);
+                make_mut(&letter).mark_queued();
+                make_mut(&letter).add_recipients(&addresses);
+                self.sending = Some(letter);
+                self.autosave();
+                true
+            }
+            Err(e) => {
+                make_mut(&letter).mark_failed();
+                self.log(format!("Не удалось отправить письмо: {}", e));
+                self.autosave();
+                false
+            }
+        }
+    }
+
+    /// Passes the worker's reports on to the progress dialog.
+    fn poll_sending(&mut self) {
+        let Some(task) = self.send_task.as_mut() else {
+            return;
+        };
+        if task.poll() {
+            self.ui.send_progress(task.progress());
+        }
+        if task.is_finished() {
+            let progress = task.progress().clone();
+            self.send_task = None;
+            self.settle_letter(&progress);
+            self.log(progress.summary());
+        }
+    }
+
+    /// Gives the letter of the finished mailing its outcome.
+    fn settle_letter(&mut self, progress: &SendProgress) {
+        if let Some(letter) = self.sending.take() {
+            progress.settle(&mut make_mut(&letter));
+            self.autosave();
+        }
+    }
+
+    fn cancel_sending(&mut self) {
+        if let Some(task) = self.send_task.as_ref() {
+            task.cancel();
+        }
+    }
+
+    fn schedule_email(
+        &mut self,
+        letter: Letter,
+        recipients: Recipients,
+        profile: String,
+        at: DateTime,
+    ) {
+        if recipients.is_empty() {
+            self.log("Не выбраны адресаты.");
+            return;
+        }
+        make_mut(&letter).set_schedule(Some(Schedule {
+            at,
+            profile,
+            to: recipients.to,
+            cc: recipients.cc,
+            bcc: recipients.bcc,
+        }));
+        self.data_handler
+            .get_letters_mut()
+            .insert_or_update(letter.clone());
+        self.autosave();
+        self.log(format!(
+            "Письмо {:?} будет отправлено {}.",
+            make_ref(&letter).get_topic(),
+            at.format("%d.%m.%Y %H:%M")
+        ));
+    }
+
+    /// Scheduled letters passing `filter`, earliest first.
+    fn scheduled(&mut self, filter: impl Fn(&LetterRepr) -> bool) -> Vec<Letter> {
+        let mut letters = self
+            .data_handler
+            .get_letters()
+            .all_representations()
+            .filter(|letter| {
+                let letter = make_ref(letter);
+                letter.schedule().is_some() && filter(&letter)
+            })
+            .collect::<Vec<_>>();
+        letters.sort_by_key(|letter| make_ref(letter).schedule().map(|schedule| schedule.at));
+        letters
+    }
+
+    fn open_schedule(&mut self) {
+        let letters = self.scheduled(|_| true);
+        if letters.is_empty() {
+            self.log("Запланированных рассылок нет.");
+        } else {
+            self.ui.schedule_form(letters);
+        }
+    }
+
+    /// Moves the mailing to `at`, or cancels it.
+    fn reschedule(&mut self, letter: Letter, at: Option<DateTime>) {
+        let identity = make_ref(&letter).identity();
+        let schedule = make_ref(&letter).schedule().cloned();
+        let schedule = at.and_then(|at| schedule.map(|schedule| Schedule { at, ..schedule }));
+        make_mut(&letter).set_schedule(schedule);
+        self.held.retain(|held| *held != identity);
+        self.autosave();
+    }
+
+    fn cancel_overdue(&mut self) {
+        for identity in std::mem::take(&mut self.held) {
+            if let Some(letter) = self.data_handler.get_letters().get(&identity) {
+                make_mut(&letter).set_schedule(None);
+            }
+        }
+        self.autosave();
+        self.log("Просроченные рассылки отменены.");
+    }
+
+    /// Starts the earliest mailing that is due unless another one is running
+    /// or another instance holds the data. A mailing that cannot start stays
+    /// in the schedule, held until it is rescheduled.
+    fn send_due(&mut self) {
+        if self.send_task.is_some() || self.data_handler.is_read_only() {
+            return;
+        }
+        let now = chrono::Local::now();
+        let held = std::mem::take(&mut self.held);
+        let due = self
+            .scheduled(|letter| letter.is_due(&now) && !held.contains(&letter.identity()))
+            .into_iter()
+            .next();
+        self.held = held;
+        let Some(letter) = due else {
+            return;
+        };
+        let Some(schedule) = make_ref(&letter).schedule().cloned() else {
+            return;
+        };
+        self.log(format!(
+            "Пришло время рассылки {:?}.",
+            make_ref(&letter).get_topic()
+        ));
+        let identity = make_ref(&letter).identity();
+        if !self.send_email(letter, Recipients::from(&schedule), schedule.profile) {
+            self.held.push(identity);
+            self.log("Рассылка осталась в расписании: исправьте ошибку и перенесите её.");
+        }
+    }
+
+    /// Stops the mailing on Quit without cutting a message in flight.
+    fn finish_sending(&mut self) {
+        if let Some(mut task) = self.send_task.take() {
+            task.cancel();
+            task.wait();
+            self.settle_letter(&task.progress().clone());
+        }
     }
 
     fn import_persona(&mut self, persona: Vec<Persona>) {
@@ -172,6 +811,7 @@ impl Controller {
         for persona in persona {
             self.data_handler.get_people_mut().insert_or_update(persona);
         }
+        self.autosave();
         self.tx
             .send(ControllerSignal::Log(format!(
                 "Импортировано {count} персон. Теперь у нас {} персон.",
@@ -181,39 +821,870 @@ impl Controller {
     }
 
     fn select_persona(&mut self) {
-        self.ui.select_persona_form(
-            self.data_handler
-                .get_people()
-                .all_representations()
-                .collect(),
-        )
+        let people = self.get_people();
+        let tags = self.data_handler.get_tags().all_representations().collect();
+        self.ui.select_persona_form(people, tags)
     }
 
     fn edit_persona(&mut self, persona: Persona) {
         let key = make_ref(&persona).identity();
-        self.ui.edit_persona_form(key, persona);
+        let tags = self.data_handler.get_tags().tree();
+        self.ui.edit_persona_form(key, persona, tags);
     }
 
-    fn complete_edit_persona(&mut self, key: Identity, persona: Persona) {
+    fn complete_edit_persona(&mut self, key: Identity, persona: Persona, tags: Vec<Identity>) {
+        let identity = make_ref(&persona).identity();
+        self.data_handler
+            .get_tags_mut()
+            .set_memberships(&key, &identity, &tags);
         self.data_handler
             .get_people_mut()
             .update_identity(key, persona);
+        self.autosave();
     }
 
-    fn remove_persona_alert(&mut self, persona: Persona) {
+    fn remove_persona_alert(&mut self, persona: Vec<Persona>) {
         self.ui.remove_persona_dialog(persona);
     }
 
-    fn remove_persona(&mut self, persona: Persona) {
+    /// The personas go to the trash together, so one Undo brings them all back.
+    fn remove_persona(&mut self, persona: Vec<Persona>) {
+        let identities = persona.iter().map(|persona| make_ref(persona).identity());
+        if self
+            .data_handler
+            .move_all_to_trash::<PersonaRepr>(identities)
+            > 0
+        {
+            self.autosave();
+        }
+    }
+
+    fn change_tag_members(&mut self, label: Identity, people: Vec<Identity>, add: bool) {
+        let Some(tag) = self.data_handler.get_tags().get(&label) else {
+            return;
+        };
+        let mut ids = make_ref(&tag)
+            .persona_ids()
+            .filter(|id| !people.contains(id))
+            .cloned()
+            .collect::<Vec<_>>();
+        if add {
+            ids.extend(people);
+        }
+        make_mut(&tag).set_persona_ids(ids);
+        self.autosave();
+        if !add && make_ref(&tag).rule().is_some() {
+            self.log(format!(
+                "Метка {:?} задана правилом: подходящие под него персоны остаются в ней.",
+                label
+            ));
+        }
+    }
+
+    /// Writes the personas next to the imported `persona.tsv`, under a new
+    /// name if an earlier export is there.
+    fn export_persona(&mut self, persona: Vec<Persona>) {
+        const EXPORT_FILE: &str = "persona-export.tsv";
+        let people = persona.iter().map(make_ref).collect::<Vec<_>>();
+        let table = export_persona(people.iter().map(|persona| &**persona));
+        drop(people);
+        match backup::write_new(Path::new(EXPORT_FILE), &table) {
+            Ok(path) => self.log(format!("{} персон выгружено в {:?}.", persona.len(), path)),
+            Err(e) => self.log(format!("Не удалось записать {:?}: {}", EXPORT_FILE, e)),
+        }
+    }
+
+    /// Writes the report to the current directory, under a new name if an
+    /// earlier report is there.
+    fn export_report(&mut self) {
+        const REPORT_FILE: &str = "contact-report.csv";
+        let table = self.data_handler.contact_report().to_csv();
+        match backup::write_new(Path::new(REPORT_FILE), &table) {
+            Ok(path) => self.log(format!("Отчёт записан в {:?}.", path)),
+            Err(e) => self.log(format!("Не удалось записать {:?}: {}", REPORT_FILE, e)),
+        }
+    }
+
+    /// The send form of the new letter starts with the personas in "To".
+    fn write_letter_to(&mut self, people: Vec<Identity>) {
+        let letter = new_letter();
+        self.addressees.insert(make_ref(&letter).identity(), people);
+        self.tx.send(ControllerSignal::EditLetter(letter)).unwrap();
+    }
+
+    /// Removal moves the record to the trash, from where Undo brings it back.
+    fn remove<R: Stored>(&mut self, identity: Identity) {
+        if self.data_handler.move_to_trash::<R>(identity) {
+            self.autosave();
+        }
+    }
+
+    fn open_trash(&mut self) {
+        let items = self.data_handler.trash_items();
+        if items.is_empty() {
+            self.log("Корзина пуста.");
+        } else {
+            self.ui.trash_form(items);
+        }
+    }
+
+    fn restore_from_trash(&mut self, file: DataFile, index: usize) {
+        let restored = match file {
+            DataFile::People => self.data_handler.restore_from_trash::<PersonaRepr>(index),
+            DataFile::Tags => self.data_handler.restore_from_trash::<TagRepr>(index),
+            DataFile::Letters => self.data_handler.restore_from_trash::<LetterRepr>(index),
+            DataFile::Templates => self.data_handler.restore_from_trash::<TemplateRepr>(index),
+        };
+        if restored.is_some() {
+            self.autosave();
+        }
+        self.open_trash();
+    }
+
+    fn purge_from_trash(&mut self, file: DataFile, index: usize) {
+        let purged = match file {
+            DataFile::People => self.data_handler.purge_from_trash::<PersonaRepr>(index),
+            DataFile::Tags => self.data_handler.purge_from_trash::<TagRepr>(index),
+            DataFile::Letters => self.data_handler.purge_from_trash::<LetterRepr>(index),
+            DataFile::Templates => self.data_handler.purge_from_trash::<TemplateRepr>(index),
+        };
+        if purged {
+            self.autosave();
+        }
+        self.open_trash();
+    }
+
+    fn empty_trash(&mut self) {
+        self.data_handler.get_trash_mut::<PersonaRepr>().purge_all();
+        self.data_handler.get_trash_mut::<TagRepr>().purge_all();
+        self.data_handler.get_trash_mut::<LetterRepr>().purge_all();
         self.data_handler
-            .get_people_mut()
-            .remove_representation(persona);
+            .get_trash_mut::<TemplateRepr>()
+            .purge_all();
+        self.autosave();
+    }
+
+    fn undo(&mut self) {
+        match self.data_handler.undo_removal() {
+            Some((file, identities)) => {
+                self.autosave();
+                self.log(format!(
+                    "Восстановлено ({}): {}",
+                    file,
+                    identities.join(", ")
+                ));
+            }
+            None => self.log("Отменять нечего."),
+        }
+    }
+
+    /// Saves the data after every completed change.
+    fn autosave(&mut self) {
+        if let Err(e) = self.data_handler.finalize() {
+            self.log(format!("Не удалось сохранить данные: {}", e));
+        }
+        for e in self.data_handler.take_load_errors() {
+            self.load_error_dialog(e);
+        }
     }
 
+    fn flush_drafts(&mut self) {
+        if let Err(e) = self.data_handler.flush_drafts(false) {
+            self.log(format!("Не удалось сохранить черновики: {}", e));
+        }
+    }
+
+    /// The last save before quitting; the interface is about to go, so the
+    /// failures are kept for `run` to return.
     fn finalize(&mut self) {
-        self.data_handler.finalize();
+        if let Err(e) = self.data_handler.finalize() {
+            self.exit_errors
+                .push(format!("Не удалось сохранить данные: {}", e));
+        }
+        for e in self.data_handler.take_load_errors() {
+            self.exit_errors.push(e.to_string());
+        }
+        if let Err(e) = self.data_handler.flush_drafts(true) {
+            self.exit_errors
+                .push(format!("Не удалось сохранить черновики: {}", e));
+        }
     }
 }
 
+pub mod mailer;
+pub mod presenter;
+pub mod secrets;
 pub mod settings;
 pub mod signals;
+pub mod worker;
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, sync::Mutex};
+
+    use lettre::Message;
+
+    use super::{
+        mailer::MailError,
+        presenter::recording::{Presented, RecordingPresenter},
+        settings::SenderProfile,
+        *,
+    };
+    use crate::{
+        data_handler::{drafts::Draft, lock::DataLock},
+        test_utils::TempDir,
+    };
+
+    #[derive(Default)]
+    struct RecordingOutbox {
+        sent: Mutex<Vec<Message>>,
+    }
+
+    impl Outbox for RecordingOutbox {
+        fn send(&self, _profile: &SenderProfile, message: &Message) -> Result<(), MailError> {
+            self.sent.lock().unwrap().push(message.clone());
+            Ok(())
+        }
+    }
+
+    struct Fixture {
+        controller: Controller<RecordingPresenter>,
+        presenter: RecordingPresenter,
+        outbox: Arc<RecordingOutbox>,
+        tx: mpsc::Sender<ControllerSignal>,
+        dir: TempDir,
+    }
+
+    impl Fixture {
+        fn new() -> Self {
+            let dir = TempDir::new("controller");
+            let presenter = RecordingPresenter::default();
+            let outbox = Arc::new(RecordingOutbox::default());
+            let (tx, rx) = mpsc::channel();
+            let controller = Controller::with_parts(
+                presenter.clone(),
+                outbox.clone(),
+                Rc::new(RefCell::new(SettingsRepr::default())),
+                DataHandler::with_dir(&dir),
+                (tx.clone(), rx),
+            );
+            Self {
+                controller,
+                presenter,
+                outbox,
+                tx,
+                dir,
+            }
+        }
+
+        fn signal(&mut self, signal: ControllerSignal) -> Vec<Presented> {
+            self.tx.send(signal).unwrap();
+            self.controller.process_signals();
+            self.presenter.take()
+        }
+
+        /// Waits for the background mailing and returns what was shown meanwhile.
+        fn finish_sending(&mut self) -> Vec<Presented> {
+            if let Some(task) = self.controller.send_task.as_mut() {
+                task.wait();
+            }
+            self.controller.poll_sending();
+            self.presenter.take()
+        }
+    }
+
+    fn persona(family: &str, email: &str) -> Persona {
+        Rc::new(RefCell::new(PersonaRepr::new(
+            family,
+            "Иван",
+            "Иванович",
+            email,
+        )))
+    }
+
+    #[test]
+    fn test_import_tag_send() {
+        let mut fixture = Fixture::new();
+        let people = vec![
+            persona("Иванов", "ivanov@mipt.ru"),
+            persona("Петров", "petrov@mipt.ru"),
+            persona("Сидоров", "sidorov@mipt.ru"),
+        ];
+        let presented = fixture.signal(ControllerSignal::ImportPersona(people));
+        assert!(matches!(&presented[..], [Presented::Info(info)] if info.contains('3')));
+
+        let (key, tag, persona_list) = match &fixture.signal(ControllerSignal::NewTag)[..] {
+            [Presented::TagForm {
+                key,
+                tag,
+                persona_list,
+            }] => (key.clone(), tag.clone(), persona_list.clone()),
+            other => panic!("expected tag form, got {:?}", other),
+        };
+        assert_eq!(persona_list.len(), 3);
+        make_mut(&tag).set_label("Совет");
+        make_mut(&tag).set_persona_ids(persona_list[..2].to_vec());
+        fixture.signal(ControllerSignal::CompleteEditTag { key, tag });
+        let presented = fixture.signal(ControllerSignal::SelectTag);
+        assert!(matches!(&presented[..], [Presented::SelectTagForm(tags)] if tags.len() == 1));
+
+        let (key, letter) = match &fixture.signal(ControllerSignal::NewLetter)[..] {
+            [Presented::LetterForm { key, letter }] => (key.clone(), letter.clone()),
+            other => panic!("expected letter form, got {:?}", other),
+        };
+        make_mut(&letter).set_topic("Заседание совета");
+        fixture.signal(ControllerSignal::CompleteEditLetter {
+            key,
+            letter: letter.clone(),
+        });
+        let profile = match &fixture.signal(ControllerSignal::OpenLetterToSend(letter.clone()))[..]
+        {
+            [Presented::SendLetterForm {
+                people, profiles, ..
+            }] => {
+                assert_eq!(people.len(), 3);
+                profiles[0].clone()
+            }
+            other => panic!("expected send form, got {:?}", other),
+        };
+
+        let recipients = Recipients {
+            to: vec!["ivanov@mipt.ru".into(), "petrov@mipt.ru".into()],
+            ..Default::default()
+        };
+        let presented = fixture.signal(ControllerSignal::SendEmail {
+            letter,
+            recipients,
+            profile,
+        });
+        assert!(
+            matches!(&presented[..], [Presented::SendProgressDialog(progress)] if progress.jobs.len() == 1)
+        );
+        let presented = fixture.finish_sending();
+        assert!(matches!(
+            presented.last(),
+            Some(Presented::Info(info)) if info.contains("отправлено 1")
+        ));
+        let sent = fixture.outbox.sent.lock().unwrap();
+        assert_eq!(sent.len(), 1);
+        assert_eq!(sent[0].envelope().to().len(), 2);
+        assert_eq!(fixture.controller.data_handler.get_letters().size(), 1);
+    }
+
+    /// Saves a new letter and sends it to Иванов; returns the letter.
+    fn send_to_ivanov(fixture: &mut Fixture) -> Letter {
+        fixture.signal(ControllerSignal::ImportPersona(vec![persona(
+            "Иванов",
+            "ivanov@mipt.ru",
+        )]));
+        let (key, letter) = match &fixture.signal(ControllerSignal::NewLetter)[..] {
+            [Presented::LetterForm { key, letter }] => (key.clone(), letter.clone()),
+            other => panic!("expected letter form, got {:?}", other),
+        };
+        fixture.signal(ControllerSignal::CompleteEditLetter {
+            key,
+            letter: letter.clone(),
+        });
+        let profile = match &fixture.signal(ControllerSignal::OpenLetterToSend(letter.clone()))[..]
+        {
+            [Presented::SendLetterForm { profiles, .. }] => profiles[0].clone(),
+            other => panic!("expected send form, got {:?}", other),
+        };
+        fixture.signal(ControllerSignal::SendEmail {
+            letter: letter.clone(),
+            recipients: Recipients {
+                to: vec!["ivanov@mipt.ru".into()],
+                ..Default::default()
+            },
+            profile,
+        });
+        letter
+    }
+
+    #[test]
+    fn test_letter_state_follows_sending() {
+        let mut fixture = Fixture::new();
+        let (key, letter) = match &fixture.signal(ControllerSignal::NewLetter)[..] {
+            [Presented::LetterForm { key, letter }] => (key.clone(), letter.clone()),
+            other => panic!("expected letter form, got {:?}", other),
+        };
+        fixture.signal(ControllerSignal::CompleteEditLetter { key, letter });
+        let mut filter = LetterFilter {
+            state: Some(LetterState::Draft),
+            ..Default::default()
+        };
+        let drafts = fixture.signal(ControllerSignal::SelectLetter(filter.clone()));
+        assert!(
+            matches!(&drafts[..], [Presented::SelectLetterForm(letters)] if letters.len() == 1)
+        );
+
+        let letter = send_to_ivanov(&mut fixture);
+        assert_eq!(make_ref(&letter).state(), LetterState::Queued);
+        fixture.finish_sending();
+        assert_eq!(make_ref(&letter).state(), LetterState::Sent);
+
+        let drafts = fixture.signal(ControllerSignal::SelectLetter(filter.clone()));
+        assert!(
+            matches!(&drafts[..], [Presented::SelectLetterForm(letters)] if letters.len() == 1)
+        );
+        filter.state = Some(LetterState::Sent);
+        let sent = fixture.signal(ControllerSignal::SelectLetter(filter));
+        assert!(matches!(&sent[..], [Presented::SelectLetterForm(letters)] if letters.len() == 1));
+    }
+
+    #[test]
+    fn test_search_finds_recipients() {
+        let mut fixture = Fixture::new();
+        send_to_ivanov(&mut fixture);
+        fixture.finish_sending();
+        let index = match &fixture.signal(ControllerSignal::SearchLetters)[..] {
+            [Presented::SearchForm(index)] => index.clone(),
+            other => panic!("expected search form, got {:?}", other),
+        };
+        assert_eq!(index.search("ivanov").len(), 1);
+        assert!(index.search("petrov").is_empty());
+    }
+
+    #[test]
+    fn test_report_counts_sent_letters() {
+        let mut fixture = Fixture::new();
+        send_to_ivanov(&mut fixture);
+        fixture.finish_sending();
+        let report = match &fixture.signal(ControllerSignal::OpenReport)[..] {
+            [Presented::ReportForm(report)] => report.clone(),
+            other => panic!("expected report, got {:?}", other),
+        };
+        let ivanov = &report.people[0];
+        assert_eq!((ivanov.letters, ivanov.never_reached), (1, 0));
+    }
+
+    #[test]
+    fn test_edit_persona_tags() {
+        let mut fixture = Fixture::new();
+        let ivanov = persona("Иванов", "ivanov@mipt.ru");
+        fixture.signal(ControllerSignal::ImportPersona(vec![ivanov.clone()]));
+        for label in ["Совет", "Кафедра"] {
+            let tag = new_tag(label);
+            make_mut(&tag).set_persona_ids(vec!["Иванов Иван Иванович".into()]);
+            fixture.signal(ControllerSignal::CompleteEditTag {
+                key: label.into(),
+                tag,
+            });
+        }
+        let presented = fixture.signal(ControllerSignal::EditPersona(ivanov.clone()));
+        let key = match &presented[..] {
+            [Presented::EditPersonaForm { key, .. }] => key.clone(),
+            other => panic!("expected persona form, got {:?}", other),
+        };
+        make_mut(&ivanov).set_family("Петров");
+        fixture.signal(ControllerSignal::CompleteEditPersona {
+            key,
+            persona: ivanov,
+            tags: vec!["Кафедра".into()],
+        });
+        let mut members = |label: &str| {
+            make_ref(
+                &fixture
+                    .controller
+                    .data_handler
+                    .get_tags()
+                    .get(label)
+                    .unwrap(),
+            )
+            .persona_ids()
+            .cloned()
+            .collect::<Vec<_>>()
+        };
+        assert!(members("Совет").is_empty());
+        assert_eq!(members("Кафедра"), vec!["Петров Иван Иванович"]);
+    }
+
+    #[test]
+    fn test_bulk_persona_actions() {
+        let mut fixture = Fixture::new();
+        let people = vec![
+            persona("Иванов", "ivanov@mipt.ru"),
+            persona("Петров", "petrov@mipt.ru"),
+            persona("Сидоров", "sidorov@mipt.ru"),
+        ];
+        fixture.signal(ControllerSignal::ImportPersona(people.clone()));
+        fixture.signal(ControllerSignal::CompleteEditTag {
+            key: "Совет".into(),
+            tag: new_tag("Совет"),
+        });
+        let identities = people
+            .iter()
+            .map(|persona| make_ref(persona).identity())
+            .collect::<Vec<_>>();
+        fixture.signal(ControllerSignal::AddToTag {
+            label: "Совет".into(),
+            people: identities.clone(),
+        });
+        fixture.signal(ControllerSignal::RemoveFromTag {
+            label: "Совет".into(),
+            people: identities[1..2].to_vec(),
+        });
+        let tag = fixture
+            .controller
+            .data_handler
+            .get_tags()
+            .get("Совет")
+            .unwrap();
+        assert_eq!(
+            make_ref(&tag).persona_ids().collect::<Vec<_>>(),
+            vec![&identities[0], &identities[2]]
+        );
+
+        let letter =
+            match &fixture.signal(ControllerSignal::WriteLetterTo(identities[..2].to_vec()))[..] {
+                [Presented::LetterForm { letter, .. }] => letter.clone(),
+                other => panic!("expected letter form, got {:?}", other),
+            };
+        let presented = fixture.signal(ControllerSignal::OpenLetterToSend(letter.clone()));
+        assert!(
+            matches!(&presented[..], [Presented::SendLetterForm { to, .. }] if to[..] == identities[..2])
+        );
+        let presented = fixture.signal(ControllerSignal::OpenLetterToSend(letter));
+        assert!(matches!(&presented[..], [Presented::SendLetterForm { to, .. }] if to.is_empty()));
+        let letter = match &fixture.signal(ControllerSignal::WriteLetterTo(identities.clone()))[..]
+        {
+            [Presented::LetterForm { letter, .. }] => letter.clone(),
+            other => panic!("expected letter form, got {:?}", other),
+        };
+        fixture.signal(ControllerSignal::CancelEditLetter(
+            make_ref(&letter).identity(),
+        ));
+        assert!(fixture.controller.addressees.is_empty());
+
+        fixture.signal(ControllerSignal::RemovePersona(people));
+        assert_eq!(fixture.controller.data_handler.get_people().size(), 0);
+        fixture.signal(ControllerSignal::Undo);
+        assert_eq!(fixture.controller.data_handler.get_people().size(), 3);
+    }
+
+    #[test]
+    fn test_letter_from_template() {
+        let mut fixture = Fixture::new();
+        let ivanov = persona("Иванов", "ivanov@mipt.ru");
+        fixture.signal(ControllerSignal::ImportPersona(vec![ivanov.clone()]));
+        let tag = new_tag("Кафедра");
+        make_mut(&tag).set_persona_ids(vec![make_ref(&ivanov).identity()]);
+        fixture.signal(ControllerSignal::CompleteEditTag {
+            key: "Кафедра".into(),
+            tag,
+        });
+        let letter = new_letter();
+        make_mut(&letter).set_topic("Семинар");
+        let (key, template) = match &fixture.signal(ControllerSignal::SaveAsTemplate(letter))[..] {
+            [Presented::TemplateForm { key, template }] => (key.clone(), template.clone()),
+            other => panic!("expected template form, got {:?}", other),
+        };
+        assert_eq!(key, "Семинар");
+        make_mut(&template).set_tag(Some("Кафедра".into()));
+        fixture.signal(ControllerSignal::CompleteEditTemplate { key, template });
+
+        let template = match &fixture.signal(ControllerSignal::SelectTemplate)[..] {
+            [Presented::SelectTemplateForm(templates)] => templates[0].clone(),
+            other => panic!("expected templates, got {:?}", other),
+        };
+        let letter = match &fixture.signal(ControllerSignal::NewLetterFromTemplate(template))[..] {
+            [Presented::LetterForm { letter, .. }] => letter.clone(),
+            other => panic!("expected letter form, got {:?}", other),
+        };
+        assert_eq!(make_ref(&letter).get_topic(), "Семинар");
+        let presented = fixture.signal(ControllerSignal::OpenLetterToSend(letter));
+        assert!(matches!(
+            &presented[..],
+            [Presented::SendLetterForm { to, .. }] if *to == vec![make_ref(&ivanov).identity()]
+        ));
+    }
+
+    #[test]
+    fn test_template_names_are_unique() {
+        let mut fixture = Fixture::new();
+        let letter = new_letter();
+        make_mut(&letter).set_topic("Семинар");
+        let save_as_template = |fixture: &mut Fixture| match &fixture
+            .signal(ControllerSignal::SaveAsTemplate(letter.clone()))[..]
+        {
+            [Presented::TemplateForm { key, template }] => {
+                fixture.signal(ControllerSignal::CompleteEditTemplate {
+                    key: key.clone(),
+                    template: template.clone(),
+                });
+                key.clone()
+            }
+            other => panic!("expected template form, got {:?}", other),
+        };
+        assert_eq!(save_as_template(&mut fixture), "Семинар");
+        assert_eq!(save_as_template(&mut fixture), "Семинар (2)");
+
+        let second = fixture
+            .controller
+            .data_handler
+            .get_templates()
+            .get("Семинар (2)")
+            .unwrap();
+        make_mut(&second).set_name("Семинар");
+        let presented = fixture.signal(ControllerSignal::CompleteEditTemplate {
+            key: "Семинар (2)".into(),
+            template: second.clone(),
+        });
+        assert!(matches!(
+            &presented[..],
+            [Presented::TemplateForm { key, .. }, Presented::Info(_)] if key == "Семинар (2)"
+        ));
+        assert_eq!(make_ref(&second).identity(), "Семинар (2)");
+        assert_eq!(fixture.controller.data_handler.get_templates().size(), 2);
+    }
+
+    #[test]
+    fn test_empty_trash_purges_templates() {
+        let mut fixture = Fixture::new();
+        let template = new_template(TemplateRepr::default());
+        make_mut(&template).set_name("Семинар");
+        fixture.signal(ControllerSignal::CompleteEditTemplate {
+            key: "Семинар".into(),
+            template: template.clone(),
+        });
+        fixture.signal(ControllerSignal::RemoveTemplate(template));
+        let presented = fixture.signal(ControllerSignal::OpenTrash);
+        assert!(matches!(&presented[..], [Presented::TrashForm(items)] if items.len() == 1));
+        fixture.signal(ControllerSignal::EmptyTrash);
+        let presented = fixture.signal(ControllerSignal::OpenTrash);
+        assert!(matches!(&presented[..], [Presented::Info(_)]));
+    }
+
+    #[test]
+    fn test_remove_persona_after_alert() {
+        let mut fixture = Fixture::new();
+        let ivanov = persona("Иванов", "ivanov@mipt.ru");
+        fixture.signal(ControllerSignal::ImportPersona(vec![ivanov.clone()]));
+        let presented = fixture.signal(ControllerSignal::RemovePersonaAlert(vec![ivanov.clone()]));
+        assert!(matches!(
+            &presented[..],
+            [Presented::RemovePersonaDialog(_)]
+        ));
+        fixture.signal(ControllerSignal::RemovePersona(vec![ivanov]));
+        assert_eq!(fixture.controller.data_handler.get_people().size(), 0);
+        let presented = fixture.signal(ControllerSignal::OpenTrash);
+        assert!(matches!(&presented[..], [Presented::TrashForm(items)] if items.len() == 1));
+        fixture.signal(ControllerSignal::Undo);
+        assert_eq!(fixture.controller.data_handler.get_people().size(), 1);
+        let presented = fixture.signal(ControllerSignal::OpenTrash);
+        assert!(matches!(&presented[..], [Presented::Info(_)]));
+    }
+
+    #[test]
+    fn test_corrupt_data_asks_user() {
+        let mut fixture = Fixture::new();
+        fs::write(fixture.dir.join("tag.json"), "not json").unwrap();
+        fixture.controller.check_data();
+        assert!(matches!(
+            &fixture.presenter.take()[..],
+            [Presented::LoadErrorDialog(DataFile::Tags)]
+        ));
+        let presented = fixture.signal(ControllerSignal::RetryLoad(DataFile::Tags));
+        assert!(matches!(
+            &presented[..],
+            [Presented::LoadErrorDialog(DataFile::Tags)]
+        ));
+        fixture.signal(ControllerSignal::StartEmpty(DataFile::Tags));
+        fixture.signal(ControllerSignal::Quit);
+        assert_eq!(
+            fs::read_to_string(fixture.dir.join("tag.json")).unwrap(),
+            "not json"
+        );
+    }
+
+    #[test]
+    fn test_restore_backup() {
+        let mut fixture = Fixture::new();
+        fixture.signal(ControllerSignal::ImportPersona(vec![persona(
+            "Иванов",
+            "ivanov@mipt.ru",
+        )]));
+        fixture.controller.finalize();
+        fixture.signal(ControllerSignal::ImportPersona(vec![persona(
+            "Петров",
+            "petrov@mipt.ru",
+        )]));
+        fixture.controller.finalize();
+        let backups = match &fixture.signal(ControllerSignal::OpenBackups)[..] {
+            [Presented::RestoreBackupForm(backups)] => backups.clone(),
+            other => panic!("expected backup form, got {:?}", other),
+        };
+        let (file, path) = backups[0].clone();
+        assert_eq!(file, DataFile::People);
+        fixture.signal(ControllerSignal::LoadBackup {
+            file,
+            path: path.to_string_lossy().into_owned(),
+        });
+        assert_eq!(fixture.controller.data_handler.get_people().size(), 1);
+    }
+
+    #[test]
+    fn test_drafts_recovered_after_restart() {
+        let mut fixture = Fixture::new();
+        fixture.signal(ControllerSignal::SaveDraft(Draft::new(
+            "unsaved".into(),
+            "Приглашение",
+            "Приходите.",
+        )));
+        fixture.controller.finalize();
+        fixture.controller.data_handler = DataHandler::with_dir(&fixture.dir);
+        fixture.controller.check_data();
+        assert!(matches!(
+            &fixture.presenter.take()[..],
+            [Presented::RecoverDraftsDialog(drafts)] if drafts.len() == 1
+        ));
+        match &fixture.signal(ControllerSignal::RecoverDrafts)[..] {
+            [Presented::LetterForm { key, letter }] => {
+                assert_eq!(key, "unsaved");
+                assert_eq!(make_ref(letter).get_topic(), "Приглашение");
+            }
+            other => panic!("expected letter form, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_overdue_mailing_waits_for_answer() {
+        let mut fixture = Fixture::new();
+        let letter = new_letter();
+        make_mut(&letter).set_topic("Семинар");
+        let profile = match &fixture.signal(ControllerSignal::OpenLetterToSend(letter.clone()))[..]
+        {
+            [Presented::SendLetterForm { profiles, .. }] => profiles[0].clone(),
+            other => panic!("expected send form, got {:?}", other),
+        };
+        let hour = chrono::Duration::hours(1);
+        fixture.signal(ControllerSignal::ScheduleEmail {
+            letter: letter.clone(),
+            recipients: Recipients {
+                to: vec!["ivanov@mipt.ru".into()],
+                ..Default::default()
+            },
+            profile,
+            at: chrono::Local::now() + hour,
+        });
+        fixture.controller.send_due();
+        assert!(fixture.controller.send_task.is_none());
+        let presented = fixture.signal(ControllerSignal::OpenSchedule);
+        assert!(matches!(&presented[..], [Presented::ScheduleForm(letters)] if letters.len() == 1));
+
+        fixture.signal(ControllerSignal::Reschedule {
+            letter,
+            at: chrono::Local::now() - hour,
+        });
+        fixture.controller.finalize();
+        fixture.controller.data_handler = DataHandler::with_dir(&fixture.dir);
+        fixture.controller.check_data();
+        assert!(matches!(
+            &fixture.presenter.take()[..],
+            [Presented::OverdueDialog(letters)] if letters.len() == 1
+        ));
+        fixture.controller.send_due();
+        assert!(fixture.controller.send_task.is_none());
+
+        fixture.signal(ControllerSignal::SendOverdue);
+        fixture.controller.send_due();
+        fixture.finish_sending();
+        assert_eq!(fixture.outbox.sent.lock().unwrap().len(), 1);
+        let presented = fixture.signal(ControllerSignal::OpenSchedule);
+        assert!(matches!(&presented[..], [Presented::Info(_)]));
+    }
+
+    #[test]
+    fn test_failed_due_mailing_stays_scheduled() {
+        let mut fixture = Fixture::new();
+        let letter = new_letter();
+        fixture.signal(ControllerSignal::ScheduleEmail {
+            letter: letter.clone(),
+            recipients: Recipients {
+                to: vec!["ivanov@mipt.ru".into()],
+                ..Default::default()
+            },
+            profile: "Удалённый".into(),
+            at: chrono::Local::now(),
+        });
+        fixture.controller.send_due();
+        assert!(fixture.controller.send_task.is_none());
+        assert_eq!(make_ref(&letter).state(), LetterState::Failed);
+        assert!(make_ref(&letter).schedule().is_some());
+        fixture.presenter.take();
+        fixture.controller.send_due();
+        assert!(
+            fixture.presenter.take().is_empty(),
+            "a held mailing is not retried"
+        );
+        let presented = fixture.signal(ControllerSignal::OpenSchedule);
+        assert!(matches!(&presented[..], [Presented::ScheduleForm(letters)] if letters.len() == 1));
+    }
+
+    #[test]
+    fn test_read_only_instance_leaves_schedule() {
+        let mut fixture = Fixture::new();
+        let letter = new_letter();
+        make_mut(&letter).set_schedule(Some(Schedule {
+            at: chrono::Local::now(),
+            profile: String::new(),
+            to: vec!["ivanov@mipt.ru".into()],
+            cc: vec![],
+            bcc: vec![],
+        }));
+        fixture
+            .controller
+            .data_handler
+            .get_letters_mut()
+            .insert_or_update(letter);
+        fixture.controller.finalize();
+        let _lock = DataLock::acquire(&fixture.dir).unwrap();
+        let mut data_handler = DataHandler::with_dir(&fixture.dir);
+        assert!(data_handler.lock().is_err());
+        fixture.controller.data_handler = data_handler;
+        fixture.controller.check_data();
+        assert!(fixture.presenter.take().is_empty());
+        fixture.controller.send_due();
+        assert!(fixture.controller.send_task.is_none());
+    }
+
+    #[test]
+    fn test_interrupted_mailing_fails_on_start() {
+        let mut fixture = Fixture::new();
+        let letter = new_letter();
+        make_mut(&letter).mark_queued();
+        fixture
+            .controller
+            .data_handler
+            .get_letters_mut()
+            .insert_or_update(letter.clone());
+        fixture.controller.finalize();
+        fixture.controller.data_handler = DataHandler::with_dir(&fixture.dir);
+        fixture.controller.check_data();
+        assert!(matches!(
+            &fixture.presenter.take()[..],
+            [Presented::Info(_)]
+        ));
+        let identity = make_ref(&letter).identity();
+        let letter = fixture
+            .controller
+            .data_handler
+            .get_letters()
+            .get(&identity)
+            .unwrap();
+        assert_eq!(make_ref(&letter).state(), LetterState::Failed);
+    }
+
+    #[test]
+    fn test_quit_saves_data() {
+        let mut fixture = Fixture::new();
+        fixture.signal(ControllerSignal::ImportPersona(vec![persona(
+            "Иванов",
+            "ivanov@mipt.ru",
+        )]));
+        fixture.signal(ControllerSignal::Quit);
+        assert!(fixture.controller.stop);
+        let mut reloaded = DataHandler::with_dir(&fixture.dir);
+        assert_eq!(reloaded.get_people().size(), 1);
+    }
+}
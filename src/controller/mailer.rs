@@ -0,0 +1,386 @@
+use std::fmt;
+
+use lettre::{
+    address::{AddressError, Envelope},
+    message::{header::ContentType, Attachment, Mailbox, MessageBuilder, MultiPart, SinglePart},
+    transport::smtp::{self, authentication::Credentials},
+    Message, SmtpTransport, Transport,
+};
+
+use crate::data_handler::letter::{LetterRepr, Schedule};
+
+use super::settings::{SenderProfile, SettingsRepr};
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Recipients {
+    pub to: Vec<String>,
+    pub cc: Vec<String>,
+    pub bcc: Vec<String>,
+}
+
+impl From<&Schedule> for Recipients {
+    fn from(schedule: &Schedule) -> Self {
+        Self {
+            to: schedule.to.clone(),
+            cc: schedule.cc.clone(),
+            bcc: schedule.bcc.clone(),
+        }
+    }
+}
+
+impl Recipients {
+    pub fn count(&self) -> usize {
+        self.to.len() + self.cc.len() + self.bcc.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.count() == 0
+    }
+
+    /// Parts of at most `limit` addresses each, To before Cc before Bcc;
+    /// zero is no limit.
+    pub fn split(&self, limit: usize) -> Vec<Recipients> {
+        if self.is_empty() {
+            return vec![];
+        }
+        if limit == 0 {
+            return vec![self.clone()];
+        }
+        type Column = fn(&mut Recipients) -> &mut Vec<String>;
+        let columns: [(&Vec<String>, Column); 3] = [
+            (&self.to, |part| &mut part.to),
+            (&self.cc, |part| &mut part.cc),
+            (&self.bcc, |part| &mut part.bcc),
+        ];
+        let mut parts = vec![Recipients::default()];
+        for (addresses, column) in columns {
+            for address in addresses {
+                if parts.last().unwrap().count() == limit {
+                    parts.push(Recipients::default());
+                }
+                column(parts.last_mut().unwrap()).push(address.to_owned());
+            }
+        }
+        parts
+    }
+
+    pub fn addresses(&self) -> Vec<String> {
+        [&self.to[..], &self.cc[..], &self.bcc[..]].concat()
+    }
+
+    /// Hides everybody in Bcc once the visible recipients exceed `bcc_threshold`
+    /// (zero disables it) and puts the sender in To when nobody else is visible.
+    pub fn prepare(mut self, bcc_threshold: usize, sender: &str) -> Self {
+        if bcc_threshold > 0 && self.to.len() + self.cc.len() > bcc_threshold {
+            self.bcc.append(&mut self.to);
+            self.bcc.append(&mut self.cc);
+        }
+        if self.to.is_empty() && self.cc.is_empty() && !self.bcc.is_empty() {
+            self.to.push(sender.to_owned());
+        }
+        self
+    }
+}
+
+#[derive(Debug)]
+pub enum MailError {
+    NoRecipients,
+    UnknownProfile(String),
+    Address(String, AddressError),
+    Message(lettre::error::Error),
+    Transport(smtp::Error),
+}
+
+impl fmt::Display for MailError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MailError::NoRecipients => write!(f, "не выбран ни один адресат"),
+            MailError::UnknownProfile(name) => {
+                write!(f, "профиль отправителя {:?} не найден", name)
+            }
+            MailError::Address(address, e) => write!(f, "неверный адрес {:?}: {}", address, e),
+            MailError::Message(e) => write!(f, "ошибка составления письма: {}", e),
+            MailError::Transport(e) => write!(f, "ошибка SMTP: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for MailError {}
+
+/// Delivers composed messages; `SmtpOutbox` talks to the profile's relay.
+pub trait Outbox: Send + Sync {
+    fn send(&self, profile: &SenderProfile, message: &Message) -> Result<(), MailError>;
+}
+
+pub struct SmtpOutbox;
+
+impl Outbox for SmtpOutbox {
+    fn send(&self, profile: &SenderProfile, message: &Message) -> Result<(), MailError> {
+        send_message(profile, message)
+    }
+}
+
+pub fn compose_text(profile: &SenderProfile, letter: &LetterRepr) -> String {
+    [
+        profile.plural_title.as_str(),
+        letter.get_text(),
+        profile.letter_signature.as_str(),
+    ]
+    .iter()
+    .filter(|part| !part.is_empty())
+    .cloned()
+    .collect::<Vec<_>>()
+    .join("\n\n")
+}
+
+pub fn build_message(
+    profile: &SenderProfile,
+    letter: &LetterRepr,
+    recipients: &Recipients,
+) -> Result<Message, MailError> {
+    if recipients.is_empty() {
+        return Err(MailError::NoRecipients);
+    }
+    let mut builder = message_builder(profile, letter)?;
+    for address in recipients.to.iter() {
+        builder = builder.to(parse_mailbox(address)?);
+    }
+    for address in recipients.cc.iter() {
+        builder = builder.cc(parse_mailbox(address)?);
+    }
+    for address in recipients.bcc.iter() {
+        builder = builder.bcc(parse_mailbox(address)?);
+    }
+    attach_body(builder, profile, letter)
+}
+
+fn message_builder(
+    profile: &SenderProfile,
+    letter: &LetterRepr,
+) -> Result<MessageBuilder, MailError> {
+    let mut builder = Message::builder()
+        .from(parse_mailbox(&profile.letter_from)?)
+        .subject(letter.get_topic());
+    if !profile.reply_to.trim().is_empty() {
+        builder = builder.reply_to(parse_mailbox(&profile.reply_to)?);
+    }
+    Ok(builder)
+}
+
+fn attach_body(
+    builder: MessageBuilder,
+    profile: &SenderProfile,
+    letter: &LetterRepr,
+) -> Result<Message, MailError> {
+    let mut body = MultiPart::mixed().singlepart(SinglePart::plain(compose_text(profile, letter)));
+    for attached_file in letter.attachments() {
+        let content_type =
+            ContentType::parse(attached_file.get_content_type()).unwrap_or(ContentType::TEXT_PLAIN);
+        body = body.singlepart(
+            Attachment::new(attached_file.get_filename().to_owned())
+                .body(attached_file.get_content_bytes().to_vec(), content_type),
+        );
+    }
+    builder.multipart(body).map_err(MailError::Message)
+}
+
+pub fn send_message(profile: &SenderProfile, message: &Message) -> Result<(), MailError> {
+    let transport = SmtpTransport::relay(&profile.smtp_relay)
+        .map_err(MailError::Transport)?
+        .credentials(Credentials::new(
+            profile.smtp_user.to_owned(),
+            profile.smtp_password.to_owned(),
+        ))
+        .build();
+    transport.send(message).map_err(MailError::Transport)?;
+    Ok(())
+}
+
+/// One message of a mailing with the addresses it is delivered to.
+pub struct SendJob {
+    pub label: String,
+    pub addresses: Vec<String>,
+    pub message: Message,
+}
+
+/// Everything the send worker needs: it owns no `Rc` and may cross threads.
+pub struct Mailing {
+    pub profile: SenderProfile,
+    pub recipients: Recipients,
+    pub jobs: Vec<SendJob>,
+}
+
+/// Splits a mailing into a message for the visible recipients and one for
+/// the Bcc recipients, which shows the visible To and Cc but is delivered to
+/// the hidden addresses only. Both are split further when the profile limits
+/// the recipients of a message.
+pub fn build_jobs(
+    profile: &SenderProfile,
+    letter: &LetterRepr,
+    recipients: &Recipients,
+) -> Result<Vec<SendJob>, MailError> {
+    if recipients.is_empty() {
+        return Err(MailError::NoRecipients);
+    }
+    let visible = Recipients {
+        to: recipients.to.clone(),
+        cc: recipients.cc.clone(),
+        bcc: vec![],
+    };
+    let hidden = Recipients {
+        bcc: recipients.bcc.clone(),
+        ..Default::default()
+    };
+    let limit = profile.limits.recipients_per_message;
+    let mut jobs = vec![];
+    for part in visible.split(limit) {
+        let addresses = part.addresses();
+        jobs.push(SendJob {
+            label: addresses.join(", "),
+            addresses,
+            message: build_message(profile, letter, &part)?,
+        });
+    }
+    let sender = parse_mailbox(&profile.letter_from)?.email;
+    for part in hidden.split(limit) {
+        let hidden_to = part
+            .bcc
+            .iter()
+            .map(|address| parse_mailbox(address).map(|mailbox| mailbox.email))
+            .collect::<Result<Vec<_>, _>>()?;
+        let envelope =
+            Envelope::new(Some(sender.clone()), hidden_to).map_err(MailError::Message)?;
+        let mut builder = message_builder(profile, letter)?.envelope(envelope);
+        for address in visible.to.iter() {
+            builder = builder.to(parse_mailbox(address)?);
+        }
+        for address in visible.cc.iter() {
+            builder = builder.cc(parse_mailbox(address)?);
+        }
+        jobs.push(SendJob {
+            label: format!("Bcc: {}", part.bcc.join(", ")),
+            message: attach_body(builder, profile, letter)?,
+            addresses: part.bcc,
+        });
+    }
+    Ok(jobs)
+}
+
+/// Picks the profile named `profile_name` and composes the messages of `letter`.
+pub fn prepare_mailing(
+    settings: &SettingsRepr,
+    profile_name: &str,
+    letter: &LetterRepr,
+    recipients: Recipients,
+) -> Result<Mailing, MailError> {
+    let settings = settings.effective();
+    let profile = settings
+        .get_profile(profile_name)
+        .ok_or_else(|| MailError::UnknownProfile(profile_name.to_owned()))?;
+    let recipients = recipients.prepare(settings.bcc_threshold, &profile.letter_from);
+    let jobs = build_jobs(profile, letter, &recipients)?;
+    Ok(Mailing {
+        profile: profile.clone(),
+        recipients,
+        jobs,
+    })
+}
+
+fn parse_mailbox(address: &str) -> Result<Mailbox, MailError> {
+    address
+        .trim()
+        .parse::<Mailbox>()
+        .map_err(|e| MailError::Address(address.to_owned(), e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn recipients(to: &[&str], cc: &[&str], bcc: &[&str]) -> Recipients {
+        let owned = |list: &[&str]| list.iter().map(|x| x.to_string()).collect::<Vec<_>>();
+        Recipients {
+            to: owned(to),
+            cc: owned(cc),
+            bcc: owned(bcc),
+        }
+    }
+
+    #[test]
+    fn test_prepare_keeps_small_mailing() {
+        let prepared = recipients(&["a@x.ru"], &["b@x.ru"], &[]).prepare(2, "me@x.ru");
+        assert_eq!(prepared, recipients(&["a@x.ru"], &["b@x.ru"], &[]));
+    }
+
+    #[test]
+    fn test_prepare_moves_mass_mailing_to_bcc() {
+        let prepared = recipients(&["a@x.ru", "b@x.ru"], &["c@x.ru"], &[]).prepare(2, "me@x.ru");
+        assert_eq!(
+            prepared,
+            recipients(&["me@x.ru"], &[], &["a@x.ru", "b@x.ru", "c@x.ru"])
+        );
+    }
+
+    #[test]
+    fn test_split_limits_recipients() {
+        let all = recipients(&["a@x.ru", "b@x.ru", "c@x.ru"], &["d@x.ru"], &[]);
+        assert_eq!(
+            all.split(2),
+            vec![
+                recipients(&["a@x.ru", "b@x.ru"], &[], &[]),
+                recipients(&["c@x.ru"], &["d@x.ru"], &[]),
+            ]
+        );
+        assert_eq!(all.split(0), vec![all.clone()]);
+        assert!(Recipients::default().split(2).is_empty());
+    }
+
+    #[test]
+    fn test_prepare_zero_threshold_disables_bcc() {
+        let prepared = recipients(&["a@x.ru", "b@x.ru"], &[], &[]).prepare(0, "me@x.ru");
+        assert_eq!(prepared, recipients(&["a@x.ru", "b@x.ru"], &[], &[]));
+    }
+
+    #[test]
+    fn test_build_message_hides_bcc() {
+        let profile = SenderProfile::default();
+        let mut letter = LetterRepr::new();
+        letter.set_topic("Семинар");
+        letter.set_text("Приглашаем.");
+        let to = recipients(&[], &[], &["hidden@x.ru"]).prepare(10, &profile.letter_from);
+        let message = build_message(&profile, &letter, &to).unwrap();
+        let formatted = String::from_utf8(message.formatted()).unwrap();
+        assert!(!formatted.contains("hidden@x.ru"));
+        assert_eq!(message.envelope().to().len(), 2);
+    }
+
+    #[test]
+    fn test_build_jobs_hides_bcc() {
+        let mut profile = SenderProfile::default();
+        let letter = LetterRepr::new();
+        let to = recipients(&["a@x.ru"], &[], &["h1@x.ru", "h2@x.ru", "h3@x.ru"]);
+        let jobs = build_jobs(&profile, &letter, &to).unwrap();
+        assert_eq!(jobs.len(), 2);
+        assert_eq!(jobs[0].message.envelope().to().len(), 1);
+        assert_eq!(jobs[1].addresses, vec!["h1@x.ru", "h2@x.ru", "h3@x.ru"]);
+        assert_eq!(jobs[1].message.envelope().to().len(), 3);
+        let formatted = String::from_utf8(jobs[1].message.formatted()).unwrap();
+        assert!(formatted.contains("a@x.ru"));
+        assert!(!formatted.contains("h1@x.ru"));
+
+        profile.limits.recipients_per_message = 2;
+        let jobs = build_jobs(&profile, &letter, &to).unwrap();
+        assert_eq!(jobs.len(), 3);
+        assert_eq!(jobs[2].addresses, vec!["h3@x.ru"]);
+    }
+
+    #[test]
+    fn test_build_message_without_recipients() {
+        let profile = SenderProfile::default();
+        let letter = LetterRepr::new();
+        assert!(matches!(
+            build_message(&profile, &letter, &Recipients::default()),
+            Err(MailError::NoRecipients)
+        ));
+    }
+}
@@ -0,0 +1,274 @@
+use std::path::PathBuf;
+
+use crate::data_handler::{
+    drafts::Draft,
+    handler::{DataFile, TrashItem},
+    letter::{Letter, LetterFilter},
+    persona::Persona,
+    report::ContactReport,
+    search::SearchIndex,
+    tag::Tag,
+    template::Template,
+    Identity,
+};
+
+use super::{settings::Settings, worker::SendProgress};
+
+/// Everything the controller asks of the user interface. The cursive `Ui`
+/// is the real implementation; user answers come back as `ControllerSignal`s.
+pub trait Presenter {
+    /// Lets the interface handle pending input once.
+    fn step_next(&mut self);
+    fn settings_form(&mut self, settings: Settings);
+    fn unlock_secrets_dialog(&mut self, is_new: bool);
+    /// Tells that the data is opened read-only and lets the user quit instead.
+    fn read_only_dialog(&mut self, info: &str);
+    /// Asks what to do about a data file that could not be read, offering
+    /// `backup` to load instead.
+    fn load_error_dialog(&mut self, file: DataFile, info: &str, backup: Option<PathBuf>);
+    /// Lets the user pick a backup to load; the choice comes back as `LoadBackup`.
+    fn restore_backup_form(&mut self, backups: Vec<(DataFile, PathBuf)>);
+    /// Tags in tree order with their depth and current number of members.
+    fn select_tag_form(&mut self, tree: Vec<(usize, Tag, usize)>);
+    /// `tags` are all the tags, to choose the parent from.
+    fn tag_form(&mut self, key: Identity, tag: Tag, persona_list: &[Identity], tags: Vec<Tag>);
+    fn remove_tag_dialog(&mut self, tag: Tag);
+    fn letter_form(&mut self, key: Identity, letter: Letter);
+    /// Lets the user start a letter from a template, edit or remove one.
+    fn select_template_form(&mut self, templates: Vec<Template>);
+    /// `tags` are the labels to choose the default recipients from.
+    fn template_form(&mut self, key: Identity, template: Template, tags: Vec<Identity>);
+    /// Offers to reopen the letters that were being edited when the program stopped.
+    fn recover_drafts_dialog(&mut self, drafts: Vec<Draft>);
+    fn send_letter_form(
+        &mut self,
+        letter: Letter,
+        people: Vec<Persona>,
+        tags: Vec<Tag>,
+        profiles: Vec<String>,
+        default_profile: String,
+        to: Vec<Identity>,
+    );
+    /// `tags` are all the tags, to add the chosen personas to.
+    fn select_persona_form(&mut self, persona: Vec<Persona>, tags: Vec<Tag>);
+    fn edit_persona_form(&mut self, key: Identity, persona: Persona, tags: Vec<(usize, Tag)>);
+    fn remove_persona_dialog(&mut self, persona: Vec<Persona>);
+    fn report_form(&mut self, report: ContactReport);
+    /// Letters passing `filter`, newest first, with the filter to change.
+    fn select_letter_form(&mut self, letters: Vec<Letter>, filter: LetterFilter);
+    /// Searches the letters as the query is typed; the chosen one is edited.
+    fn search_form(&mut self, index: SearchIndex);
+    /// Scheduled letters, earliest first, to reschedule or cancel.
+    fn schedule_form(&mut self, letters: Vec<Letter>);
+    /// Asks whether to send the mailings that fell due while the program was
+    /// closed; the answer comes back as `SendOverdue` or `CancelOverdue`.
+    fn overdue_dialog(&mut self, letters: Vec<Letter>);
+    /// Opens the progress dialog of a mailing that has just started.
+    fn send_progress_dialog(&mut self, progress: &SendProgress);
+    /// Refreshes the progress dialog unless the user has closed it.
+    fn send_progress(&mut self, progress: &SendProgress);
+    /// Lists removed records; answers come back as `RestoreFromTrash`,
+    /// `PurgeFromTrash` or `EmptyTrash`.
+    fn trash_form(&mut self, items: Vec<TrashItem>);
+    fn present_info(&mut self, info: &str);
+}
+
+#[cfg(test)]
+pub mod recording {
+    use std::{cell::RefCell, rc::Rc};
+
+    use super::*;
+
+    /// What a `RecordingPresenter` was asked to show.
+    #[derive(Debug, Clone)]
+    pub enum Presented {
+        SettingsForm,
+        UnlockSecretsDialog {
+            is_new: bool,
+        },
+        ReadOnlyDialog,
+        LoadErrorDialog(DataFile),
+        RestoreBackupForm(Vec<(DataFile, PathBuf)>),
+        SelectTagForm(Vec<(usize, Tag, usize)>),
+        TagForm {
+            key: Identity,
+            tag: Tag,
+            persona_list: Vec<Identity>,
+        },
+        RemoveTagDialog(Tag),
+        LetterForm {
+            key: Identity,
+            letter: Letter,
+        },
+        SelectLetterForm(Vec<Letter>),
+        SearchForm(SearchIndex),
+        SelectTemplateForm(Vec<Template>),
+        TemplateForm {
+            key: Identity,
+            template: Template,
+        },
+        RecoverDraftsDialog(Vec<Draft>),
+        SendLetterForm {
+            letter: Letter,
+            people: Vec<Persona>,
+            profiles: Vec<String>,
+            to: Vec<Identity>,
+        },
+        SelectPersonaForm(Vec<Persona>),
+        EditPersonaForm {
+            key: Identity,
+            persona: Persona,
+        },
+        RemovePersonaDialog(Vec<Persona>),
+        ReportForm(ContactReport),
+        ScheduleForm(Vec<Letter>),
+        OverdueDialog(Vec<Letter>),
+        SendProgressDialog(SendProgress),
+        SendProgress(SendProgress),
+        TrashForm(Vec<TrashItem>),
+        Info(String),
+    }
+
+    /// Records every request instead of drawing; clones share the record.
+    #[derive(Debug, Clone, Default)]
+    pub struct RecordingPresenter {
+        presented: Rc<RefCell<Vec<Presented>>>,
+    }
+
+    impl RecordingPresenter {
+        pub fn take(&self) -> Vec<Presented> {
+            self.presented.borrow_mut().drain(..).collect()
+        }
+
+        fn record(&mut self, presented: Presented) {
+            self.presented.borrow_mut().push(presented);
+        }
+    }
+
+    impl Presenter for RecordingPresenter {
+        fn step_next(&mut self) {}
+
+        fn settings_form(&mut self, _settings: Settings) {
+            self.record(Presented::SettingsForm);
+        }
+
+        fn unlock_secrets_dialog(&mut self, is_new: bool) {
+            self.record(Presented::UnlockSecretsDialog { is_new });
+        }
+
+        fn read_only_dialog(&mut self, _info: &str) {
+            self.record(Presented::ReadOnlyDialog);
+        }
+
+        fn load_error_dialog(&mut self, file: DataFile, _info: &str, _backup: Option<PathBuf>) {
+            self.record(Presented::LoadErrorDialog(file));
+        }
+
+        fn restore_backup_form(&mut self, backups: Vec<(DataFile, PathBuf)>) {
+            self.record(Presented::RestoreBackupForm(backups));
+        }
+
+        fn select_tag_form(&mut self, tree: Vec<(usize, Tag, usize)>) {
+            self.record(Presented::SelectTagForm(tree));
+        }
+
+        fn tag_form(
+            &mut self,
+            key: Identity,
+            tag: Tag,
+            persona_list: &[Identity],
+            _tags: Vec<Tag>,
+        ) {
+            self.record(Presented::TagForm {
+                key,
+                tag,
+                persona_list: persona_list.to_vec(),
+            });
+        }
+
+        fn remove_tag_dialog(&mut self, tag: Tag) {
+            self.record(Presented::RemoveTagDialog(tag));
+        }
+
+        fn letter_form(&mut self, key: Identity, letter: Letter) {
+            self.record(Presented::LetterForm { key, letter });
+        }
+
+        fn select_template_form(&mut self, templates: Vec<Template>) {
+            self.record(Presented::SelectTemplateForm(templates));
+        }
+
+        fn template_form(&mut self, key: Identity, template: Template, _tags: Vec<Identity>) {
+            self.record(Presented::TemplateForm { key, template });
+        }
+
+        fn recover_drafts_dialog(&mut self, drafts: Vec<Draft>) {
+            self.record(Presented::RecoverDraftsDialog(drafts));
+        }
+
+        fn send_letter_form(
+            &mut self,
+            letter: Letter,
+            people: Vec<Persona>,
+            _tags: Vec<Tag>,
+            profiles: Vec<String>,
+            _default_profile: String,
+            to: Vec<Identity>,
+        ) {
+            self.record(Presented::SendLetterForm {
+                letter,
+                people,
+                profiles,
+                to,
+            });
+        }
+
+        fn select_persona_form(&mut self, persona: Vec<Persona>, _tags: Vec<Tag>) {
+            self.record(Presented::SelectPersonaForm(persona));
+        }
+
+        fn edit_persona_form(&mut self, key: Identity, persona: Persona, _tags: Vec<(usize, Tag)>) {
+            self.record(Presented::EditPersonaForm { key, persona });
+        }
+
+        fn remove_persona_dialog(&mut self, persona: Vec<Persona>) {
+            self.record(Presented::RemovePersonaDialog(persona));
+        }
+
+        fn select_letter_form(&mut self, letters: Vec<Letter>, _filter: LetterFilter) {
+            self.record(Presented::SelectLetterForm(letters));
+        }
+
+        fn search_form(&mut self, index: SearchIndex) {
+            self.record(Presented::SearchForm(index));
+        }
+
+        fn report_form(&mut self, report: ContactReport) {
+            self.record(Presented::ReportForm(report));
+        }
+
+        fn schedule_form(&mut self, letters: Vec<Letter>) {
+            self.record(Presented::ScheduleForm(letters));
+        }
+
+        fn overdue_dialog(&mut self, letters: Vec<Letter>) {
+            self.record(Presented::OverdueDialog(letters));
+        }
+
+        fn send_progress_dialog(&mut self, progress: &SendProgress) {
+            self.record(Presented::SendProgressDialog(progress.clone()));
+        }
+
+        fn send_progress(&mut self, progress: &SendProgress) {
+            self.record(Presented::SendProgress(progress.clone()));
+        }
+
+        fn trash_form(&mut self, items: Vec<TrashItem>) {
+            self.record(Presented::TrashForm(items));
+        }
+
+        fn present_info(&mut self, info: &str) {
+            self.record(Presented::Info(info.to_owned()));
+        }
+    }
+}
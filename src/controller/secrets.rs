@@ -0,0 +1,225 @@
+use std::{
+    collections::BTreeMap,
+    fmt, fs, io,
+    path::{Path, PathBuf},
+};
+
+use argon2::Argon2;
+use chacha20poly1305::{
+    aead::{rand_core::RngCore, Aead, AeadCore, KeyInit, OsRng},
+    ChaCha20Poly1305, Key, Nonce,
+};
+use serde::{Deserialize, Serialize};
+
+use super::settings::config_dir;
+use crate::data_handler::backup::write_atomically;
+
+pub const SECRETS_FILE: &str = "secrets.enc";
+const SALT_LEN: usize = 16;
+/// ChaCha20-Poly1305 nonces are 96 bits.
+const NONCE_LEN: usize = 12;
+
+pub fn secrets_path() -> PathBuf {
+    config_dir().join(SECRETS_FILE)
+}
+
+/// Secrets (SMTP passwords) sealed with a key derived from the master passphrase.
+/// A session store is never written to disk.
+pub struct SecretStore {
+    path: Option<PathBuf>,
+    salt: Vec<u8>,
+    key: Key,
+    secrets: BTreeMap<String, String>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct SealedSecrets {
+    salt: Vec<u8>,
+    nonce: Vec<u8>,
+    ciphertext: Vec<u8>,
+}
+
+#[derive(Debug)]
+pub enum SecretError {
+    WrongPassphrase,
+    EmptyPassphrase,
+    Crypto,
+    Io(io::Error),
+    Format(serde_json::Error),
+}
+
+impl fmt::Display for SecretError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SecretError::WrongPassphrase => write!(f, "неверный мастер-пароль"),
+            SecretError::EmptyPassphrase => write!(f, "мастер-пароль не может быть пустым"),
+            SecretError::Crypto => write!(f, "ошибка шифрования"),
+            SecretError::Io(e) => write!(f, "ошибка ввода-вывода: {}", e),
+            SecretError::Format(e) => write!(f, "повреждённое хранилище: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for SecretError {}
+
+impl SecretStore {
+    pub fn session() -> Self {
+        Self {
+            path: None,
+            salt: vec![],
+            key: Key::default(),
+            secrets: BTreeMap::new(),
+        }
+    }
+
+    pub fn exists(path: impl AsRef<Path>) -> bool {
+        path.as_ref().exists()
+    }
+
+    /// Opens the store at `path`, or starts a new one sealed with `passphrase`
+    /// if there is no file yet.
+    pub fn unlock(path: impl AsRef<Path>, passphrase: &str) -> Result<Self, SecretError> {
+        if passphrase.is_empty() {
+            return Err(SecretError::EmptyPassphrase);
+        }
+        let path = path.as_ref().to_path_buf();
+        match fs::read(&path) {
+            Ok(bytes) => {
+                let sealed: SealedSecrets =
+                    serde_json::from_slice(&bytes).map_err(SecretError::Format)?;
+                if sealed.nonce.len() != NONCE_LEN {
+                    return Err(SecretError::Crypto);
+                }
+                let key = derive_key(passphrase, &sealed.salt)?;
+                let plaintext = ChaCha20Poly1305::new(&key)
+                    .decrypt(Nonce::from_slice(&sealed.nonce), sealed.ciphertext.as_ref())
+                    .map_err(|_| SecretError::WrongPassphrase)?;
+                let secrets = serde_json::from_slice(&plaintext).map_err(SecretError::Format)?;
+                Ok(Self {
+                    path: Some(path),
+                    salt: sealed.salt,
+                    key,
+                    secrets,
+                })
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                let mut salt = vec![0; SALT_LEN];
+                OsRng.fill_bytes(&mut salt);
+                let key = derive_key(passphrase, &salt)?;
+                Ok(Self {
+                    path: Some(path),
+                    salt,
+                    key,
+                    secrets: BTreeMap::new(),
+                })
+            }
+            Err(e) => Err(SecretError::Io(e)),
+        }
+    }
+
+    pub fn is_persistent(&self) -> bool {
+        self.path.is_some()
+    }
+
+    pub fn get(&self, name: impl AsRef<str>) -> Option<&str> {
+        self.secrets
+            .get(name.as_ref())
+            .map(|secret| secret.as_str())
+    }
+
+    /// An empty secret removes the entry.
+    pub fn set(&mut self, name: impl ToString, secret: impl ToString) {
+        let secret = secret.to_string();
+        if secret.is_empty() {
+            self.secrets.remove(&name.to_string());
+        } else {
+            self.secrets.insert(name.to_string(), secret);
+        }
+    }
+
+    pub fn retain(&mut self, mut keep: impl FnMut(&str) -> bool) {
+        self.secrets.retain(|name, _| keep(name));
+    }
+
+    pub fn save(&self) -> Result<(), SecretError> {
+        let path = match self.path {
+            Some(ref path) => path,
+            None => return Ok(()),
+        };
+        let plaintext = serde_json::to_vec(&self.secrets).map_err(SecretError::Format)?;
+        let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let ciphertext = ChaCha20Poly1305::new(&self.key)
+            .encrypt(&nonce, plaintext.as_ref())
+            .map_err(|_| SecretError::Crypto)?;
+        let sealed = SealedSecrets {
+            salt: self.salt.clone(),
+            nonce: nonce.to_vec(),
+            ciphertext,
+        };
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).map_err(SecretError::Io)?;
+        }
+        // The store is the only copy of the passwords: never leave it half written.
+        let json = serde_json::to_string(&sealed).map_err(SecretError::Format)?;
+        write_atomically(path, &json).map_err(SecretError::Io)
+    }
+}
+
+impl fmt::Debug for SecretStore {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SecretStore")
+            .field("path", &self.path)
+            .field("secrets", &self.secrets.keys().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+fn derive_key(passphrase: &str, salt: &[u8]) -> Result<Key, SecretError> {
+    let mut key = Key::default();
+    Argon2::default()
+        .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+        .map_err(|_| SecretError::Crypto)?;
+    Ok(key)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::TempDir;
+
+    #[test]
+    fn test_secrets_roundtrip() {
+        let dir = TempDir::new("secrets");
+        let path = dir.join(SECRETS_FILE);
+        let mut store = SecretStore::unlock(&path, "master").unwrap();
+        store.set("smtp_password:Основной", "qwerty");
+        store.save().unwrap();
+        let sealed = fs::read_to_string(&path).unwrap();
+        assert!(!sealed.contains("qwerty"));
+
+        let store = SecretStore::unlock(&path, "master").unwrap();
+        assert_eq!(store.get("smtp_password:Основной"), Some("qwerty"));
+        assert!(matches!(
+            SecretStore::unlock(&path, "wrong"),
+            Err(SecretError::WrongPassphrase)
+        ));
+    }
+
+    #[test]
+    fn test_corrupt_nonce_is_an_error() {
+        let dir = TempDir::new("secrets");
+        let path = dir.join(SECRETS_FILE);
+        SecretStore::unlock(&path, "master")
+            .unwrap()
+            .save()
+            .unwrap();
+        let mut sealed: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        sealed["nonce"] = serde_json::json!([1, 2, 3]);
+        fs::write(&path, sealed.to_string()).unwrap();
+        assert!(matches!(
+            SecretStore::unlock(&path, "master"),
+            Err(SecretError::Crypto)
+        ));
+    }
+}
@@ -1,18 +1,101 @@
-use std::{cell::RefCell, env, fs, rc::Rc};
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    env, fmt, fs, io,
+    path::{Path, PathBuf},
+    rc::Rc,
+};
+
+use serde::{Deserialize, Serialize};
+
+use super::secrets::{SecretStore, SECRETS_FILE};
+use crate::data_handler::{backup::write_atomically, storage::StorageKind};
 
 pub type Settings = Rc<RefCell<SettingsRepr>>;
 
-#[derive(Debug)]
+pub const SETTINGS_VERSION: u32 = 1;
+const SETTINGS_FILE: &str = "settings.json";
+/// Older versions kept their settings in the current directory.
+const LEGACY_DIR: &str = ".";
+const LEGACY_ENV_PATH: &str = ".env";
+const LEGACY_PROFILES_PATH: &str = "profiles.json";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct SettingsRepr {
+    pub profiles: Vec<SenderProfile>,
+    pub default_profile: String,
+    pub single_greet: String,
+    pub bcc_threshold: usize,
+    /// How the data files are kept; `letcom storage convert` switches it.
+    pub storage: StorageKind,
+    #[serde(skip)]
+    plaintext_passwords: bool,
+    /// Values taken from the environment at startup; they hold for this run
+    /// only and are never saved.
+    #[serde(skip)]
+    overrides: HashMap<String, String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SenderProfile {
+    pub name: String,
+    pub letter_from: String,
+    pub reply_to: String,
     pub smtp_relay: String,
     pub smtp_user: String,
+    /// Read from old plaintext files for migration, never written back.
+    #[serde(skip_serializing)]
     pub smtp_password: String,
-    pub letter_from: String,
     pub plural_title: String,
-    pub single_greet: String,
     pub letter_signature: String,
+    pub limits: SendLimits,
+}
+
+/// How much mail the account's relay takes; zero is no limit.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SendLimits {
+    pub messages_per_minute: usize,
+    /// Recipients are split into messages of at most this many addresses,
+    /// the visible ones apart from the Bcc.
+    pub recipients_per_message: usize,
+    /// Seconds to wait at least between two batches of `messages_per_minute`.
+    pub batch_pause: u64,
 }
 
+/// On-disk layout of `settings.json`: the settings tagged with the schema version.
+#[derive(Serialize, Deserialize)]
+struct SettingsFile<S> {
+    version: u32,
+    #[serde(flatten)]
+    settings: S,
+}
+
+#[derive(Debug)]
+pub enum SettingsError {
+    Io(io::Error),
+    Format(serde_json::Error),
+    UnsupportedVersion(u32),
+}
+
+impl fmt::Display for SettingsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SettingsError::Io(e) => write!(f, "ошибка ввода-вывода: {}", e),
+            SettingsError::Format(e) => write!(f, "повреждённый файл настроек: {}", e),
+            SettingsError::UnsupportedVersion(version) => write!(
+                f,
+                "версия файла настроек {} новее поддерживаемой {}",
+                version, SETTINGS_VERSION
+            ),
+        }
+    }
+}
+
+impl std::error::Error for SettingsError {}
+
 impl SettingsRepr {
     const SMTP_RELAY: &str = "SMTP_RELAY";
     const SMTP_USER: &str = "SMTP_USER";
@@ -21,55 +104,455 @@ impl SettingsRepr {
     const PLURAL_TITLE: &str = "PLURAL_TITLE";
     const SINGLE_GREET: &str = "SINGLE_GREET";
     const LETTER_SIGNATURE: &str = "LETTER_SIGNATURE";
+    const BCC_THRESHOLD: &str = "BCC_THRESHOLD";
+    const DEFAULT_PROFILE: &str = "DEFAULT_PROFILE";
+    const OVERRIDABLE: [&str; 8] = [
+        Self::DEFAULT_PROFILE,
+        Self::SINGLE_GREET,
+        Self::BCC_THRESHOLD,
+        Self::SMTP_RELAY,
+        Self::SMTP_USER,
+        Self::LETTER_FROM,
+        Self::PLURAL_TITLE,
+        Self::LETTER_SIGNATURE,
+    ];
 
-    pub fn save(&self) {
-        let mut settings = vec![];
-        settings.push(format!("{}=\"{}\"", Self::SMTP_RELAY, self.smtp_relay));
-        settings.push(format!("{}=\"{}\"", Self::SMTP_USER, self.smtp_user));
-        settings.push(format!(
-            "{}=\"{}\"",
-            Self::SMTP_PASSWORD,
-            self.smtp_password
-        ));
-        settings.push(format!("{}=\"{}\"", Self::LETTER_FROM, self.letter_from));
-        settings.push(format!("{}=\"{}\"", Self::PLURAL_TITLE, self.plural_title));
-        settings.push(format!("{}=\"{}\"", Self::SINGLE_GREET, self.single_greet));
-        settings.push(format!(
-            "{}=\"{}\"",
-            Self::LETTER_SIGNATURE,
-            self.letter_signature
-        ));
-        fs::write(".env", settings.join("\n")).unwrap();
+    pub fn save(&self) -> io::Result<()> {
+        self.save_to(settings_path())
+    }
+
+    pub fn save_to(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        if let Some(dir) = path.as_ref().parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let file = SettingsFile {
+            version: SETTINGS_VERSION,
+            settings: self,
+        };
+        let json = serde_json::to_string_pretty(&file)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        fs::write(path, json)
+    }
+
+    /// The settings in force: the stored ones with the environment overrides
+    /// applied. Edit and save the stored ones.
+    pub fn effective(&self) -> SettingsRepr {
+        let mut result = self.clone();
+        apply_overrides(&mut result, |key| self.overrides.get(key).cloned());
+        result
+    }
+
+    pub fn profile_names(&self) -> Vec<String> {
+        self.profiles.iter().map(|p| p.name.to_owned()).collect()
+    }
+
+    pub fn get_profile(&self, name: impl AsRef<str>) -> Option<&SenderProfile> {
+        self.profiles.iter().find(|p| p.name == name.as_ref())
+    }
+
+    /// The profile chosen as default, or the first one if the default has gone.
+    pub fn get_default_profile(&self) -> Option<&SenderProfile> {
+        self.get_profile(&self.default_profile)
+            .or_else(|| self.profiles.first())
+    }
+
+    /// Replaces the profile formerly named `key` (or appends a new one),
+    /// keeping the default pointing at it after a rename.
+    pub fn update_profile(&mut self, key: impl AsRef<str>, profile: SenderProfile) {
+        let key = key.as_ref();
+        if self.default_profile == key {
+            self.default_profile = profile.name.to_owned();
+        }
+        match self.profiles.iter_mut().find(|p| p.name == key) {
+            Some(old) => *old = profile,
+            None => self.profiles.push(profile),
+        }
+    }
+
+    pub fn remove_profile(&mut self, name: impl AsRef<str>) {
+        self.profiles.retain(|p| p.name != name.as_ref());
+    }
+
+    /// Whether a password was found in `.env` or `profiles.json` at load time.
+    pub fn has_plaintext_passwords(&self) -> bool {
+        self.plaintext_passwords
+    }
+
+    /// Called once the plaintext passwords are safe in the secret store.
+    pub fn forget_plaintext_passwords(&mut self) {
+        self.plaintext_passwords = false;
+    }
+
+    pub fn fill_passwords(&mut self, secrets: &SecretStore) {
+        for profile in self.profiles.iter_mut() {
+            if let Some(password) = secrets.get(profile.password_secret_name()) {
+                profile.smtp_password = password.to_owned();
+            }
+        }
+    }
+
+    pub fn store_passwords(&self, secrets: &mut SecretStore) {
+        for profile in self.profiles.iter() {
+            secrets.set(profile.password_secret_name(), &profile.smtp_password);
+        }
+        let names = self
+            .profiles
+            .iter()
+            .map(|profile| profile.password_secret_name())
+            .collect::<Vec<_>>();
+        secrets.retain(|name| names.iter().any(|n| n == name));
     }
 }
 
 impl Default for SettingsRepr {
+    fn default() -> Self {
+        let profile = SenderProfile::default();
+        Self {
+            default_profile: profile.name.to_owned(),
+            profiles: vec![profile],
+            single_greet: Default::default(),
+            bcc_threshold: 5,
+            storage: StorageKind::default(),
+            plaintext_passwords: false,
+            overrides: HashMap::new(),
+        }
+    }
+}
+
+impl SenderProfile {
+    pub fn new(name: impl ToString) -> Self {
+        Self {
+            name: name.to_string(),
+            ..Default::default()
+        }
+    }
+
+    pub fn password_secret_name(&self) -> String {
+        format!("smtp_password:{}", self.name)
+    }
+}
+
+impl Default for SenderProfile {
     fn default() -> Self {
         Self {
+            name: "Основной".into(),
+            letter_from: "john@dow.com".parse().unwrap(),
+            reply_to: Default::default(),
             smtp_relay: Default::default(),
             smtp_user: Default::default(),
             smtp_password: Default::default(),
-            letter_from: "john@dow.com".parse().unwrap(),
             plural_title: Default::default(),
-            single_greet: Default::default(),
             letter_signature: Default::default(),
+            limits: Default::default(),
         }
     }
 }
 
-pub fn load_settings() -> Settings {
-    dotenv::dotenv().ok();
-    let result = SettingsRepr {
-        smtp_relay: env::var(SettingsRepr::SMTP_RELAY).unwrap_or_else(|_| "post.mipt.ru".into()),
-        smtp_user: env::var(SettingsRepr::SMTP_USER).unwrap_or_default(),
-        smtp_password: env::var(SettingsRepr::SMTP_PASSWORD).unwrap_or_default(),
-        letter_from: env::var(SettingsRepr::LETTER_FROM)
-            .unwrap_or_else(|_| "kalashnikov.ad@mipt.ru".into()),
-        plural_title: env::var(SettingsRepr::PLURAL_TITLE)
-            .unwrap_or_else(|_| "Уважаемые коллеги!".into()),
-        single_greet: env::var(SettingsRepr::SINGLE_GREET).unwrap_or_else(|_| "".into()),
-        letter_signature: env::var(SettingsRepr::LETTER_SIGNATURE)
-            .unwrap_or_else(|_| "С уважением,\nАлександр Калашников.".into()),
+/// `$XDG_CONFIG_HOME/letcom`, falling back to `~/.config/letcom`.
+pub fn config_dir() -> PathBuf {
+    env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+        .unwrap_or_else(|| PathBuf::from("."))
+        .join("letcom")
+}
+
+pub fn settings_path() -> PathBuf {
+    config_dir().join(SETTINGS_FILE)
+}
+
+/// Reads `settings.json` from the config directory, importing the legacy `.env`
+/// from the current directory when there is none yet. Environment variables
+/// named like the old `.env` keys override the stored values in `effective`.
+pub fn load_settings() -> Result<Settings, SettingsError> {
+    let mut result = read_settings(&settings_path(), Path::new(LEGACY_DIR))?;
+    result.overrides = SettingsRepr::OVERRIDABLE
+        .into_iter()
+        .filter_map(|key| env::var(key).ok().map(|value| (key.to_owned(), value)))
+        .collect();
+    Ok(Rc::new(RefCell::new(result)))
+}
+
+fn read_settings(path: &Path, legacy_dir: &Path) -> Result<SettingsRepr, SettingsError> {
+    let config_dir = path.parent().unwrap_or(Path::new("."));
+    let mut result = match fs::read_to_string(path) {
+        Ok(json) => parse_settings(&json)?,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => import_legacy(legacy_dir, config_dir),
+        Err(e) => return Err(SettingsError::Io(e)),
     };
-    Rc::new(RefCell::new(result))
+    result.plaintext_passwords = result
+        .profiles
+        .iter()
+        .any(|profile| !profile.smtp_password.is_empty());
+    if result.profiles.is_empty() {
+        result.profiles.push(SenderProfile::default());
+    }
+    Ok(result)
+}
+
+fn parse_settings(json: &str) -> Result<SettingsRepr, SettingsError> {
+    let file: SettingsFile<serde_json::Value> =
+        serde_json::from_str(json).map_err(SettingsError::Format)?;
+    if file.version > SETTINGS_VERSION {
+        return Err(SettingsError::UnsupportedVersion(file.version));
+    }
+    serde_json::from_value(file.settings).map_err(SettingsError::Format)
+}
+
+/// Builds settings from the `.env` and `profiles.json` kept in `legacy_dir` by older
+/// versions, and moves the old secret store into `config_dir`. The plaintext
+/// passwords are removed from the old files by `scrub_legacy_passwords` once
+/// they are in the secret store.
+fn import_legacy(legacy_dir: &Path, config_dir: &Path) -> SettingsRepr {
+    // The iterator reads `.env` without exporting it into the process environment,
+    // which would turn every legacy value into an override.
+    #[allow(deprecated)]
+    let legacy_env = dotenv::from_path_iter(legacy_dir.join(LEGACY_ENV_PATH))
+        .map(|iter| iter.filter_map(|item| item.ok()).collect::<HashMap<_, _>>())
+        .unwrap_or_default();
+    let lookup = |key: &str| legacy_env.get(key).cloned();
+    let profiles = fs::read_to_string(legacy_dir.join(LEGACY_PROFILES_PATH))
+        .ok()
+        .and_then(|json| serde_json::from_str::<Vec<SenderProfile>>(&json).ok())
+        .filter(|profiles| !profiles.is_empty())
+        .unwrap_or_else(|| vec![legacy_profile(lookup)]);
+    let mut result = SettingsRepr {
+        default_profile: profiles[0].name.to_owned(),
+        profiles,
+        ..Default::default()
+    };
+    apply_overrides(&mut result, lookup);
+    let legacy_secrets = legacy_dir.join(SECRETS_FILE);
+    let secrets = config_dir.join(SECRETS_FILE);
+    if legacy_secrets.exists() && !secrets.exists() {
+        fs::create_dir_all(config_dir)
+            .and_then(|_| fs::copy(&legacy_secrets, &secrets))
+            .and_then(|_| fs::remove_file(&legacy_secrets))
+            .ok();
+    }
+    result
+}
+
+/// Removes `SMTP_PASSWORD` from the legacy `.env` and the passwords from the
+/// legacy `profiles.json`, leaving the rest of both files alone.
+pub fn scrub_legacy_passwords() -> io::Result<()> {
+    scrub_legacy_dir(Path::new(LEGACY_DIR))
+}
+
+fn scrub_legacy_dir(legacy_dir: &Path) -> io::Result<()> {
+    let env_path = legacy_dir.join(LEGACY_ENV_PATH);
+    if let Some(text) = read_legacy(&env_path)? {
+        let is_password = |line: &&str| {
+            let line = line.trim_start();
+            let line = line.strip_prefix("export ").unwrap_or(line);
+            line.split('=').next().map(str::trim) == Some(SettingsRepr::SMTP_PASSWORD)
+        };
+        if text.lines().any(|line| is_password(&line)) {
+            let mut kept = text
+                .lines()
+                .filter(|line| !is_password(line))
+                .collect::<Vec<_>>()
+                .join("\n");
+            kept.push('\n');
+            write_atomically(&env_path, &kept)?;
+        }
+    }
+    let profiles_path = legacy_dir.join(LEGACY_PROFILES_PATH);
+    if let Some(json) = read_legacy(&profiles_path)? {
+        let invalid = |e| io::Error::new(io::ErrorKind::InvalidData, e);
+        let mut profiles: serde_json::Value = serde_json::from_str(&json).map_err(invalid)?;
+        let mut removed = false;
+        for profile in profiles.as_array_mut().into_iter().flatten() {
+            if let Some(profile) = profile.as_object_mut() {
+                removed |= profile.remove("smtp_password").is_some();
+            }
+        }
+        if removed {
+            let json = serde_json::to_string_pretty(&profiles).map_err(invalid)?;
+            write_atomically(&profiles_path, &json)?;
+        }
+    }
+    Ok(())
+}
+
+fn read_legacy(path: &Path) -> io::Result<Option<String>> {
+    match fs::read_to_string(path) {
+        Ok(text) => Ok(Some(text)),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// Builds the single profile from the variables `.env` held before profiles appeared.
+fn legacy_profile(lookup: impl Fn(&str) -> Option<String>) -> SenderProfile {
+    SenderProfile {
+        smtp_relay: lookup(SettingsRepr::SMTP_RELAY).unwrap_or_else(|| "post.mipt.ru".into()),
+        smtp_user: lookup(SettingsRepr::SMTP_USER).unwrap_or_default(),
+        smtp_password: lookup(SettingsRepr::SMTP_PASSWORD).unwrap_or_default(),
+        letter_from: lookup(SettingsRepr::LETTER_FROM)
+            .unwrap_or_else(|| "kalashnikov.ad@mipt.ru".into()),
+        plural_title: lookup(SettingsRepr::PLURAL_TITLE)
+            .unwrap_or_else(|| "Уважаемые коллеги!".into()),
+        letter_signature: lookup(SettingsRepr::LETTER_SIGNATURE)
+            .unwrap_or_else(|| "С уважением,\nАлександр Калашников.".into()),
+        ..Default::default()
+    }
+}
+
+/// Global values and the default profile's sender fields may be overridden;
+/// the SMTP password comes from the secret store only.
+fn apply_overrides(settings: &mut SettingsRepr, lookup: impl Fn(&str) -> Option<String>) {
+    if let Some(name) = lookup(SettingsRepr::DEFAULT_PROFILE) {
+        settings.default_profile = name;
+    }
+    if let Some(greet) = lookup(SettingsRepr::SINGLE_GREET) {
+        settings.single_greet = greet;
+    }
+    if let Some(threshold) =
+        lookup(SettingsRepr::BCC_THRESHOLD).and_then(|threshold| threshold.parse().ok())
+    {
+        settings.bcc_threshold = threshold;
+    }
+    let default_profile = settings.default_profile.to_owned();
+    if let Some(profile) = settings
+        .profiles
+        .iter_mut()
+        .find(|profile| profile.name == default_profile)
+    {
+        let fields = [
+            (SettingsRepr::SMTP_RELAY, &mut profile.smtp_relay),
+            (SettingsRepr::SMTP_USER, &mut profile.smtp_user),
+            (SettingsRepr::LETTER_FROM, &mut profile.letter_from),
+            (SettingsRepr::PLURAL_TITLE, &mut profile.plural_title),
+            (
+                SettingsRepr::LETTER_SIGNATURE,
+                &mut profile.letter_signature,
+            ),
+        ];
+        for (key, field) in fields {
+            if let Some(value) = lookup(key) {
+                *field = value;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::TempDir;
+
+    #[test]
+    fn test_rename_default_profile() {
+        let mut settings = SettingsRepr::default();
+        let old_name = settings.default_profile.to_owned();
+        settings.update_profile(&old_name, SenderProfile::new("Кафедра"));
+        assert_eq!(settings.default_profile, "Кафедра");
+        assert_eq!(settings.profile_names(), vec!["Кафедра".to_string()]);
+    }
+
+    #[test]
+    fn test_default_profile_falls_back_to_first() {
+        let mut settings = SettingsRepr::default();
+        settings.update_profile("Личный", SenderProfile::new("Личный"));
+        let old_default = settings.default_profile.to_owned();
+        settings.remove_profile(old_default);
+        assert_eq!(settings.get_default_profile().unwrap().name, "Личный");
+    }
+
+    #[test]
+    fn test_password_is_not_serialized() {
+        let mut profile = SenderProfile::new("Личный");
+        profile.smtp_password = "qwerty".into();
+        let json = serde_json::to_string(&profile).unwrap();
+        assert!(!json.contains("qwerty"));
+        let legacy = json.replacen('{', "{\"smtp_password\":\"qwerty\",", 1);
+        let restored: SenderProfile = serde_json::from_str(&legacy).unwrap();
+        assert_eq!(restored.smtp_password, "qwerty");
+    }
+
+    #[test]
+    fn test_settings_roundtrip_with_quotes() {
+        let dir = TempDir::new("settings");
+        let path = dir.join(SETTINGS_FILE);
+        let mut settings = SettingsRepr::default();
+        settings.profiles[0].letter_signature = "С уважением,\n\"Кафедра\"".into();
+        settings.save_to(&path).unwrap();
+        let restored = read_settings(&path, &dir).unwrap();
+        assert_eq!(restored.profiles, settings.profiles);
+    }
+
+    #[test]
+    fn test_newer_version_is_rejected() {
+        let json = format!("{{\"version\": {}}}", SETTINGS_VERSION + 1);
+        assert!(matches!(
+            parse_settings(&json),
+            Err(SettingsError::UnsupportedVersion(_))
+        ));
+    }
+
+    #[test]
+    fn test_import_legacy_env() {
+        let legacy_dir = TempDir::new("settings");
+        let config_dir = TempDir::new("settings");
+        fs::write(
+            legacy_dir.join(LEGACY_ENV_PATH),
+            "SMTP_USER=\"kalashnikov\"\nSMTP_PASSWORD=\"secret\"\nBCC_THRESHOLD=\"7\"",
+        )
+        .unwrap();
+        fs::write(legacy_dir.join(SECRETS_FILE), "{}").unwrap();
+        let settings = read_settings(&config_dir.join(SETTINGS_FILE), &legacy_dir).unwrap();
+        assert_eq!(settings.bcc_threshold, 7);
+        assert_eq!(settings.profiles[0].smtp_user, "kalashnikov");
+        assert!(settings.has_plaintext_passwords());
+        assert!(config_dir.join(SECRETS_FILE).exists());
+        assert!(!legacy_dir.join(SECRETS_FILE).exists());
+    }
+
+    #[test]
+    fn test_scrub_legacy_passwords() {
+        let dir = TempDir::new("settings");
+        fs::write(
+            dir.join(LEGACY_ENV_PATH),
+            "SMTP_USER=\"kalashnikov\"\nexport SMTP_PASSWORD=\"secret\"\n",
+        )
+        .unwrap();
+        fs::write(
+            dir.join(LEGACY_PROFILES_PATH),
+            r#"[{"name": "Кафедра", "smtp_user": "kafedra", "smtp_password": "qwerty"}]"#,
+        )
+        .unwrap();
+        scrub_legacy_dir(&dir).unwrap();
+        let env = fs::read_to_string(dir.join(LEGACY_ENV_PATH)).unwrap();
+        assert_eq!(env, "SMTP_USER=\"kalashnikov\"\n");
+        let profiles = fs::read_to_string(dir.join(LEGACY_PROFILES_PATH)).unwrap();
+        assert!(!profiles.contains("qwerty"));
+        assert!(profiles.contains("kafedra"));
+    }
+
+    #[test]
+    fn test_overrides_are_not_saved() {
+        let dir = TempDir::new("settings");
+        let path = dir.join(SETTINGS_FILE);
+        let mut settings = SettingsRepr::default();
+        settings.update_profile("Личный", SenderProfile::new("Личный"));
+        settings.overrides = HashMap::from([
+            ("DEFAULT_PROFILE".into(), "Личный".into()),
+            ("SMTP_RELAY".into(), "smtp.example.org".into()),
+        ]);
+        let effective = settings.effective();
+        assert_eq!(
+            effective.get_default_profile().unwrap().smtp_relay,
+            "smtp.example.org"
+        );
+        assert!(effective.profiles[0].smtp_relay.is_empty());
+
+        settings.save_to(&path).unwrap();
+        let restored = read_settings(&path, &dir).unwrap();
+        assert_eq!(restored.default_profile, "Основной");
+        assert!(!fs::read_to_string(&path)
+            .unwrap()
+            .contains("smtp.example.org"));
+    }
 }
@@ -1,4 +1,13 @@
-use crate::data_handler::{letter::Letter, persona::Persona, tag::Tag, Identity};
+use super::mailer::Recipients;
+use crate::data_handler::{
+    drafts::Draft,
+    handler::DataFile,
+    letter::{DateTime, Letter, LetterFilter},
+    persona::Persona,
+    tag::Tag,
+    template::Template,
+    Identity,
+};
 
 #[derive(Debug, Clone)]
 pub enum ControllerSignal {
@@ -6,22 +15,106 @@ pub enum ControllerSignal {
     Log(String),
     OpenSettings,
     SaveSettings,
+    UnlockSecrets(Option<String>),
+    RetryLoad(DataFile),
+    LoadBackup {
+        file: DataFile,
+        path: String,
+    },
+    StartEmpty(DataFile),
+    OpenBackups,
     NewTag,
     SelectTag,
     EditTag(Tag),
-    CompleteEditTag { key: Identity, tag: Tag },
+    CompleteEditTag {
+        key: Identity,
+        tag: Tag,
+    },
     RemoveTagAlert(Tag),
     RemoveTag(Tag),
     NewLetter,
     EditLetter(Letter),
-    CompleteEditLetter { key: Identity, letter: Letter },
+    CompleteEditLetter {
+        key: Identity,
+        letter: Letter,
+    },
+    SaveDraft(Draft),
+    DiscardDraft(Identity),
+    /// The letter form was closed without saving the letter.
+    CancelEditLetter(Identity),
+    RecoverDrafts,
+    DiscardDrafts,
+    RemoveLetter(Letter),
+    /// Lists the letters passing the filter.
+    SelectLetter(LetterFilter),
+    SearchLetters,
+    SelectTemplate,
+    NewLetterFromTemplate(Template),
+    SaveAsTemplate(Letter),
+    EditTemplate(Template),
+    CompleteEditTemplate {
+        key: Identity,
+        template: Template,
+    },
+    RemoveTemplate(Template),
     OpenLetterToSend(Letter),
-    SendEmail { letter: Letter, to: Vec<String> },
+    SendEmail {
+        letter: Letter,
+        recipients: Recipients,
+        profile: String,
+    },
+    CancelSending,
+    /// Sends the letter at `at` instead of now.
+    ScheduleEmail {
+        letter: Letter,
+        recipients: Recipients,
+        profile: String,
+        at: DateTime,
+    },
+    OpenSchedule,
+    Reschedule {
+        letter: Letter,
+        at: DateTime,
+    },
+    Unschedule(Letter),
+    /// Answers about the mailings that fell due while the program was closed.
+    SendOverdue,
+    CancelOverdue,
     ImportPersona(Vec<Persona>),
     SelectPersona,
     EditPersona(Persona),
-    CompleteEditPersona { key: Identity, persona: Persona },
-    RemovePersonaAlert(Persona),
-    RemovePersona(Persona),
+    CompleteEditPersona {
+        key: Identity,
+        persona: Persona,
+        /// Labels of the tags listing the persona.
+        tags: Vec<Identity>,
+    },
+    RemovePersonaAlert(Vec<Persona>),
+    RemovePersona(Vec<Persona>),
+    AddToTag {
+        label: Identity,
+        people: Vec<Identity>,
+    },
+    RemoveFromTag {
+        label: Identity,
+        people: Vec<Identity>,
+    },
+    ExportPersona(Vec<Persona>),
+    /// Starts a new letter that will be sent to these personas.
+    WriteLetterTo(Vec<Identity>),
+    OpenReport,
+    /// Writes the contact report to a CSV file.
+    ExportReport,
+    OpenTrash,
+    RestoreFromTrash {
+        file: DataFile,
+        index: usize,
+    },
+    PurgeFromTrash {
+        file: DataFile,
+        index: usize,
+    },
+    EmptyTrash,
+    Undo,
     Quit,
 }
@@ -0,0 +1,467 @@
+use std::{
+    fmt,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc, Arc,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use super::{
+    mailer::{Mailing, Outbox},
+    settings::SendLimits,
+};
+use crate::data_handler::letter::{Delivery, LetterRepr};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum JobStatus {
+    Pending,
+    Sending,
+    Sent,
+    Failed(String),
+    Cancelled,
+}
+
+impl fmt::Display for JobStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            JobStatus::Pending => write!(f, "ожидает"),
+            JobStatus::Sending => write!(f, "отправляется"),
+            JobStatus::Sent => write!(f, "отправлено"),
+            JobStatus::Failed(e) => write!(f, "ошибка: {}", e),
+            JobStatus::Cancelled => write!(f, "отменено"),
+        }
+    }
+}
+
+#[derive(Debug)]
+enum SendReport {
+    Status { index: usize, status: JobStatus },
+    Remaining(Duration),
+    Finished,
+}
+
+/// Keeps a mailing within the limits of the relay: after every
+/// `messages_per_minute` messages it waits for the rest of the minute, or
+/// for the batch pause if that is longer.
+#[derive(Debug)]
+struct Pacer {
+    limits: SendLimits,
+    minute: Duration,
+    batch_start: Instant,
+    in_batch: usize,
+    /// Time the relay took for the messages sent so far.
+    sending: Duration,
+    sent: u32,
+}
+
+impl Pacer {
+    /// A guess at how long a message takes before any was sent.
+    const FIRST_GUESS: Duration = Duration::from_secs(1);
+
+    fn new(limits: SendLimits, now: Instant) -> Self {
+        Self {
+            limits,
+            minute: Duration::from_secs(60),
+            batch_start: now,
+            in_batch: 0,
+            sending: Duration::ZERO,
+            sent: 0,
+        }
+    }
+
+    /// How long to wait before the next message, which is counted in the
+    /// batch it starts.
+    fn next(&mut self, now: Instant) -> Duration {
+        let per_minute = self.limits.messages_per_minute;
+        let mut wait = Duration::ZERO;
+        if per_minute > 0 && self.in_batch >= per_minute {
+            wait = self
+                .minute
+                .saturating_sub(now.saturating_duration_since(self.batch_start))
+                .max(Duration::from_secs(self.limits.batch_pause));
+            self.batch_start = now + wait;
+            self.in_batch = 0;
+        }
+        self.in_batch += 1;
+        wait
+    }
+
+    fn record(&mut self, took: Duration) {
+        self.sending += took;
+        self.sent += 1;
+    }
+
+    /// The time `pending` more messages are expected to take.
+    fn remaining(&self, pending: usize, now: Instant) -> Duration {
+        // A batch starting after a pause starts in the future.
+        self.batch_start.saturating_duration_since(now) + self.after_pause(pending, now)
+    }
+
+    fn after_pause(&self, pending: usize, now: Instant) -> Duration {
+        let per_message = match self.sent {
+            0 => Self::FIRST_GUESS,
+            sent => self.sending / sent,
+        };
+        let per_minute = self.limits.messages_per_minute;
+        let room = per_minute.saturating_sub(self.in_batch);
+        if per_minute == 0 || pending <= room {
+            return per_message * pending as u32;
+        }
+        let batch = (per_message * per_minute as u32
+            + Duration::from_secs(self.limits.batch_pause))
+        .max(self.minute);
+        let current = batch.saturating_sub(now.saturating_duration_since(self.batch_start));
+        let rest = pending - room;
+        let full = (rest - 1) / per_minute;
+        current + batch * full as u32 + per_message * (rest - full * per_minute) as u32
+    }
+}
+
+/// Sleeps for `wait` unless the mailing is cancelled meanwhile.
+fn pause(wait: Duration, cancelled: &AtomicBool) {
+    const STEP: Duration = Duration::from_millis(200);
+    let until = Instant::now() + wait;
+    while !cancelled.load(Ordering::Relaxed) {
+        let left = until.saturating_duration_since(Instant::now());
+        if left.is_zero() {
+            break;
+        }
+        thread::sleep(left.min(STEP));
+    }
+}
+
+/// What the user sees of a running mailing.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SendProgress {
+    pub topic: String,
+    pub jobs: Vec<(String, JobStatus)>,
+    /// The addresses each of the `jobs` goes to.
+    pub addresses: Vec<Vec<String>>,
+    /// Expected time to the end of the mailing, the pauses included.
+    pub remaining: Option<Duration>,
+    pub finished: bool,
+}
+
+impl SendProgress {
+    pub fn count(&self, status: &JobStatus) -> usize {
+        self.jobs.iter().filter(|(_, s)| s == status).count()
+    }
+
+    pub fn failed(&self) -> usize {
+        self.jobs
+            .iter()
+            .filter(|(_, s)| matches!(s, JobStatus::Failed(_)))
+            .count()
+    }
+
+    /// Number of messages that are no longer waiting or in flight.
+    pub fn done(&self) -> usize {
+        self.count(&JobStatus::Sent) + self.count(&JobStatus::Cancelled) + self.failed()
+    }
+
+    /// Every address with the status of the message carrying it.
+    pub fn deliveries(&self) -> impl Iterator<Item = (&str, &JobStatus)> + '_ {
+        self.addresses
+            .iter()
+            .zip(self.jobs.iter())
+            .flat_map(|(addresses, (_, status))| {
+                addresses
+                    .iter()
+                    .map(move |address| (address.as_str(), status))
+            })
+    }
+
+    /// Gives the letter the outcome of the finished mailing and adds its
+    /// deliveries to the letter's history.
+    pub fn settle(&self, letter: &mut LetterRepr) {
+        letter.mark_sent(self.count(&JobStatus::Sent), self.failed());
+        let at = chrono::Local::now();
+        for (address, status) in self.deliveries() {
+            let error = match status {
+                JobStatus::Sent => None,
+                JobStatus::Failed(e) => Some(e.to_owned()),
+                _ => continue,
+            };
+            letter.add_delivery(Delivery {
+                at,
+                address: address.to_owned(),
+                error,
+            });
+        }
+    }
+
+    pub fn summary(&self) -> String {
+        format!(
+            "Рассылка {:?}: отправлено {}, ошибок {}, отменено {}.",
+            self.topic,
+            self.count(&JobStatus::Sent),
+            self.failed(),
+            self.count(&JobStatus::Cancelled)
+        )
+    }
+
+    /// A line for every recipient with the status of their message.
+    pub fn describe(&self) -> String {
+        self.deliveries()
+            .map(|(address, status)| format!("[{}] {}", status, address))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+/// A mailing delivered message by message on a background thread.
+pub struct SendTask {
+    progress: SendProgress,
+    reports: mpsc::Receiver<SendReport>,
+    cancel: Arc<AtomicBool>,
+}
+
+impl SendTask {
+    pub fn spawn(outbox: Arc<dyn Outbox>, mailing: Mailing, topic: String) -> Self {
+        let (tx, reports) = mpsc::channel();
+        let cancel = Arc::new(AtomicBool::new(false));
+        let progress = SendProgress {
+            topic,
+            jobs: mailing
+                .jobs
+                .iter()
+                .map(|job| (job.label.to_owned(), JobStatus::Pending))
+                .collect(),
+            addresses: mailing
+                .jobs
+                .iter()
+                .map(|job| job.addresses.clone())
+                .collect(),
+            remaining: None,
+            finished: false,
+        };
+        let cancelled = cancel.clone();
+        thread::spawn(move || {
+            let Mailing { profile, jobs, .. } = mailing;
+            let count = jobs.len();
+            let mut pacer = Pacer::new(profile.limits, Instant::now());
+            // The receiver may be gone when the application quits; nobody
+            // needs the reports then.
+            for (index, job) in jobs.into_iter().enumerate() {
+                if !cancelled.load(Ordering::Relaxed) {
+                    let wait = pacer.next(Instant::now());
+                    if !wait.is_zero() {
+                        let remaining = pacer.remaining(count - index - 1, Instant::now());
+                        tx.send(SendReport::Remaining(remaining)).ok();
+                        pause(wait, &cancelled);
+                    }
+                }
+                if cancelled.load(Ordering::Relaxed) {
+                    tx.send(SendReport::Status {
+                        index,
+                        status: JobStatus::Cancelled,
+                    })
+                    .ok();
+                    continue;
+                }
+                tx.send(SendReport::Status {
+                    index,
+                    status: JobStatus::Sending,
+                })
+                .ok();
+                let started = Instant::now();
+                let status = match outbox.send(&profile, &job.message) {
+                    Ok(()) => JobStatus::Sent,
+                    Err(e) => JobStatus::Failed(e.to_string()),
+                };
+                pacer.record(started.elapsed());
+                tx.send(SendReport::Status { index, status }).ok();
+                let remaining = pacer.remaining(count - index - 1, Instant::now());
+                tx.send(SendReport::Remaining(remaining)).ok();
+            }
+            tx.send(SendReport::Finished).ok();
+        });
+        Self {
+            progress,
+            reports,
+            cancel,
+        }
+    }
+
+    /// Messages already handed to the relay are not recalled.
+    pub fn cancel(&self) {
+        self.cancel.store(true, Ordering::Relaxed);
+    }
+
+    /// Applies the reports received so far, returns whether anything changed.
+    pub fn poll(&mut self) -> bool {
+        let mut changed = false;
+        while let Ok(report) = self.reports.try_recv() {
+            self.apply(report);
+            changed = true;
+        }
+        changed
+    }
+
+    /// Blocks until the worker is done.
+    pub fn wait(&mut self) {
+        while !self.progress.finished {
+            match self.reports.recv() {
+                Ok(report) => self.apply(report),
+                Err(_) => self.progress.finished = true,
+            }
+        }
+    }
+
+    pub fn progress(&self) -> &SendProgress {
+        &self.progress
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.progress.finished
+    }
+
+    fn apply(&mut self, report: SendReport) {
+        match report {
+            SendReport::Status { index, status } => self.progress.jobs[index].1 = status,
+            SendReport::Remaining(remaining) => self.progress.remaining = Some(remaining),
+            SendReport::Finished => self.progress.finished = true,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use lettre::Message;
+
+    use super::*;
+    use crate::{
+        controller::{
+            mailer::{build_jobs, MailError, Recipients},
+            settings::SenderProfile,
+        },
+        data_handler::letter::LetterRepr,
+    };
+
+    /// Fails for one address and records the rest.
+    struct PickyOutbox {
+        sent: Mutex<Vec<String>>,
+    }
+
+    impl Outbox for PickyOutbox {
+        fn send(&self, _: &SenderProfile, message: &Message) -> Result<(), MailError> {
+            let to = message.envelope().to()[0].to_string();
+            if to == "bad@x.ru" {
+                return Err(MailError::NoRecipients);
+            }
+            self.sent.lock().unwrap().push(to);
+            Ok(())
+        }
+    }
+
+    /// Holds every message until the test lets it through.
+    struct GateOutbox {
+        started: Mutex<mpsc::Sender<()>>,
+        release: Mutex<mpsc::Receiver<()>>,
+    }
+
+    impl Outbox for GateOutbox {
+        fn send(&self, _: &SenderProfile, _: &Message) -> Result<(), MailError> {
+            self.started.lock().unwrap().send(()).unwrap();
+            self.release.lock().unwrap().recv().unwrap();
+            Ok(())
+        }
+    }
+
+    /// A message per Bcc address.
+    fn mailing(bcc: &[&str]) -> Mailing {
+        let mut profile = SenderProfile::default();
+        profile.limits.recipients_per_message = 1;
+        let recipients = Recipients {
+            to: vec![],
+            cc: vec![],
+            bcc: bcc.iter().map(|x| x.to_string()).collect(),
+        };
+        let jobs = build_jobs(&profile, &LetterRepr::new(), &recipients).unwrap();
+        Mailing {
+            profile,
+            recipients,
+            jobs,
+        }
+    }
+
+    #[test]
+    fn test_failure_does_not_stop_mailing() {
+        let outbox = Arc::new(PickyOutbox {
+            sent: Mutex::new(vec![]),
+        });
+        let mut task = SendTask::spawn(
+            outbox.clone(),
+            mailing(&["a@x.ru", "bad@x.ru", "b@x.ru"]),
+            "Тема".into(),
+        );
+        task.wait();
+        let progress = task.progress();
+        assert_eq!(progress.count(&JobStatus::Sent), 2);
+        assert_eq!(progress.failed(), 1);
+        assert_eq!(progress.done(), 3);
+        assert_eq!(*outbox.sent.lock().unwrap(), vec!["a@x.ru", "b@x.ru"]);
+    }
+
+    #[test]
+    fn test_pacer_waits_between_batches() {
+        let limits = SendLimits {
+            messages_per_minute: 2,
+            recipients_per_message: 0,
+            batch_pause: 0,
+        };
+        let second = Duration::from_secs(1);
+        let start = Instant::now();
+        let mut pacer = Pacer::new(limits, start);
+        for elapsed in [0, 1] {
+            assert_eq!(pacer.next(start + second * elapsed), Duration::ZERO);
+            pacer.record(second);
+        }
+        let now = start + second * 2;
+        assert_eq!(pacer.next(now), second * 58);
+        // One more fits in the batch after the pause, the next one waits again.
+        assert_eq!(pacer.remaining(2, now), second * 119);
+
+        let mut pacer = Pacer::new(
+            SendLimits {
+                batch_pause: 70,
+                ..limits
+            },
+            start,
+        );
+        pacer.next(start);
+        pacer.next(start);
+        assert_eq!(pacer.next(start), second * 70);
+    }
+
+    #[test]
+    fn test_cancel_skips_remaining() {
+        let (started_tx, started) = mpsc::channel();
+        let (release, release_rx) = mpsc::channel();
+        let outbox = Arc::new(GateOutbox {
+            started: Mutex::new(started_tx),
+            release: Mutex::new(release_rx),
+        });
+        let mut task = SendTask::spawn(
+            outbox,
+            mailing(&["a@x.ru", "b@x.ru", "c@x.ru"]),
+            "Тема".into(),
+        );
+        started.recv().unwrap();
+        task.cancel();
+        release.send(()).unwrap();
+        task.wait();
+        let progress = task.progress();
+        assert_eq!(progress.count(&JobStatus::Sent), 1);
+        assert_eq!(progress.count(&JobStatus::Cancelled), 2);
+        assert!(started.try_recv().is_err());
+        assert_eq!(
+            progress.describe().lines().last(),
+            Some("[отменено] c@x.ru")
+        );
+    }
+}
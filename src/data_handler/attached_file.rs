@@ -2,7 +2,7 @@ use std::{fs, io, path::Path};
 
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AttachedFile {
     content_bytes: Vec<u8>,
     filename: String,
@@ -27,4 +27,30 @@ impl AttachedFile {
     pub fn get_size(&self) -> usize {
         self.content_bytes.len()
     }
+
+    pub fn get_content_bytes(&self) -> &[u8] {
+        &self.content_bytes
+    }
+
+    pub fn get_content_type(&self) -> &str {
+        &self.content_type
+    }
+}
+
+/// `[name (size байт)] ...` or a note that there are none.
+pub fn attachment_info<'a>(files: impl Iterator<Item = &'a AttachedFile>) -> String {
+    let info = files
+        .map(|attached_file| {
+            format!(
+                "[{} ({} байт)]",
+                attached_file.get_filename(),
+                attached_file.get_size()
+            )
+        })
+        .collect::<Vec<_>>();
+    if info.is_empty() {
+        "[Вложений нет]".to_string()
+    } else {
+        info.join(" ")
+    }
 }
@@ -0,0 +1,208 @@
+use std::{
+    fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+/// Backups live in this subdirectory next to the data files.
+pub const BACKUP_DIR: &str = "backups";
+/// How many previous versions of every data file are kept.
+pub const BACKUP_COUNT: usize = 10;
+
+/// Replaces `path` with `contents` atomically, keeping the previous version
+/// as a timestamped backup.
+pub fn save(path: impl AsRef<Path>, contents: &str) -> io::Result<()> {
+    save_with(path, contents, true)
+}
+
+/// Like `save`, but only keeps a backup if `keep_backup` is set.
+pub fn save_with(path: impl AsRef<Path>, contents: &str, keep_backup: bool) -> io::Result<()> {
+    let path = path.as_ref();
+    let changed = fs::read_to_string(path)
+        .map(|old| old != contents)
+        .unwrap_or(true);
+    if !changed {
+        return Ok(());
+    }
+    if keep_backup && path.exists() {
+        self::keep_backup(path)?;
+    }
+    write_atomically(path, contents)
+}
+
+/// Copies `path` into the backups and drops the oldest ones. Nothing is
+/// copied when the newest backup already holds the same contents.
+pub fn keep_backup(path: impl AsRef<Path>) -> io::Result<()> {
+    let path = path.as_ref();
+    let newest = list_backups(path)?.into_iter().next();
+    if let Some(newest) = newest {
+        if fs::read(&newest)? == fs::read(path)? {
+            return Ok(());
+        }
+    }
+    make_backup(path)?;
+    prune_backups(path, BACKUP_COUNT)
+}
+
+/// Backups of `path`, newest first.
+pub fn list_backups(path: impl AsRef<Path>) -> io::Result<Vec<PathBuf>> {
+    let path = path.as_ref();
+    let prefix = backup_prefix(path);
+    let mut backups = match fs::read_dir(backup_dir(path)) {
+        Ok(entries) => entries
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .filter(|backup| {
+                backup
+                    .file_name()
+                    .and_then(|name| name.to_str())
+                    .map(|name| name.starts_with(&prefix))
+                    .unwrap_or(false)
+            })
+            .collect::<Vec<_>>(),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => vec![],
+        Err(e) => return Err(e),
+    };
+    backups.sort();
+    backups.reverse();
+    Ok(backups)
+}
+
+/// The timestamp part of a backup name, for display.
+pub fn backup_time(backup: &Path) -> String {
+    backup
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .unwrap_or_default()
+        .to_owned()
+}
+
+/// Writes a temporary file next to `path`, syncs it and renames it over `path`.
+pub fn write_atomically(path: &Path, contents: &str) -> io::Result<()> {
+    let mut tmp_name = path.file_name().unwrap_or_default().to_os_string();
+    tmp_name.push(".tmp");
+    let tmp = path.with_file_name(tmp_name);
+    {
+        let mut file = fs::File::create(&tmp)?;
+        file.write_all(contents.as_bytes())?;
+        file.sync_all()?;
+    }
+    fs::rename(&tmp, path)?;
+    // Persist the rename itself; not every platform can open a directory.
+    if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        if let Ok(dir) = fs::File::open(dir) {
+            dir.sync_all().ok();
+        }
+    }
+    Ok(())
+}
+
+/// Writes `contents` to `path`, or to `<stem>-2.<ext>`, `<stem>-3.<ext>`...
+/// if it is taken, never replacing an existing file; returns the path used.
+pub fn write_new(path: &Path, contents: &str) -> io::Result<PathBuf> {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let extension = path
+        .extension()
+        .map(|ext| format!(".{}", ext.to_string_lossy()))
+        .unwrap_or_default();
+    for n in 1.. {
+        let candidate = match n {
+            1 => path.to_owned(),
+            n => path.with_file_name(format!("{}-{}{}", stem, n, extension)),
+        };
+        match fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&candidate)
+        {
+            Ok(mut file) => {
+                file.write_all(contents.as_bytes())?;
+                return Ok(candidate);
+            }
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(e),
+        }
+    }
+    unreachable!()
+}
+
+fn make_backup(path: &Path) -> io::Result<()> {
+    let dir = backup_dir(path);
+    fs::create_dir_all(&dir)?;
+    let backup = dir.join(format!(
+        "{}{}",
+        backup_prefix(path),
+        chrono::Local::now().format("%Y%m%d-%H%M%S-%3f")
+    ));
+    fs::copy(path, backup)?;
+    Ok(())
+}
+
+fn prune_backups(path: &Path, keep: usize) -> io::Result<()> {
+    for old in list_backups(path)?.into_iter().skip(keep) {
+        fs::remove_file(old)?;
+    }
+    Ok(())
+}
+
+fn backup_dir(path: &Path) -> PathBuf {
+    path.parent().unwrap_or(Path::new(".")).join(BACKUP_DIR)
+}
+
+fn backup_prefix(path: &Path) -> String {
+    format!(
+        "{}.",
+        path.file_name().unwrap_or_default().to_string_lossy()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::TempDir;
+
+    #[test]
+    fn test_save_rotates_backups() {
+        let dir = TempDir::new("backup");
+        let path = dir.join("tag.json");
+        for n in 0..BACKUP_COUNT + 3 {
+            save(&path, &format!("[{}]", n)).unwrap();
+            std::thread::sleep(std::time::Duration::from_millis(2));
+        }
+        save(&path, &format!("[{}]", BACKUP_COUNT + 2)).unwrap();
+
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            format!("[{}]", BACKUP_COUNT + 2)
+        );
+        let backups = list_backups(&path).unwrap();
+        assert_eq!(backups.len(), BACKUP_COUNT);
+        assert_eq!(
+            fs::read_to_string(&backups[0]).unwrap(),
+            format!("[{}]", BACKUP_COUNT + 1)
+        );
+        assert!(!dir.join("tag.json.tmp").exists());
+    }
+
+    #[test]
+    fn test_write_new_keeps_existing_files() {
+        let dir = TempDir::new("backup");
+        let path = dir.join("persona-export.tsv");
+        assert_eq!(write_new(&path, "1").unwrap(), path);
+        let second = write_new(&path, "2").unwrap();
+        assert_eq!(second, dir.join("persona-export-2.tsv"));
+        assert_eq!(fs::read_to_string(&path).unwrap(), "1");
+        assert_eq!(fs::read_to_string(&second).unwrap(), "2");
+    }
+
+    #[test]
+    fn test_same_contents_are_backed_up_once() {
+        let dir = TempDir::new("backup");
+        let path = dir.join("tag.json");
+        fs::write(&path, "[1]").unwrap();
+        keep_backup(&path).unwrap();
+        std::thread::sleep(std::time::Duration::from_millis(2));
+        keep_backup(&path).unwrap();
+        assert_eq!(list_backups(&path).unwrap().len(), 1);
+    }
+}
@@ -1,15 +1,12 @@
-use std::{
-    cell::RefCell,
-    collections::BTreeMap,
-    fs,
-    io::{self, Write},
-    path::Path,
-    rc::Rc,
-};
+use std::{cell::RefCell, collections::BTreeMap, fs, io, path::Path, rc::Rc};
 
 use serde::{de::DeserializeOwned, Serialize};
 
-use super::{make_ref, Identity, Represent};
+use super::{
+    make_ref,
+    schema::{self, Versioned},
+    Identity, Represent,
+};
 
 #[derive(Debug)]
 pub struct DataContainer<Repr> {
@@ -31,6 +28,10 @@ impl<Repr> DataContainer<Repr> {
         self.container.keys()
     }
 
+    pub fn get(&self, identity: impl AsRef<str>) -> Option<Rc<RefCell<Repr>>> {
+        self.container.get(identity.as_ref()).cloned()
+    }
+
     pub fn all_representations(&self) -> impl Iterator<Item = Rc<RefCell<Repr>>> + '_ {
         self.container.values().cloned()
     }
@@ -69,32 +70,24 @@ impl<Repr: Represent> DataContainer<Repr> {
     }
 }
 
-impl<Repr: Serialize> DataContainer<Repr> {
+impl<Repr: Serialize + Versioned> DataContainer<Repr> {
     pub fn to_json(&self) -> String {
-        serde_json::to_string(&self.container.values().collect::<Vec<_>>()).unwrap()
-    }
-
-    pub fn finalize(&self, path: impl AsRef<Path>) -> io::Result<()> {
-        let mut file = fs::File::create(path)?;
-        write!(file, "{}", self.to_json())?;
-        Ok(())
+        schema::to_json(self.container.values())
     }
 }
 
-impl<Repr: DeserializeOwned + Represent> DataContainer<Repr> {
-    pub fn from_json(json: impl AsRef<str>) -> serde_json::Result<Self> {
-        let data: Vec<Rc<RefCell<Repr>>> = serde_json::from_str(json.as_ref())?;
-        Ok(Self {
-            container: data
-                .into_iter()
-                .map(|x| (make_ref(&x).identity(), Rc::clone(&x)))
-                .collect(),
-        })
+impl<Repr: DeserializeOwned + Represent + Versioned> DataContainer<Repr> {
+    /// Reads any known version of the file; returns the version found too.
+    pub fn from_json(json: impl AsRef<str>) -> io::Result<(Self, u32)> {
+        let (records, version) = schema::from_json::<Repr>(json.as_ref())?;
+        let mut container = Self::new();
+        for repr in records {
+            container.insert_or_update(Rc::new(RefCell::new(repr)));
+        }
+        Ok((container, version))
     }
 
-    pub fn restore(path: impl AsRef<Path>) -> io::Result<Self> {
-        let json = fs::read_to_string(path)?;
-        DataContainer::from_json(&json)
-            .map_err(|json_err| io::Error::new(io::ErrorKind::InvalidData, json_err))
+    pub fn restore(path: impl AsRef<Path>) -> io::Result<(Self, u32)> {
+        DataContainer::from_json(fs::read_to_string(path)?)
     }
 }
@@ -0,0 +1,139 @@
+use std::{
+    collections::BTreeMap,
+    fs, io,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+use serde::{Deserialize, Serialize};
+
+use super::{backup, letter::DateTime, Identity};
+
+/// Unsaved drafts are written at most this often.
+const FLUSH_INTERVAL: Duration = Duration::from_secs(2);
+
+/// The state of an open letter form that has not been submitted yet.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Draft {
+    /// Identity of the edited letter.
+    pub key: Identity,
+    pub topic: String,
+    pub text: String,
+    pub saved: DateTime,
+}
+
+impl Draft {
+    pub fn new(key: Identity, topic: impl ToString, text: impl ToString) -> Self {
+        Self {
+            key,
+            topic: topic.to_string(),
+            text: text.to_string(),
+            saved: chrono::Local::now(),
+        }
+    }
+}
+
+/// Drafts of the open letter forms, kept on disk to survive a crash.
+#[derive(Debug)]
+pub struct DraftJournal {
+    path: PathBuf,
+    drafts: BTreeMap<Identity, Draft>,
+    dirty: bool,
+    flushed: Option<Instant>,
+}
+
+impl DraftJournal {
+    /// An unreadable journal is treated as empty: drafts are a convenience.
+    pub fn open(path: impl AsRef<Path>) -> Self {
+        let drafts = fs::read_to_string(path.as_ref())
+            .ok()
+            .and_then(|json| serde_json::from_str::<Vec<Draft>>(&json).ok())
+            .unwrap_or_default()
+            .into_iter()
+            .map(|draft| (draft.key.to_owned(), draft))
+            .collect();
+        Self {
+            path: path.as_ref().to_path_buf(),
+            drafts,
+            dirty: false,
+            flushed: None,
+        }
+    }
+
+    pub fn drafts(&self) -> impl Iterator<Item = &Draft> + '_ {
+        self.drafts.values()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.drafts.is_empty()
+    }
+
+    pub fn update(&mut self, draft: Draft) {
+        self.drafts.insert(draft.key.to_owned(), draft);
+        self.dirty = true;
+    }
+
+    pub fn remove(&mut self, key: impl AsRef<str>) {
+        if self.drafts.remove(key.as_ref()).is_some() {
+            self.dirty = true;
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.dirty |= !self.drafts.is_empty();
+        self.drafts.clear();
+    }
+
+    /// Writes pending changes unless the last write was too recent.
+    pub fn flush_due(&mut self) -> io::Result<()> {
+        match self.flushed {
+            Some(flushed) if flushed.elapsed() < FLUSH_INTERVAL => Ok(()),
+            _ => self.flush(),
+        }
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        if !self.dirty {
+            return Ok(());
+        }
+        if self.drafts.is_empty() {
+            match fs::remove_file(&self.path) {
+                Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+                _ => {}
+            }
+        } else {
+            let json = serde_json::to_string(&self.drafts.values().collect::<Vec<_>>())
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+            backup::write_atomically(&self.path, &json)?;
+        }
+        self.dirty = false;
+        self.flushed = Some(Instant::now());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::TempDir;
+
+    #[test]
+    fn test_journal_survives_reopen() {
+        let dir = TempDir::new("drafts");
+        let path = dir.join("drafts.json");
+        let mut journal = DraftJournal::open(&path);
+        journal.update(Draft::new("a".into(), "Тема", "Текст"));
+        journal.update(Draft::new("b".into(), "", ""));
+        journal.remove("b");
+        journal.flush().unwrap();
+
+        let mut reopened = DraftJournal::open(&path);
+        assert_eq!(
+            reopened.drafts().collect::<Vec<_>>(),
+            journal.drafts().collect::<Vec<_>>()
+        );
+        reopened.clear();
+        reopened.flush().unwrap();
+        assert!(!path.exists());
+    }
+}
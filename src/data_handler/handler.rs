@@ -1,23 +1,364 @@
-use std::io;
+use std::{
+    collections::BTreeMap,
+    fmt, fs, io,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
 
-use super::{letter::LetterContainer, persona::PersonaContainer, tag::TagContainer};
+use serde::{de::DeserializeOwned, Serialize};
+
+use super::{
+    backup,
+    data_container::DataContainer,
+    drafts::DraftJournal,
+    letter::{DateTime, LetterContainer, LetterRepr},
+    lock::{DataLock, LockError},
+    persona::{PersonaContainer, PersonaRepr},
+    report::ContactReport,
+    schema::Versioned,
+    storage::{Storage, StorageKind},
+    tag::{Membership, TagContainer, TagRepr},
+    template::{TemplateContainer, TemplateRepr},
+    trash::Trash,
+    Identity, Represent,
+};
 
 const PERSONA_CONTAINER_PATH: &str = "persona.json";
 const TAG_CONTAINER_PATH: &str = "tag.json";
+const LETTER_CONTAINER_PATH: &str = "letter.json";
+const TEMPLATE_CONTAINER_PATH: &str = "template.json";
+const DRAFTS_PATH: &str = "drafts.json";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum DataFile {
+    People,
+    Tags,
+    Letters,
+    Templates,
+}
+
+impl DataFile {
+    pub const ALL: [DataFile; 4] = [
+        DataFile::People,
+        DataFile::Tags,
+        DataFile::Letters,
+        DataFile::Templates,
+    ];
+
+    pub fn file_name(self) -> &'static str {
+        match self {
+            DataFile::People => PERSONA_CONTAINER_PATH,
+            DataFile::Tags => TAG_CONTAINER_PATH,
+            DataFile::Letters => LETTER_CONTAINER_PATH,
+            DataFile::Templates => TEMPLATE_CONTAINER_PATH,
+        }
+    }
+
+    pub fn trash_file_name(self) -> String {
+        self.file_name().replace(".json", ".trash.json")
+    }
+}
+
+impl fmt::Display for DataFile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DataFile::People => write!(f, "персоны"),
+            DataFile::Tags => write!(f, "метки"),
+            DataFile::Letters => write!(f, "письма"),
+            DataFile::Templates => write!(f, "шаблоны"),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct LoadError {
+    pub file: DataFile,
+    pub path: PathBuf,
+    pub error: io::Error,
+    /// Where the unreadable file was copied, if it was.
+    pub preserved: Option<PathBuf>,
+    /// The file was changed on disk by someone else before we saved it;
+    /// `preserved` then holds our version.
+    pub conflict: bool,
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.conflict {
+            write!(
+                f,
+                "Файл {:?} ({}) изменён другой программой и не перезаписан.",
+                self.path, self.file
+            )?;
+            if let Some(ref preserved) = self.preserved {
+                write!(f, "\nВаша версия сохранена в {:?}.", preserved)?;
+            }
+            return Ok(());
+        }
+        write!(
+            f,
+            "Не удалось прочитать {:?} ({}): {}",
+            self.path, self.file, self.error
+        )?;
+        if let Some(ref preserved) = self.preserved {
+            write!(f, "\nКопия файла сохранена в {:?}.", preserved)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for LoadError {}
+
+/// A record type kept in one of the data files.
+pub trait Stored: Represent + Versioned + Serialize + DeserializeOwned + Sized + 'static {
+    const FILE: DataFile;
+
+    fn container(data_handler: &mut DataHandler) -> &mut DataContainer<Self>;
+
+    fn slot(data_handler: &mut DataHandler) -> &mut Option<DataContainer<Self>>;
+
+    fn storage_slot(data_handler: &mut DataHandler) -> &mut Option<Box<dyn Storage<Self>>>;
+
+    /// The container if it has been loaded.
+    fn loaded(data_handler: &DataHandler) -> Option<&DataContainer<Self>>;
+
+    fn trash_slot(data_handler: &mut DataHandler) -> &mut Option<Trash<Self>>;
+
+    /// A short line for lists such as the trash.
+    fn describe(&self) -> String {
+        self.identity()
+    }
+}
+
+impl Stored for PersonaRepr {
+    const FILE: DataFile = DataFile::People;
+
+    fn container(data_handler: &mut DataHandler) -> &mut DataContainer<Self> {
+        data_handler.get_people_mut()
+    }
+
+    fn slot(data_handler: &mut DataHandler) -> &mut Option<DataContainer<Self>> {
+        &mut data_handler.people
+    }
+
+    fn storage_slot(data_handler: &mut DataHandler) -> &mut Option<Box<dyn Storage<Self>>> {
+        &mut data_handler.people_storage
+    }
+
+    fn loaded(data_handler: &DataHandler) -> Option<&DataContainer<Self>> {
+        data_handler.people.as_ref()
+    }
+
+    fn trash_slot(data_handler: &mut DataHandler) -> &mut Option<Trash<Self>> {
+        &mut data_handler.people_trash
+    }
+}
+
+impl Stored for TagRepr {
+    const FILE: DataFile = DataFile::Tags;
+
+    fn container(data_handler: &mut DataHandler) -> &mut DataContainer<Self> {
+        data_handler.get_tags_mut()
+    }
+
+    fn slot(data_handler: &mut DataHandler) -> &mut Option<DataContainer<Self>> {
+        &mut data_handler.tags
+    }
+
+    fn storage_slot(data_handler: &mut DataHandler) -> &mut Option<Box<dyn Storage<Self>>> {
+        &mut data_handler.tags_storage
+    }
+
+    fn loaded(data_handler: &DataHandler) -> Option<&DataContainer<Self>> {
+        data_handler.tags.as_ref()
+    }
+
+    fn trash_slot(data_handler: &mut DataHandler) -> &mut Option<Trash<Self>> {
+        &mut data_handler.tags_trash
+    }
+}
+
+impl Stored for LetterRepr {
+    const FILE: DataFile = DataFile::Letters;
+
+    fn container(data_handler: &mut DataHandler) -> &mut DataContainer<Self> {
+        data_handler.get_letters_mut()
+    }
+
+    fn slot(data_handler: &mut DataHandler) -> &mut Option<DataContainer<Self>> {
+        &mut data_handler.letters
+    }
+
+    fn storage_slot(data_handler: &mut DataHandler) -> &mut Option<Box<dyn Storage<Self>>> {
+        &mut data_handler.letters_storage
+    }
+
+    fn loaded(data_handler: &DataHandler) -> Option<&DataContainer<Self>> {
+        data_handler.letters.as_ref()
+    }
+
+    fn trash_slot(data_handler: &mut DataHandler) -> &mut Option<Trash<Self>> {
+        &mut data_handler.letters_trash
+    }
+
+    fn describe(&self) -> String {
+        format!(
+            "{} {}",
+            self.get_time().format("%d.%m.%Y %H:%M"),
+            self.get_topic()
+        )
+    }
+}
+
+impl Stored for TemplateRepr {
+    const FILE: DataFile = DataFile::Templates;
+
+    fn container(data_handler: &mut DataHandler) -> &mut DataContainer<Self> {
+        data_handler.get_templates_mut()
+    }
+
+    fn slot(data_handler: &mut DataHandler) -> &mut Option<DataContainer<Self>> {
+        &mut data_handler.templates
+    }
+
+    fn storage_slot(data_handler: &mut DataHandler) -> &mut Option<Box<dyn Storage<Self>>> {
+        &mut data_handler.templates_storage
+    }
+
+    fn loaded(data_handler: &DataHandler) -> Option<&DataContainer<Self>> {
+        data_handler.templates.as_ref()
+    }
+
+    fn trash_slot(data_handler: &mut DataHandler) -> &mut Option<Trash<Self>> {
+        &mut data_handler.templates_trash
+    }
+
+    fn describe(&self) -> String {
+        format!("Шаблон {}", self.name())
+    }
+}
+
+/// One line of the trash view.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TrashItem {
+    pub file: DataFile,
+    pub index: usize,
+    pub label: String,
+    pub removed: DateTime,
+}
 
 #[derive(Debug)]
 pub struct DataHandler {
+    dir: PathBuf,
     people: Option<PersonaContainer>,
     tags: Option<TagContainer>,
-    _letters: Option<LetterContainer>,
+    letters: Option<LetterContainer>,
+    templates: Option<TemplateContainer>,
+    people_trash: Option<Trash<PersonaRepr>>,
+    tags_trash: Option<Trash<TagRepr>>,
+    letters_trash: Option<Trash<LetterRepr>>,
+    templates_trash: Option<Trash<TemplateRepr>>,
+    storage: StorageKind,
+    people_storage: Option<Box<dyn Storage<PersonaRepr>>>,
+    tags_storage: Option<Box<dyn Storage<TagRepr>>>,
+    letters_storage: Option<Box<dyn Storage<LetterRepr>>>,
+    templates_storage: Option<Box<dyn Storage<TemplateRepr>>>,
+    drafts: Option<DraftJournal>,
+    protected: Vec<DataFile>,
+    /// Files backed up this session; later saves do not wash out older backups.
+    backed_up: Vec<DataFile>,
+    /// Size and modification time of every file as we last read or wrote it.
+    stamps: BTreeMap<DataFile, Option<Stamp>>,
+    load_errors: Vec<LoadError>,
+    lock: Option<DataLock>,
+    read_only: bool,
 }
 
+type Stamp = (u64, SystemTime);
+
 impl DataHandler {
     pub fn new() -> Self {
+        Self::with_dir(".")
+    }
+
+    /// Keeps the data files in `dir` instead of the current directory.
+    pub fn with_dir(dir: impl AsRef<Path>) -> Self {
         Self {
+            dir: dir.as_ref().to_path_buf(),
             people: None,
             tags: None,
-            _letters: None,
+            letters: None,
+            templates: None,
+            people_trash: None,
+            tags_trash: None,
+            letters_trash: None,
+            templates_trash: None,
+            storage: StorageKind::default(),
+            people_storage: None,
+            tags_storage: None,
+            letters_storage: None,
+            templates_storage: None,
+            drafts: None,
+            protected: vec![],
+            backed_up: vec![],
+            stamps: BTreeMap::new(),
+            load_errors: vec![],
+            lock: None,
+            read_only: false,
+        }
+    }
+
+    /// Locks the data directory against other instances. If it is taken,
+    /// the handler stays read-only.
+    pub fn lock(&mut self) -> Result<(), LockError> {
+        match DataLock::acquire(&self.dir) {
+            Ok(lock) => {
+                self.lock = Some(lock);
+                Ok(())
+            }
+            Err(e) => {
+                self.read_only = true;
+                Err(e)
+            }
+        }
+    }
+
+    pub fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+
+    /// Chooses the backend; it takes effect for containers not loaded yet.
+    pub fn set_storage(&mut self, kind: StorageKind) {
+        self.storage = kind;
+        self.people_storage = None;
+        self.tags_storage = None;
+        self.letters_storage = None;
+        self.templates_storage = None;
+    }
+
+    pub fn storage_kind(&self) -> StorageKind {
+        self.storage
+    }
+
+    /// Rewrites every container with the `to` backend and switches to it.
+    /// The files of the old backend are left in place.
+    pub fn convert(&mut self, to: StorageKind) -> io::Result<()> {
+        if self.read_only {
+            return Err(io::Error::other("данные открыты только для чтения"));
+        }
+        self.load_all();
+        if let Some(e) = self.take_load_errors().into_iter().next() {
+            return Err(io::Error::other(e.to_string()));
+        }
+        self.set_storage(to);
+        self.backed_up.clear();
+        for file in DataFile::ALL {
+            self.stamps.insert(file, disk_stamp(&self.path(file)));
+        }
+        self.finalize()?;
+        match self.take_load_errors().into_iter().next() {
+            Some(e) => Err(io::Error::other(e.to_string())),
+            None => Ok(()),
         }
     }
 
@@ -25,7 +366,7 @@ impl DataHandler {
         match self.people {
             Some(ref people) => people,
             None => {
-                self.people = Some(Self::restore_people().unwrap_or_default());
+                self.people = Some(self.load::<PersonaRepr>());
                 self.get_people()
             }
         }
@@ -35,7 +376,7 @@ impl DataHandler {
         match self.people {
             Some(ref mut people) => people,
             None => {
-                self.people = Some(Self::restore_people().unwrap_or_default());
+                self.people = Some(self.load::<PersonaRepr>());
                 self.get_people_mut()
             }
         }
@@ -45,7 +386,7 @@ impl DataHandler {
         match self.tags {
             Some(ref tags) => tags,
             None => {
-                self.tags = Some(Self::restore_tags().unwrap_or_default());
+                self.tags = Some(self.load::<TagRepr>());
                 self.get_tags()
             }
         }
@@ -55,30 +396,578 @@ impl DataHandler {
         match self.tags {
             Some(ref mut tags) => tags,
             None => {
-                self.tags = Some(Self::restore_tags().unwrap_or_default());
+                self.tags = Some(self.load::<TagRepr>());
                 self.get_tags_mut()
             }
         }
     }
 
-    fn restore_people() -> io::Result<PersonaContainer> {
-        PersonaContainer::restore(PERSONA_CONTAINER_PATH)
+    /// Evaluates tag members against the current personas.
+    pub fn membership(&mut self) -> Membership<'_> {
+        self.get_people();
+        self.get_tags();
+        Membership::new(self.tags.as_ref().unwrap(), self.people.as_ref().unwrap())
+    }
+
+    /// Statistics of the personas and tags from the letters' send history.
+    pub fn contact_report(&mut self) -> ContactReport {
+        self.get_letters();
+        self.get_people();
+        self.get_tags();
+        let mut membership =
+            Membership::new(self.tags.as_ref().unwrap(), self.people.as_ref().unwrap());
+        ContactReport::build(self.letters.as_ref().unwrap(), &mut membership)
+    }
+
+    pub fn get_drafts_mut(&mut self) -> &mut DraftJournal {
+        let path = self.dir.join(DRAFTS_PATH);
+        self.drafts.get_or_insert_with(|| DraftJournal::open(path))
+    }
+
+    pub fn get_letters(&mut self) -> &LetterContainer {
+        match self.letters {
+            Some(ref letters) => letters,
+            None => {
+                self.letters = Some(self.load::<LetterRepr>());
+                self.get_letters()
+            }
+        }
+    }
+
+    pub fn get_letters_mut(&mut self) -> &mut LetterContainer {
+        match self.letters {
+            Some(ref mut letters) => letters,
+            None => {
+                self.letters = Some(self.load::<LetterRepr>());
+                self.get_letters_mut()
+            }
+        }
+    }
+
+    pub fn get_templates(&mut self) -> &TemplateContainer {
+        match self.templates {
+            Some(ref templates) => templates,
+            None => {
+                self.templates = Some(self.load::<TemplateRepr>());
+                self.get_templates()
+            }
+        }
+    }
+
+    pub fn get_templates_mut(&mut self) -> &mut TemplateContainer {
+        match self.templates {
+            Some(ref mut templates) => templates,
+            None => {
+                self.templates = Some(self.load::<TemplateRepr>());
+                self.get_templates_mut()
+            }
+        }
+    }
+
+    pub fn get_trash_mut<R: Stored>(&mut self) -> &mut Trash<R> {
+        if R::trash_slot(self).is_none() {
+            let trash = self.load_trash();
+            *R::trash_slot(self) = Some(trash);
+        }
+        R::trash_slot(self).get_or_insert_with(Trash::new)
+    }
+
+    /// Moves the record to the trash; returns `false` if there was no such record.
+    pub fn move_to_trash<R: Stored>(&mut self, identity: impl AsRef<str>) -> bool {
+        let mut trash = std::mem::take(self.get_trash_mut::<R>());
+        let moved = trash.take_from(R::container(self), identity);
+        *self.get_trash_mut::<R>() = trash;
+        moved
+    }
+
+    /// Moves the records to the trash as one batch, which Undo brings back
+    /// together; returns how many were moved.
+    pub fn move_all_to_trash<R: Stored>(
+        &mut self,
+        identities: impl IntoIterator<Item = impl AsRef<str>>,
+    ) -> usize {
+        let mut trash = std::mem::take(self.get_trash_mut::<R>());
+        let moved = trash.take_all_from(R::container(self), identities);
+        *self.get_trash_mut::<R>() = trash;
+        moved
+    }
+
+    pub fn restore_from_trash<R: Stored>(&mut self, index: usize) -> Option<Identity> {
+        let mut trash = std::mem::take(self.get_trash_mut::<R>());
+        let restored = trash.restore_into(R::container(self), index);
+        *self.get_trash_mut::<R>() = trash;
+        restored
     }
 
-    fn restore_tags() -> io::Result<TagContainer> {
-        TagContainer::restore(TAG_CONTAINER_PATH)
+    pub fn purge_from_trash<R: Stored>(&mut self, index: usize) -> bool {
+        self.get_trash_mut::<R>().purge(index).is_some()
     }
 
-    fn _restore_letters() -> io::Result<LetterContainer> {
-        todo!()
+    /// Brings back the most recently removed records of any kind, the whole
+    /// batch if they were removed together.
+    pub fn undo_removal(&mut self) -> Option<(DataFile, Vec<Identity>)> {
+        let newest = [
+            self.get_trash_mut::<PersonaRepr>().last_removed().cloned(),
+            self.get_trash_mut::<TagRepr>().last_removed().cloned(),
+            self.get_trash_mut::<LetterRepr>().last_removed().cloned(),
+            self.get_trash_mut::<TemplateRepr>().last_removed().cloned(),
+        ]
+        .into_iter()
+        .zip(DataFile::ALL)
+        .filter_map(|(removed, file)| removed.map(|removed| (removed, file)))
+        .max_by_key(|(removed, _)| *removed)
+        .map(|(_, file)| file)?;
+        let restored = match newest {
+            DataFile::People => self.restore_last::<PersonaRepr>(),
+            DataFile::Tags => self.restore_last::<TagRepr>(),
+            DataFile::Letters => self.restore_last::<LetterRepr>(),
+            DataFile::Templates => self.restore_last::<TemplateRepr>(),
+        };
+        Some((newest, restored))
     }
 
-    pub fn finalize(&mut self) {
-        if let Some(ref people) = self.people {
-            people.finalize(PERSONA_CONTAINER_PATH).unwrap();
+    pub fn trash_items(&mut self) -> Vec<TrashItem> {
+        let mut items = self.trash_items_of::<PersonaRepr>();
+        items.extend(self.trash_items_of::<TagRepr>());
+        items.extend(self.trash_items_of::<LetterRepr>());
+        items.extend(self.trash_items_of::<TemplateRepr>());
+        items
+    }
+
+    fn trash_items_of<R: Stored>(&mut self) -> Vec<TrashItem> {
+        self.get_trash_mut::<R>()
+            .items()
+            .enumerate()
+            .map(|(index, trashed)| TrashItem {
+                file: R::FILE,
+                index,
+                label: trashed.item.borrow().describe(),
+                removed: trashed.removed,
+            })
+            .collect()
+    }
+
+    fn restore_last<R: Stored>(&mut self) -> Vec<Identity> {
+        let newest = self.get_trash_mut::<R>().last_removed().cloned();
+        let mut restored = vec![];
+        while newest.is_some() && self.get_trash_mut::<R>().last_removed() == newest.as_ref() {
+            let last = self.get_trash_mut::<R>().size() - 1;
+            restored.extend(self.restore_from_trash::<R>(last));
         }
-        if let Some(ref tags) = self.tags {
-            tags.finalize(TAG_CONTAINER_PATH).unwrap();
+        restored.reverse();
+        restored
+    }
+
+    /// An unreadable trash is copied aside and started anew.
+    fn load_trash<R: Stored>(&self) -> Trash<R> {
+        let path = self.dir.join(R::FILE.trash_file_name());
+        match Trash::restore(&path) {
+            Ok(trash) => trash,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Trash::new(),
+            Err(_) => {
+                preserve_aside(&path, "corrupt").ok();
+                Trash::new()
+            }
         }
     }
+
+    fn save_trash<R: Stored>(&mut self) -> io::Result<()> {
+        let path = self.dir.join(R::FILE.trash_file_name());
+        match R::trash_slot(self) {
+            Some(trash) => trash.finalize(path),
+            None => Ok(()),
+        }
+    }
+
+    /// Loads every container so that load errors show up at once.
+    pub fn load_all(&mut self) {
+        self.get_people();
+        self.get_tags();
+        self.get_letters();
+        self.get_templates();
+    }
+
+    pub fn take_load_errors(&mut self) -> Vec<LoadError> {
+        self.load_errors.drain(..).collect()
+    }
+
+    /// A file that failed to load is never overwritten until it is loaded
+    /// again or replaced by a backup.
+    pub fn is_protected(&self, file: DataFile) -> bool {
+        self.protected.contains(&file)
+    }
+
+    pub fn retry_load(&mut self, file: DataFile) -> Result<(), LoadError> {
+        let path = self.path(file);
+        self.reload(file, path)
+    }
+
+    pub fn load_backup(&mut self, file: DataFile, path: impl AsRef<Path>) -> Result<(), LoadError> {
+        self.reload(file, path.as_ref().to_path_buf())
+    }
+
+    /// Backups of every data file, newest first within a file.
+    pub fn backups(&self) -> io::Result<Vec<(DataFile, PathBuf)>> {
+        let mut backups = vec![];
+        for file in DataFile::ALL {
+            for backup in backup::list_backups(self.path(file))? {
+                backups.push((file, backup));
+            }
+        }
+        Ok(backups)
+    }
+
+    fn reload(&mut self, file: DataFile, path: PathBuf) -> Result<(), LoadError> {
+        let result = match file {
+            DataFile::People => self.reload_from::<PersonaRepr>(&path),
+            DataFile::Tags => self.reload_from::<TagRepr>(&path),
+            DataFile::Letters => self.reload_from::<LetterRepr>(&path),
+            DataFile::Templates => self.reload_from::<TemplateRepr>(&path),
+        };
+        match result {
+            Ok(()) => {
+                self.protected.retain(|protected| *protected != file);
+                self.backed_up.retain(|backed_up| *backed_up != file);
+                self.stamps.insert(file, disk_stamp(&self.path(file)));
+                Ok(())
+            }
+            Err(error) => Err(LoadError {
+                file,
+                path,
+                error,
+                preserved: None,
+                conflict: false,
+            }),
+        }
+    }
+
+    fn reload_from<R: Stored>(&mut self, path: &Path) -> io::Result<()> {
+        let (container, _) = self.storage::<R>().load_from(path)?;
+        *R::slot(self) = Some(container);
+        Ok(())
+    }
+
+    fn storage<R: Stored>(&mut self) -> &mut Box<dyn Storage<R>> {
+        let (kind, path) = (self.storage, self.path(R::FILE));
+        R::storage_slot(self).get_or_insert_with(|| kind.open(path))
+    }
+
+    /// A missing file is an empty container; an unreadable one is copied
+    /// aside, reported and protected from `finalize`. A file of an older
+    /// version is backed up before it is upgraded.
+    fn load<R: Stored>(&mut self) -> DataContainer<R> {
+        let file = R::FILE;
+        let path = self.path(file);
+        self.stamps.insert(file, disk_stamp(&path));
+        match self.storage::<R>().load_from(&path) {
+            Ok((container, version)) => {
+                if version < R::version() && !self.read_only && backup::keep_backup(&path).is_ok() {
+                    self.backed_up.push(file);
+                }
+                container
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => DataContainer::new(),
+            Err(error) => {
+                let preserved = preserve_aside(&path, "corrupt").ok();
+                self.protected.push(file);
+                self.load_errors.push(LoadError {
+                    file,
+                    path,
+                    error,
+                    preserved,
+                    conflict: false,
+                });
+                DataContainer::new()
+            }
+        }
+    }
+
+    /// The data file of `file` for the chosen backend.
+    pub fn path(&self, file: DataFile) -> PathBuf {
+        self.dir
+            .join(file.file_name())
+            .with_extension(self.storage.extension())
+    }
+
+    /// Leaves `file` empty and protected after a failed load or a conflict.
+    pub fn start_empty(&mut self, file: DataFile) {
+        match file {
+            DataFile::People => self.people = Some(DataContainer::new()),
+            DataFile::Tags => self.tags = Some(DataContainer::new()),
+            DataFile::Letters => self.letters = Some(DataContainer::new()),
+            DataFile::Templates => self.templates = Some(DataContainer::new()),
+        }
+        if !self.is_protected(file) {
+            self.protected.push(file);
+        }
+    }
+
+    /// Saves the loaded containers. Only the first save of a session that
+    /// changes an existing file backs it up, so autosaving does not wash out
+    /// the backups from before the session.
+    /// Nothing is written in read-only mode.
+    pub fn finalize(&mut self) -> io::Result<()> {
+        if self.read_only {
+            return Ok(());
+        }
+        self.save_container::<PersonaRepr>()?;
+        self.save_container::<TagRepr>()?;
+        self.save_container::<LetterRepr>()?;
+        self.save_container::<TemplateRepr>()?;
+        self.save_trash::<PersonaRepr>()?;
+        self.save_trash::<TagRepr>()?;
+        self.save_trash::<LetterRepr>()?;
+        self.save_trash::<TemplateRepr>()
+    }
+
+    /// Writes the drafts journal unless the handler is read-only.
+    pub fn flush_drafts(&mut self, force: bool) -> io::Result<()> {
+        if self.read_only {
+            return Ok(());
+        }
+        let drafts = self.get_drafts_mut();
+        if force {
+            drafts.flush()
+        } else {
+            drafts.flush_due()
+        }
+    }
+
+    /// Writes our version of the container next to `path` in the same format.
+    fn preserve_conflict<R: Stored>(&mut self, path: &Path) -> io::Result<PathBuf> {
+        let preserved = aside_path(path, "conflict");
+        let mut storage = self.storage.open::<R>(preserved.clone());
+        if let Some(container) = R::loaded(self) {
+            storage.save(container, false)?;
+        }
+        Ok(preserved)
+    }
+
+    /// A file changed on disk since we read it is not overwritten: our
+    /// version goes next to it and the conflict is reported as a load error.
+    fn save_container<R: Stored>(&mut self) -> io::Result<()> {
+        let file = R::FILE;
+        if R::loaded(self).is_none() || self.is_protected(file) {
+            return Ok(());
+        }
+        let path = self.path(file);
+        if self.stamps.get(&file).copied().flatten() != disk_stamp(&path) {
+            let preserved = self.preserve_conflict::<R>(&path).ok();
+            self.protected.push(file);
+            self.load_errors.push(LoadError {
+                file,
+                error: io::Error::other("файл изменён на диске"),
+                path,
+                preserved,
+                conflict: true,
+            });
+            return Ok(());
+        }
+        let keep_backup = !self.backed_up.contains(&file);
+        let before = disk_stamp(&path);
+        let mut storage = R::storage_slot(self)
+            .take()
+            .unwrap_or_else(|| self.storage.open(path.clone()));
+        let saved = match R::loaded(self) {
+            Some(container) => storage.save(container, keep_backup),
+            None => Ok(()),
+        };
+        *R::storage_slot(self) = Some(storage);
+        saved?;
+        if keep_backup && before.is_some() && disk_stamp(&path) != before {
+            self.backed_up.push(file);
+        }
+        self.stamps.insert(file, disk_stamp(&path));
+        Ok(())
+    }
+}
+
+impl Default for DataHandler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn disk_stamp(path: &Path) -> Option<Stamp> {
+    let metadata = fs::metadata(path).ok()?;
+    Some((metadata.len(), metadata.modified().ok()?))
+}
+
+/// `<name>.<suffix>-<timestamp>` next to `path`.
+fn aside_path(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(format!(
+        ".{}-{}",
+        suffix,
+        chrono::Local::now().format("%Y%m%d-%H%M%S")
+    ));
+    path.with_file_name(name)
+}
+
+/// Copies `path` to `<name>.<suffix>-<timestamp>` next to it.
+fn preserve_aside(path: &Path, suffix: &str) -> io::Result<PathBuf> {
+    let preserved = aside_path(path, suffix);
+    fs::copy(path, &preserved)?;
+    Ok(preserved)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::TempDir;
+
+    #[test]
+    fn test_corrupt_file_is_kept() {
+        let dir = TempDir::new("handler");
+        let path = dir.join(PERSONA_CONTAINER_PATH);
+        fs::write(&path, "[{broken").unwrap();
+
+        let mut data_handler = DataHandler::with_dir(&dir);
+        data_handler.load_all();
+        let errors = data_handler.take_load_errors();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].file, DataFile::People);
+        let preserved = errors[0].preserved.clone().unwrap();
+        assert_eq!(fs::read_to_string(preserved).unwrap(), "[{broken");
+
+        data_handler.finalize().unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "[{broken");
+
+        let backup = dir.join("backup.json");
+        fs::write(&backup, "[]").unwrap();
+        data_handler.load_backup(DataFile::People, &backup).unwrap();
+        assert!(!data_handler.is_protected(DataFile::People));
+        data_handler.finalize().unwrap();
+        assert!(fs::read_to_string(&path)
+            .unwrap()
+            .contains("\"records\":[]"));
+    }
+
+    #[test]
+    fn test_changed_file_is_not_overwritten() {
+        let dir = TempDir::new("handler");
+        let path = dir.join(TAG_CONTAINER_PATH);
+        let mut data_handler = DataHandler::with_dir(&dir);
+        data_handler
+            .get_tags_mut()
+            .insert_or_update(crate::data_handler::tag::new_tag("Совет"));
+        data_handler.finalize().unwrap();
+
+        fs::write(&path, "[] ").unwrap();
+        data_handler
+            .get_tags_mut()
+            .insert_or_update(crate::data_handler::tag::new_tag("Кафедра"));
+        data_handler.finalize().unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "[] ");
+        let errors = data_handler.take_load_errors();
+        assert!(errors[0].conflict);
+        let ours = fs::read_to_string(errors[0].preserved.as_ref().unwrap()).unwrap();
+        assert!(ours.contains("Кафедра"));
+    }
+
+    #[test]
+    fn test_trash_and_undo() {
+        let dir = TempDir::new("handler");
+        let mut data_handler = DataHandler::with_dir(&dir);
+        let tags = data_handler.get_tags_mut();
+        tags.insert_or_update(crate::data_handler::tag::new_tag("Совет"));
+        tags.insert_or_update(crate::data_handler::tag::new_tag("Кафедра"));
+        assert!(data_handler.move_to_trash::<TagRepr>("Совет"));
+        assert!(!data_handler.move_to_trash::<TagRepr>("Совет"));
+        assert_eq!(data_handler.get_tags().size(), 1);
+        data_handler.finalize().unwrap();
+
+        let mut reopened = DataHandler::with_dir(&dir);
+        assert_eq!(
+            reopened.undo_removal(),
+            Some((DataFile::Tags, vec!["Совет".to_owned()]))
+        );
+        assert_eq!(reopened.get_tags().size(), 2);
+        assert_eq!(reopened.get_trash_mut::<TagRepr>().size(), 0);
+    }
+
+    #[test]
+    fn test_autosaves_keep_backup_from_before_session() {
+        let dir = TempDir::new("handler");
+        let path = dir.join(TAG_CONTAINER_PATH);
+        let mut data_handler = DataHandler::with_dir(&dir);
+        data_handler
+            .get_tags_mut()
+            .insert_or_update(crate::data_handler::tag::new_tag("Совет"));
+        data_handler.finalize().unwrap();
+        let before_session = fs::read_to_string(&path).unwrap();
+
+        let mut data_handler = DataHandler::with_dir(&dir);
+        for n in 0..=backup::BACKUP_COUNT {
+            data_handler
+                .get_tags_mut()
+                .insert_or_update(crate::data_handler::tag::new_tag(format!("Метка {}", n)));
+            data_handler.finalize().unwrap();
+        }
+        let backups = backup::list_backups(&path).unwrap();
+        assert_eq!(backups.len(), 1);
+        assert_eq!(fs::read_to_string(&backups[0]).unwrap(), before_session);
+    }
+
+    #[test]
+    fn test_convert_to_journal() {
+        let dir = TempDir::new("handler");
+        let mut data_handler = DataHandler::with_dir(&dir);
+        data_handler
+            .get_tags_mut()
+            .insert_or_update(crate::data_handler::tag::new_tag("Совет"));
+        data_handler.finalize().unwrap();
+
+        data_handler.convert(StorageKind::Journal).unwrap();
+        data_handler
+            .get_tags_mut()
+            .insert_or_update(crate::data_handler::tag::new_tag("Кафедра"));
+        data_handler.finalize().unwrap();
+        assert!(dir.join(TAG_CONTAINER_PATH).exists());
+        let journal = fs::read_to_string(dir.join("tag.journal")).unwrap();
+        assert_eq!(journal.lines().count(), 3);
+
+        let mut reopened = DataHandler::with_dir(&dir);
+        reopened.set_storage(StorageKind::Journal);
+        assert_eq!(reopened.get_tags().size(), 2);
+    }
+
+    #[test]
+    fn test_convert_to_database() {
+        let dir = TempDir::new("handler");
+        let mut data_handler = DataHandler::with_dir(&dir);
+        data_handler
+            .get_tags_mut()
+            .insert_or_update(crate::data_handler::tag::new_tag("Совет"));
+        data_handler.convert(StorageKind::Database).unwrap();
+        data_handler
+            .get_tags_mut()
+            .insert_or_update(crate::data_handler::tag::new_tag("Кафедра"));
+        data_handler.finalize().unwrap();
+        assert!(data_handler.take_load_errors().is_empty());
+
+        let mut reopened = DataHandler::with_dir(&dir);
+        reopened.set_storage(StorageKind::Database);
+        assert_eq!(reopened.get_tags().size(), 2);
+        reopened.get_tags_mut().remove_identity("Совет");
+        reopened.finalize().unwrap();
+        assert!(reopened.take_load_errors().is_empty());
+    }
+
+    #[test]
+    fn test_old_version_is_backed_up() {
+        let dir = TempDir::new("handler");
+        let path = dir.join(TAG_CONTAINER_PATH);
+        let legacy = include_str!("fixtures/tag.v0.json");
+        fs::write(&path, legacy).unwrap();
+
+        let mut data_handler = DataHandler::with_dir(&dir);
+        assert_eq!(data_handler.get_tags().size(), 1);
+        data_handler.finalize().unwrap();
+        let backups = backup::list_backups(&path).unwrap();
+        assert_eq!(backups.len(), 1);
+        assert_eq!(fs::read_to_string(&backups[0]).unwrap(), legacy);
+        let expected = format!("{{\"version\":{},", TagRepr::version());
+        assert!(fs::read_to_string(&path).unwrap().starts_with(&expected));
+    }
 }
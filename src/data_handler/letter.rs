@@ -1,20 +1,111 @@
-use std::{cell::RefCell, io, path::Path, rc::Rc};
+use std::{cell::RefCell, fmt, io, path::Path, rc::Rc};
 
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
 
-use super::{attached_file::AttachedFile, data_container::DataContainer, Identity, Represent};
+use super::{
+    attached_file::{self, AttachedFile},
+    data_container::DataContainer,
+    schema::{self, Migration, Versioned},
+    Identity, Represent,
+};
 
 pub type LetterContainer = DataContainer<LetterRepr>;
 pub type Letter = Rc<RefCell<LetterRepr>>;
 pub type DateTime = chrono::DateTime<chrono::Local>;
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct LetterRepr {
     time: DateTime,
     topic: String,
     text: String,
     attachment: Vec<AttachedFile>,
+    schedule: Option<Schedule>,
+    state: LetterState,
+    /// Every address the letter was ever mailed to.
+    recipients: Vec<String>,
+    /// The outcome for every address of every mailing.
+    deliveries: Vec<Delivery>,
 }
+
+/// One address of a mailing: delivered, or the error it failed with.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Delivery {
+    pub at: DateTime,
+    pub address: String,
+    pub error: Option<String>,
+}
+
+/// Where the letter is on its way to the recipients.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LetterState {
+    #[default]
+    Draft,
+    Scheduled,
+    Queued,
+    Sent,
+    PartiallyFailed,
+    Failed,
+}
+
+impl LetterState {
+    pub const ALL: [LetterState; 6] = [
+        LetterState::Draft,
+        LetterState::Scheduled,
+        LetterState::Queued,
+        LetterState::Sent,
+        LetterState::PartiallyFailed,
+        LetterState::Failed,
+    ];
+
+    /// The name used on the command line.
+    pub fn name(&self) -> &'static str {
+        match self {
+            LetterState::Draft => "draft",
+            LetterState::Scheduled => "scheduled",
+            LetterState::Queued => "queued",
+            LetterState::Sent => "sent",
+            LetterState::PartiallyFailed => "partially_failed",
+            LetterState::Failed => "failed",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|state| state.name() == name)
+    }
+}
+
+impl fmt::Display for LetterState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LetterState::Draft => write!(f, "черновик"),
+            LetterState::Scheduled => write!(f, "запланировано"),
+            LetterState::Queued => write!(f, "отправляется"),
+            LetterState::Sent => write!(f, "отправлено"),
+            LetterState::PartiallyFailed => write!(f, "доставлено не всем"),
+            LetterState::Failed => write!(f, "не отправлено"),
+        }
+    }
+}
+
+/// A mailing of the letter waiting for its time.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Schedule {
+    pub at: DateTime,
+    /// The sender profile.
+    pub profile: String,
+    pub to: Vec<String>,
+    pub cc: Vec<String>,
+    pub bcc: Vec<String>,
+}
+
+impl Schedule {
+    pub fn count(&self) -> usize {
+        self.to.len() + self.cc.len() + self.bcc.len()
+    }
+}
+
 impl LetterRepr {
     pub fn new() -> Self {
         let now = chrono::Local::now();
@@ -23,6 +114,10 @@ impl LetterRepr {
             topic: String::new(),
             text: String::new(),
             attachment: vec![],
+            schedule: None,
+            state: LetterState::Draft,
+            recipients: vec![],
+            deliveries: vec![],
         }
     }
 
@@ -47,22 +142,11 @@ impl LetterRepr {
     }
 
     pub fn attachment_info(&self) -> String {
-        let info = self
-            .attachment
-            .iter()
-            .map(|attached_file| {
-                format!(
-                    "[{} ({} байт)]",
-                    attached_file.get_filename(),
-                    attached_file.get_size()
-                )
-            })
-            .collect::<Vec<_>>();
-        if info.is_empty() {
-            "[Вложений нет]".to_string()
-        } else {
-            info.join(" ")
-        }
+        attached_file::attachment_info(self.attachment.iter())
+    }
+
+    pub fn attachments(&self) -> impl Iterator<Item = &AttachedFile> + '_ {
+        self.attachment.iter()
     }
 
     pub fn add_attachment_from_path(&mut self, path: impl AsRef<Path>) -> io::Result<()> {
@@ -71,9 +155,154 @@ impl LetterRepr {
         Ok(())
     }
 
+    pub fn set_attachments(&mut self, attachment: Vec<AttachedFile>) {
+        self.attachment = attachment;
+    }
+
     pub fn clear_attachment(&mut self) {
         self.attachment.clear();
     }
+
+    pub fn schedule(&self) -> Option<&Schedule> {
+        self.schedule.as_ref()
+    }
+
+    /// Scheduling makes the letter scheduled, unscheduling a draft again.
+    pub fn set_schedule(&mut self, schedule: Option<Schedule>) {
+        if schedule.is_some() {
+            self.state = LetterState::Scheduled;
+        } else if self.state == LetterState::Scheduled {
+            self.state = LetterState::Draft;
+        }
+        self.schedule = schedule;
+    }
+
+    pub fn recipients(&self) -> &[String] {
+        &self.recipients
+    }
+
+    /// Remembers the addresses of a mailing, each only once.
+    pub fn add_recipients<'a>(&mut self, addresses: impl IntoIterator<Item = &'a String>) {
+        for address in addresses {
+            if !self.recipients.contains(address) {
+                self.recipients.push(address.to_owned());
+            }
+        }
+    }
+
+    pub fn deliveries(&self) -> &[Delivery] {
+        &self.deliveries
+    }
+
+    pub fn add_delivery(&mut self, delivery: Delivery) {
+        self.deliveries.push(delivery);
+    }
+
+    pub fn state(&self) -> LetterState {
+        self.state
+    }
+
+    /// The mailing of the letter has started.
+    pub fn mark_queued(&mut self) {
+        self.schedule = None;
+        self.state = LetterState::Queued;
+    }
+
+    /// The mailing is over; a letter nobody got nor failed to get (every
+    /// message cancelled) is a draft again.
+    pub fn mark_sent(&mut self, sent: usize, failed: usize) {
+        self.state = match (sent, failed) {
+            (0, 0) => LetterState::Draft,
+            (_, 0) => LetterState::Sent,
+            (0, _) => LetterState::Failed,
+            _ => LetterState::PartiallyFailed,
+        };
+    }
+
+    /// The mailing could not start at all.
+    pub fn mark_failed(&mut self) {
+        self.state = LetterState::Failed;
+    }
+
+    /// Scheduled for `now` or earlier.
+    pub fn is_due(&self, now: &DateTime) -> bool {
+        self.schedule
+            .as_ref()
+            .map(|schedule| schedule.at <= *now)
+            .unwrap_or(false)
+    }
+}
+
+impl Default for LetterRepr {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Versioned for LetterRepr {
+    const MIGRATIONS: &'static [Migration] = &[
+        schema::add_envelope,
+        add_schedule,
+        add_state,
+        add_recipients,
+        add_deliveries,
+    ];
+}
+
+/// Version 1 letters could not be scheduled.
+fn add_schedule(record: &mut Value) {
+    if let Some(letter) = record.as_object_mut() {
+        letter.entry("schedule").or_insert(Value::Null);
+    }
+}
+
+/// Version 2 letters had no state; only the scheduled ones can be told apart.
+fn add_state(record: &mut Value) {
+    if let Some(letter) = record.as_object_mut() {
+        let state = match letter.get("schedule") {
+            Some(Value::Null) | None => LetterState::Draft,
+            Some(_) => LetterState::Scheduled,
+        };
+        letter.entry("state").or_insert(Value::from(state.name()));
+    }
+}
+
+/// Version 3 letters did not keep their addresses.
+fn add_recipients(record: &mut Value) {
+    if let Some(letter) = record.as_object_mut() {
+        letter.entry("recipients").or_insert(Value::Array(vec![]));
+    }
+}
+
+/// Version 4 letters had no send history.
+fn add_deliveries(record: &mut Value) {
+    if let Some(letter) = record.as_object_mut() {
+        letter.entry("deliveries").or_insert(Value::Array(vec![]));
+    }
+}
+
+pub const DATE_FORMAT: &str = "%d.%m.%Y";
+
+/// Reads a date written as [`DATE_FORMAT`].
+pub fn parse_date(text: &str) -> Option<chrono::NaiveDate> {
+    chrono::NaiveDate::parse_from_str(text.trim(), DATE_FORMAT).ok()
+}
+
+/// Which letters to list: the state and the days the letter was written in.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LetterFilter {
+    pub state: Option<LetterState>,
+    pub since: Option<chrono::NaiveDate>,
+    pub until: Option<chrono::NaiveDate>,
+}
+
+impl LetterFilter {
+    pub fn matches(&self, letter: &LetterRepr) -> bool {
+        let day = letter.time.date_naive();
+        self.state.is_none_or(|state| state == letter.state)
+            && self.since.is_none_or(|since| since <= day)
+            && self.until.is_none_or(|until| day <= until)
+    }
 }
 
 impl Represent for LetterRepr {
@@ -100,4 +329,54 @@ mod tests {
         let letter = container.all_representations().next().unwrap();
         assert_eq!(time, &letter.borrow().identity());
     }
+
+    #[test]
+    fn test_state_transitions() {
+        let mut letter = LetterRepr::new();
+        assert_eq!(letter.state(), LetterState::Draft);
+        let schedule = Schedule {
+            at: chrono::Local::now(),
+            profile: String::new(),
+            to: vec!["ivanov@mipt.ru".into()],
+            cc: vec![],
+            bcc: vec![],
+        };
+        letter.set_schedule(Some(schedule));
+        assert_eq!(letter.state(), LetterState::Scheduled);
+        letter.set_schedule(None);
+        assert_eq!(letter.state(), LetterState::Draft);
+        letter.mark_queued();
+        assert_eq!(letter.state(), LetterState::Queued);
+        letter.mark_sent(2, 1);
+        assert_eq!(letter.state(), LetterState::PartiallyFailed);
+        letter.set_schedule(None);
+        assert_eq!(letter.state(), LetterState::PartiallyFailed);
+        letter.mark_sent(0, 3);
+        assert_eq!(letter.state(), LetterState::Failed);
+        letter.mark_sent(3, 0);
+        assert_eq!(letter.state(), LetterState::Sent);
+    }
+
+    #[test]
+    fn test_filter() {
+        let mut letter = LetterRepr::new();
+        let today = letter.get_time().date_naive();
+        let mut filter = LetterFilter {
+            state: Some(LetterState::Draft),
+            since: Some(today),
+            until: None,
+        };
+        assert!(filter.matches(&letter));
+        filter.until = today.pred_opt();
+        assert!(!filter.matches(&letter));
+        filter.until = None;
+        letter.mark_sent(1, 0);
+        assert!(!filter.matches(&letter));
+        filter.state = Some(LetterState::Sent);
+        assert!(filter.matches(&letter));
+        assert_eq!(
+            LetterState::from_name("partially_failed"),
+            Some(LetterState::PartiallyFailed)
+        );
+    }
 }
@@ -0,0 +1,85 @@
+use std::{
+    fmt,
+    fs::{self, File, OpenOptions, TryLockError},
+    io::{self, Write},
+    path::Path,
+};
+
+/// The lock file in the data directory.
+pub const LOCK_FILE: &str = ".letcom.lock";
+
+/// An advisory lock on the data directory, released when dropped.
+#[derive(Debug)]
+pub struct DataLock {
+    _file: File,
+}
+
+#[derive(Debug)]
+pub enum LockError {
+    /// Another instance holds the lock; the text tells which one.
+    Busy(String),
+    Io(io::Error),
+}
+
+impl fmt::Display for LockError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LockError::Busy(holder) => {
+                write!(f, "данные уже открыты другим экземпляром ({})", holder)
+            }
+            LockError::Io(e) => write!(f, "не удалось заблокировать каталог данных: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for LockError {}
+
+impl DataLock {
+    pub fn acquire(dir: impl AsRef<Path>) -> Result<Self, LockError> {
+        let path = dir.as_ref().join(LOCK_FILE);
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&path)
+            .map_err(LockError::Io)?;
+        match file.try_lock() {
+            Ok(()) => {}
+            Err(TryLockError::WouldBlock) => {
+                let holder = fs::read_to_string(&path).unwrap_or_default();
+                return Err(LockError::Busy(holder.trim().to_owned()));
+            }
+            Err(TryLockError::Error(e)) => return Err(LockError::Io(e)),
+        }
+        file.set_len(0).map_err(LockError::Io)?;
+        write!(
+            file,
+            "pid {}, с {}",
+            std::process::id(),
+            chrono::Local::now().format("%d.%m.%Y %H:%M")
+        )
+        .map_err(LockError::Io)?;
+        Ok(Self { _file: file })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::TempDir;
+
+    #[test]
+    fn test_second_lock_is_refused() {
+        let dir = TempDir::new("lock");
+        let lock = DataLock::acquire(&dir).unwrap();
+        match DataLock::acquire(&dir) {
+            Err(LockError::Busy(holder)) => {
+                assert!(holder.contains(&std::process::id().to_string()))
+            }
+            other => panic!("expected busy lock, got {:?}", other),
+        }
+        drop(lock);
+        assert!(DataLock::acquire(&dir).is_ok());
+    }
+}
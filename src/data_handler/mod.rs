@@ -5,11 +5,22 @@ use std::{
 };
 
 pub mod attached_file;
+pub mod backup;
 pub mod data_container;
+pub mod drafts;
 pub mod handler;
 pub mod letter;
+pub mod lock;
 pub mod persona;
+pub mod report;
+pub mod rule;
+pub mod schema;
+pub mod search;
+pub mod selection;
+pub mod storage;
 pub mod tag;
+pub mod template;
+pub mod trash;
 
 pub type Identity = String;
 
@@ -1,8 +1,19 @@
-use std::{cell::RefCell, fs, path::Path, rc::Rc};
+use std::{
+    cell::RefCell,
+    fs,
+    io::{self, BufRead},
+    path::Path,
+    rc::Rc,
+};
 
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
 
-use super::{data_container::DataContainer, Identity, Represent};
+use super::{
+    data_container::DataContainer,
+    schema::{self, Migration, Versioned},
+    Identity, Represent,
+};
 
 pub type Persona = Rc<RefCell<PersonaRepr>>;
 pub type PersonaContainer = DataContainer<PersonaRepr>;
@@ -13,6 +24,9 @@ pub struct PersonaRepr {
     name: String,
     surname: String,
     email: String,
+    position: String,
+    /// Academic degree, e.g. "д.ф.-м.н.".
+    degree: String,
 }
 
 impl PersonaRepr {
@@ -22,6 +36,8 @@ impl PersonaRepr {
             name: name.into(),
             surname: surname.into(),
             email: email.into(),
+            position: String::new(),
+            degree: String::new(),
         }
     }
 
@@ -56,6 +72,35 @@ impl PersonaRepr {
     pub fn set_email(&mut self, email: impl ToString) {
         self.email = email.to_string();
     }
+
+    pub fn get_position(&self) -> &str {
+        &self.position
+    }
+
+    pub fn set_position(&mut self, position: impl ToString) {
+        self.position = position.to_string();
+    }
+
+    pub fn get_degree(&self) -> &str {
+        &self.degree
+    }
+
+    pub fn set_degree(&mut self, degree: impl ToString) {
+        self.degree = degree.to_string();
+    }
+}
+
+impl Versioned for PersonaRepr {
+    const MIGRATIONS: &'static [Migration] = &[schema::add_envelope, add_position_and_degree];
+}
+
+/// Version 1 did not keep the position and degree columns of the import.
+fn add_position_and_degree(record: &mut Value) {
+    if let Some(persona) = record.as_object_mut() {
+        for field in ["position", "degree"] {
+            persona.entry(field).or_insert_with(|| Value::from(""));
+        }
+    }
 }
 
 impl Represent for PersonaRepr {
@@ -68,29 +113,85 @@ pub fn new_persona() -> Persona {
     Rc::new(RefCell::new(PersonaRepr::new("", "", "", "")))
 }
 
+const FAMILY: usize = 0;
+const NAME: usize = 1;
+const SURNAME: usize = 2;
+const POSITION: usize = 4;
+const DEGREE: usize = 5;
+const _BIRTHDATE: usize = 9;
+const _SHT_SOVM: usize = 12;
+const _STAVKA: usize = 13;
+const _PHONE: usize = 14;
+const EMAIL1: usize = 15;
+const _EMAIL2: usize = 16;
+const _EMAIL3: usize = 17;
+const COLUMNS: usize = 18;
+
 pub fn import_persona(data: &str) -> Option<Persona> {
-    const FAMILY: usize = 0;
-    const NAME: usize = 1;
-    const SURNAME: usize = 2;
-    const _POSITION: usize = 4;
-    const _DEGREE: usize = 5;
-    const _BIRTHDATE: usize = 9;
-    const _SHT_SOVM: usize = 12;
-    const _STAVKA: usize = 13;
-    const _PHONE: usize = 14;
-    const EMAIL1: usize = 15;
-    const _EMAIL2: usize = 16;
-    const _EMAIL3: usize = 17;
     let data = data.split('\t').collect::<Vec<_>>();
-    Some(Rc::new(RefCell::new(PersonaRepr::new(
+    let mut persona = PersonaRepr::new(
         data.get(FAMILY)?.trim(),
         data.get(NAME)?.trim(),
         data.get(SURNAME)?.trim(),
         data.get(EMAIL1)?.trim(),
-    ))))
+    );
+    persona.set_position(data.get(POSITION)?.trim());
+    persona.set_degree(data.get(DEGREE)?.trim());
+    Some(Rc::new(RefCell::new(persona)))
+}
+
+/// Reads a staff table in the layout of 'persona.tsv', skipping the header line.
+pub fn import_persona_file(path: impl AsRef<Path>) -> io::Result<Vec<Persona>> {
+    let f = fs::File::open(path)?;
+    Ok(io::BufReader::new(f)
+        .lines()
+        .skip(1)
+        .filter_map(|x| x.ok())
+        .filter_map(|data| import_persona(&data))
+        .collect())
+}
+
+/// Writes persona in the same column layout `import_persona` reads, header first.
+pub fn export_persona<'a>(persona: impl Iterator<Item = &'a PersonaRepr>) -> String {
+    let mut header = vec![""; COLUMNS];
+    header[FAMILY] = "Фамилия";
+    header[NAME] = "Имя";
+    header[SURNAME] = "Отчество";
+    header[POSITION] = "Должность";
+    header[DEGREE] = "Учёная степень";
+    header[EMAIL1] = "E-mail";
+    let mut lines = vec![header.join("\t")];
+    for persona in persona {
+        let mut row = vec![""; COLUMNS];
+        row[FAMILY] = persona.get_family();
+        row[NAME] = persona.get_name();
+        row[SURNAME] = persona.get_surname();
+        row[POSITION] = persona.get_position();
+        row[DEGREE] = persona.get_degree();
+        row[EMAIL1] = persona.get_email();
+        lines.push(row.join("\t"));
+    }
+    lines.join("\n")
 }
 
 pub fn restore_persona_container(path: impl AsRef<Path>) -> Option<PersonaContainer> {
     let json = fs::read_to_string(path).ok()?;
-    PersonaContainer::from_json(&json).ok()
+    PersonaContainer::from_json(&json)
+        .ok()
+        .map(|(container, _)| container)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_export_import_roundtrip() {
+        let persona = PersonaRepr::new("Иванов", "Иван", "Иванович", "ivanov@mipt.ru");
+        let exported = export_persona([&persona].into_iter());
+        let row = exported.lines().nth(1).unwrap();
+        let imported = import_persona(row).unwrap();
+        assert_eq!(imported.borrow().identity(), persona.identity());
+        assert_eq!(imported.borrow().get_email(), "ivanov@mipt.ru");
+    }
 }
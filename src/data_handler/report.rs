@@ -0,0 +1,267 @@
+use std::{
+    cmp::Reverse,
+    collections::{HashMap, HashSet},
+};
+
+use super::{
+    letter::{DateTime, Delivery, LetterContainer, LetterRepr, LetterState, DATE_FORMAT},
+    make_ref,
+    tag::Membership,
+    Identity, Represent,
+};
+
+/// Mailing statistics of a persona or of the members of a tag.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ContactStats {
+    pub name: String,
+    /// Personas counted: one for a persona, the members for a tag.
+    pub people: usize,
+    /// Letters delivered to at least one of them.
+    pub letters: usize,
+    /// Deliveries that failed.
+    pub failed: usize,
+    pub last_contact: Option<DateTime>,
+    /// Personas no letter was ever delivered to.
+    pub never_reached: usize,
+}
+
+/// Statistics of every persona and tag from the send history of the letters.
+/// Those never reached or with failed deliveries come first.
+#[derive(Debug, Clone, Default)]
+pub struct ContactReport {
+    pub people: Vec<ContactStats>,
+    pub tags: Vec<ContactStats>,
+}
+
+/// What the letters' history says about one address.
+#[derive(Default)]
+struct AddressHistory {
+    letters: HashSet<Identity>,
+    failed: usize,
+    last_contact: Option<DateTime>,
+}
+
+impl ContactReport {
+    /// Letters sent before the send history was kept count as delivered to
+    /// their recipients on the day they were written.
+    pub fn build(letters: &LetterContainer, membership: &mut Membership) -> Self {
+        let mut history = HashMap::<String, AddressHistory>::new();
+        for letter in letters.all_representations() {
+            let letter = make_ref(&letter);
+            for delivery in letter.deliveries().iter().cloned().chain(backfill(&letter)) {
+                let entry = history.entry(address_key(&delivery.address)).or_default();
+                if delivery.error.is_some() {
+                    entry.failed += 1;
+                } else {
+                    entry.letters.insert(letter.identity());
+                    entry.last_contact = entry.last_contact.max(Some(delivery.at));
+                }
+            }
+        }
+        let emails = membership
+            .people()
+            .all_representations()
+            .map(|persona| {
+                let persona = make_ref(&persona);
+                (persona.identity(), address_key(persona.get_email()))
+            })
+            .collect::<HashMap<_, _>>();
+        let stats = |name: Identity, members: &[Identity]| {
+            let mut letters = HashSet::new();
+            let mut stats = ContactStats {
+                name,
+                people: members.len(),
+                letters: 0,
+                failed: 0,
+                last_contact: None,
+                never_reached: 0,
+            };
+            for member in members {
+                let found = emails.get(member).and_then(|email| history.get(email));
+                match found {
+                    Some(found) if !found.letters.is_empty() => {
+                        letters.extend(found.letters.iter().cloned());
+                        stats.last_contact = stats.last_contact.max(found.last_contact);
+                    }
+                    _ => stats.never_reached += 1,
+                }
+                stats.failed += found.map_or(0, |found| found.failed);
+            }
+            stats.letters = letters.len();
+            stats
+        };
+        let mut people = emails
+            .keys()
+            .map(|identity| stats(identity.to_owned(), &[identity.to_owned()]))
+            .collect::<Vec<_>>();
+        let labels = membership.tags().idendities().cloned().collect::<Vec<_>>();
+        let mut tags = labels
+            .into_iter()
+            .map(|label| {
+                let members = membership.members(&label);
+                stats(label, &members)
+            })
+            .collect::<Vec<_>>();
+        for list in [&mut people, &mut tags] {
+            list.sort_by_key(|stats| {
+                (
+                    Reverse(stats.never_reached),
+                    Reverse(stats.failed),
+                    stats.name.clone(),
+                )
+            });
+        }
+        Self { people, tags }
+    }
+
+    /// A table with a row for every persona and tag.
+    pub fn to_csv(&self) -> String {
+        let mut lines = vec!["Тип,Имя,Персон,Писем,Последнее письмо,Ошибок,Не получили".to_owned()];
+        let rows = self
+            .people
+            .iter()
+            .map(|stats| ("персона", stats))
+            .chain(self.tags.iter().map(|stats| ("метка", stats)));
+        for (kind, stats) in rows {
+            lines.push(
+                [
+                    kind.to_owned(),
+                    csv_field(&stats.name),
+                    stats.people.to_string(),
+                    stats.letters.to_string(),
+                    last_contact(stats),
+                    stats.failed.to_string(),
+                    stats.never_reached.to_string(),
+                ]
+                .join(","),
+            );
+        }
+        lines.join("\n")
+    }
+}
+
+pub fn last_contact(stats: &ContactStats) -> String {
+    stats
+        .last_contact
+        .map(|at| at.format(DATE_FORMAT).to_string())
+        .unwrap_or_default()
+}
+
+fn address_key(address: &str) -> String {
+    address.trim().to_lowercase()
+}
+
+/// Quoted when it holds a comma, a quote or a line break.
+fn csv_field(text: &str) -> String {
+    if text.contains([',', '"', '\n']) {
+        format!("\"{}\"", text.replace('"', "\"\""))
+    } else {
+        text.to_owned()
+    }
+}
+
+/// Deliveries of a letter sent before deliveries were recorded, made up
+/// from its recipients.
+fn backfill(letter: &LetterRepr) -> Vec<Delivery> {
+    if letter.state() != LetterState::Sent || !letter.deliveries().is_empty() {
+        return vec![];
+    }
+    letter
+        .recipients()
+        .iter()
+        .map(|address| Delivery {
+            at: *letter.get_time(),
+            address: address.to_owned(),
+            error: None,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data_handler::{
+        letter::{new_letter, Delivery},
+        make_mut,
+        persona::{new_persona, PersonaContainer, PersonaRepr},
+        tag::{new_tag, TagContainer},
+    };
+
+    #[test]
+    fn test_report_counts_deliveries() {
+        let mut people = PersonaContainer::new();
+        for (family, email) in [("Иванов", "ivanov@mipt.ru"), ("Петров", "petrov@mpit.ru")]
+        {
+            let persona = new_persona();
+            *make_mut(&persona) = PersonaRepr::new(family, "", "", email);
+            people.insert_or_update(persona);
+        }
+        let mut tags = TagContainer::new();
+        let tag = new_tag("Совет");
+        make_mut(&tag).set_persona_ids(people.idendities().cloned().collect());
+        tags.insert_or_update(tag);
+
+        let mut letters = LetterContainer::new();
+        let letter = new_letter();
+        let at = chrono::Local::now();
+        make_mut(&letter).add_delivery(Delivery {
+            at,
+            address: "Ivanov@mipt.ru".into(),
+            error: None,
+        });
+        make_mut(&letter).add_delivery(Delivery {
+            at,
+            address: "petrov@mpit.ru".into(),
+            error: Some("ошибка SMTP".into()),
+        });
+        letters.insert_or_update(letter);
+
+        let report = ContactReport::build(&letters, &mut Membership::new(&tags, &people));
+        let petrov = &report.people[0];
+        assert!(petrov.name.starts_with("Петров"));
+        assert_eq!(
+            (petrov.letters, petrov.failed, petrov.never_reached),
+            (0, 1, 1)
+        );
+        let ivanov = &report.people[1];
+        assert_eq!(
+            (ivanov.letters, ivanov.failed, ivanov.never_reached),
+            (1, 0, 0)
+        );
+        assert_eq!(ivanov.last_contact, Some(at));
+        let council = &report.tags[0];
+        assert_eq!(
+            (
+                council.people,
+                council.letters,
+                council.failed,
+                council.never_reached
+            ),
+            (2, 1, 1, 1)
+        );
+        let csv = report.to_csv();
+        assert_eq!(csv.lines().count(), 4);
+        assert!(csv.lines().last().unwrap().starts_with("метка,Совет,2,1,"));
+    }
+
+    #[test]
+    fn test_report_backfills_old_letters() {
+        let mut people = PersonaContainer::new();
+        let persona = new_persona();
+        *make_mut(&persona) = PersonaRepr::new("Петров", "", "", "petrov@mipt.ru");
+        people.insert_or_update(persona);
+        let mut letters = LetterContainer::new();
+        for (sent, failed) in [(1, 0), (1, 1)] {
+            let letter = new_letter();
+            make_mut(&letter).add_recipients(&["petrov@mipt.ru".to_owned()]);
+            make_mut(&letter).mark_sent(sent, failed);
+            letters.insert_or_update(letter);
+        }
+
+        let tags = TagContainer::new();
+        let report = ContactReport::build(&letters, &mut Membership::new(&tags, &people));
+        let petrov = &report.people[0];
+        assert_eq!((petrov.letters, petrov.never_reached), (1, 0));
+        assert!(petrov.last_contact.is_some());
+    }
+}
@@ -0,0 +1,216 @@
+use std::{fmt, str::FromStr};
+
+use serde::{Deserialize, Serialize};
+
+use super::persona::PersonaRepr;
+
+/// The condition of a smart tag, evaluated against the current personas.
+///
+/// Written as alternatives separated by `|`, each a list of conditions
+/// separated by `&`; a condition is `field:value`, negated by a leading `!`.
+/// For example `domain:phystech.edu | position:профессор & !tag:Совет`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Rule {
+    alternatives: Vec<Vec<Condition>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Condition {
+    negated: bool,
+    field: Field,
+    value: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Field {
+    /// The e-mail domain or a parent domain of it.
+    Domain,
+    /// A part of the position, case aside.
+    Position,
+    /// A part of the degree, case aside.
+    Degree,
+    /// Membership in another tag.
+    Tag,
+}
+
+impl Field {
+    const ALL: [Field; 4] = [Field::Domain, Field::Position, Field::Degree, Field::Tag];
+
+    fn name(self) -> &'static str {
+        match self {
+            Field::Domain => "domain",
+            Field::Position => "position",
+            Field::Degree => "degree",
+            Field::Tag => "tag",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RuleError(String);
+
+impl fmt::Display for RuleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "непонятное условие {:?}: ожидается domain:, position:, degree: или tag:",
+            self.0
+        )
+    }
+}
+
+impl std::error::Error for RuleError {}
+
+impl Rule {
+    /// `in_tag` tells whether the persona is a member of the named tag.
+    pub fn matches(&self, persona: &PersonaRepr, mut in_tag: impl FnMut(&str) -> bool) -> bool {
+        self.alternatives.iter().any(|conditions| {
+            conditions
+                .iter()
+                .all(|condition| condition.matches(persona, &mut in_tag) != condition.negated)
+        })
+    }
+
+    /// Tags the rule refers to.
+    pub fn tags(&self) -> impl Iterator<Item = &str> + '_ {
+        self.conditions()
+            .filter(|condition| condition.field == Field::Tag)
+            .map(|condition| condition.value.as_str())
+    }
+
+    pub fn rename_tag(&mut self, old: &str, new: &str) {
+        for conditions in self.alternatives.iter_mut() {
+            for condition in conditions.iter_mut() {
+                if condition.field == Field::Tag && condition.value == old {
+                    condition.value = new.to_owned();
+                }
+            }
+        }
+    }
+
+    fn conditions(&self) -> impl Iterator<Item = &Condition> + '_ {
+        self.alternatives.iter().flatten()
+    }
+}
+
+impl Condition {
+    fn matches(&self, persona: &PersonaRepr, in_tag: &mut impl FnMut(&str) -> bool) -> bool {
+        let contains = |text: &str| text.to_lowercase().contains(&self.value.to_lowercase());
+        match self.field {
+            Field::Domain => {
+                let domain = persona
+                    .get_email()
+                    .rsplit_once('@')
+                    .map(|(_, domain)| domain.to_lowercase())
+                    .unwrap_or_default();
+                let wanted = self.value.to_lowercase();
+                domain == wanted || domain.ends_with(&format!(".{}", wanted))
+            }
+            Field::Position => contains(persona.get_position()),
+            Field::Degree => contains(persona.get_degree()),
+            Field::Tag => in_tag(&self.value),
+        }
+    }
+}
+
+impl FromStr for Rule {
+    type Err = RuleError;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let alternatives = text
+            .split('|')
+            .map(|alternative| alternative.split('&').map(parse_condition).collect())
+            .collect::<Result<_, _>>()?;
+        Ok(Self { alternatives })
+    }
+}
+
+fn parse_condition(text: &str) -> Result<Condition, RuleError> {
+    let error = || RuleError(text.trim().to_owned());
+    let (negated, text) = match text.trim().strip_prefix('!') {
+        Some(rest) => (true, rest),
+        None => (false, text),
+    };
+    let (name, value) = text.split_once(':').ok_or_else(error)?;
+    let field = Field::ALL
+        .into_iter()
+        .find(|field| field.name() == name.trim())
+        .ok_or_else(error)?;
+    let value = value.trim();
+    if value.is_empty() {
+        return Err(error());
+    }
+    Ok(Condition {
+        negated,
+        field,
+        value: value.to_owned(),
+    })
+}
+
+impl fmt::Display for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let alternatives = self
+            .alternatives
+            .iter()
+            .map(|conditions| {
+                conditions
+                    .iter()
+                    .map(|condition| {
+                        format!(
+                            "{}{}:{}",
+                            if condition.negated { "!" } else { "" },
+                            condition.field.name(),
+                            condition.value
+                        )
+                    })
+                    .collect::<Vec<_>>()
+                    .join(" & ")
+            })
+            .collect::<Vec<_>>();
+        write!(f, "{}", alternatives.join(" | "))
+    }
+}
+
+impl TryFrom<String> for Rule {
+    type Error = RuleError;
+
+    fn try_from(text: String) -> Result<Self, Self::Error> {
+        text.parse()
+    }
+}
+
+impl From<Rule> for String {
+    fn from(rule: Rule) -> Self {
+        rule.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn persona(email: &str, position: &str) -> PersonaRepr {
+        let mut persona = PersonaRepr::new("Иванов", "Иван", "Иванович", email);
+        persona.set_position(position);
+        persona
+    }
+
+    #[test]
+    fn test_rule_matches() {
+        let rule = "domain:phystech.edu | position:Профессор & !tag:Совет"
+            .parse::<Rule>()
+            .unwrap();
+        assert_eq!(
+            rule.to_string(),
+            "domain:phystech.edu | position:Профессор & !tag:Совет"
+        );
+        let no_tags = |_: &str| false;
+        assert!(rule.matches(&persona("a@lab.phystech.edu", ""), no_tags));
+        assert!(rule.matches(&persona("a@mipt.ru", "профессор кафедры"), no_tags));
+        assert!(!rule.matches(&persona("a@mipt.ru", "профессор"), |_| true));
+        assert!(!rule.matches(&persona("a@notphystech.edu", ""), no_tags));
+        assert!("position".parse::<Rule>().is_err());
+        assert!("rank:доцент".parse::<Rule>().is_err());
+    }
+}
@@ -0,0 +1,160 @@
+use std::{cell::RefCell, io, rc::Rc};
+
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::Value;
+
+/// Upgrades one stored record by one version.
+pub type Migration = fn(&mut Value);
+
+/// A record type whose stored form is versioned.
+pub trait Versioned {
+    /// `MIGRATIONS[n]` upgrades a record of version `n` to `n + 1`; the
+    /// current version is the number of migrations.
+    const MIGRATIONS: &'static [Migration];
+
+    fn version() -> u32 {
+        Self::MIGRATIONS.len() as u32
+    }
+}
+
+/// Version 0 is the bare array written before versioning; its records only
+/// get wrapped in the envelope.
+pub fn add_envelope(_: &mut Value) {}
+
+/// The current on-disk layout of a JSON data file.
+#[derive(Serialize, Deserialize)]
+struct Envelope<T> {
+    version: u32,
+    records: T,
+}
+
+pub fn to_json<'a, Repr: Versioned + Serialize + 'a>(
+    records: impl Iterator<Item = &'a Rc<RefCell<Repr>>>,
+) -> String {
+    serde_json::to_string(&Envelope {
+        version: Repr::version(),
+        records: records.collect::<Vec<_>>(),
+    })
+    .unwrap()
+}
+
+/// Reads a data file of any known version; returns the records and the
+/// version they were stored with.
+pub fn from_json<Repr: Versioned + DeserializeOwned>(json: &str) -> io::Result<(Vec<Repr>, u32)> {
+    let (records, version) = match serde_json::from_str(json).map_err(invalid_data)? {
+        Value::Array(records) => (records, 0),
+        envelope => {
+            let envelope: Envelope<Vec<Value>> =
+                serde_json::from_value(envelope).map_err(invalid_data)?;
+            (envelope.records, envelope.version)
+        }
+    };
+    check_version::<Repr>(version)?;
+    let records = records
+        .into_iter()
+        .map(|record| upgrade(record, version))
+        .collect::<io::Result<_>>()?;
+    Ok((records, version))
+}
+
+/// Applies the migrations from `version` on and reads the record.
+pub fn upgrade<Repr: Versioned + DeserializeOwned>(
+    mut record: Value,
+    version: u32,
+) -> io::Result<Repr> {
+    check_version::<Repr>(version)?;
+    for migration in &Repr::MIGRATIONS[version as usize..] {
+        migration(&mut record);
+    }
+    serde_json::from_value(record).map_err(invalid_data)
+}
+
+/// Files written by a newer program are refused rather than misread.
+pub fn check_version<Repr: Versioned>(version: u32) -> io::Result<()> {
+    if version > Repr::version() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "версия данных {} новее поддерживаемой {}",
+                version,
+                Repr::version()
+            ),
+        ));
+    }
+    Ok(())
+}
+
+fn invalid_data(e: serde_json::Error) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, e)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data_handler::{
+        letter::LetterRepr, persona::PersonaRepr, tag::TagRepr, template::TemplateRepr, Identity,
+        Represent,
+    };
+
+    /// A sample of every version each data file was ever written in.
+    fn fixture(name: &str, version: u32) -> Option<&'static str> {
+        match (name, version) {
+            ("persona", 0) => Some(include_str!("fixtures/persona.v0.json")),
+            ("persona", 1) => Some(include_str!("fixtures/persona.v1.json")),
+            ("persona", 2) => Some(include_str!("fixtures/persona.v2.json")),
+            ("tag", 0) => Some(include_str!("fixtures/tag.v0.json")),
+            ("tag", 1) => Some(include_str!("fixtures/tag.v1.json")),
+            ("tag", 2) => Some(include_str!("fixtures/tag.v2.json")),
+            ("tag", 3) => Some(include_str!("fixtures/tag.v3.json")),
+            ("letter", 0) => Some(include_str!("fixtures/letter.v0.json")),
+            ("letter", 1) => Some(include_str!("fixtures/letter.v1.json")),
+            ("letter", 2) => Some(include_str!("fixtures/letter.v2.json")),
+            ("letter", 3) => Some(include_str!("fixtures/letter.v3.json")),
+            ("letter", 4) => Some(include_str!("fixtures/letter.v4.json")),
+            ("letter", 5) => Some(include_str!("fixtures/letter.v5.json")),
+            ("template", 1) => Some(include_str!("fixtures/template.v1.json")),
+            _ => None,
+        }
+    }
+
+    /// Files that came to be after versioning have no version 0.
+    fn load_fixtures<Repr: Versioned + DeserializeOwned + Represent>(
+        name: &str,
+    ) -> Vec<Vec<Identity>> {
+        let first = fixture(name, 0).map_or(1, |_| 0);
+        (first..=Repr::version())
+            .map(|version| {
+                let json = fixture(name, version)
+                    .unwrap_or_else(|| panic!("no fixture for {} version {}", name, version));
+                let (records, found) = from_json::<Repr>(json).unwrap();
+                assert_eq!(found, version);
+                records.iter().map(|repr| repr.identity()).collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_every_version_loads() {
+        for identities in load_fixtures::<PersonaRepr>("persona") {
+            assert_eq!(
+                identities,
+                vec!["Иванов Иван Иванович", "Петров Пётр Петрович"]
+            );
+        }
+        for identities in load_fixtures::<TagRepr>("tag") {
+            assert_eq!(identities, vec!["Совет"]);
+        }
+        for identities in load_fixtures::<LetterRepr>("letter") {
+            assert_eq!(identities.len(), 1);
+        }
+        for identities in load_fixtures::<TemplateRepr>("template") {
+            assert_eq!(identities, vec!["Семинар"]);
+        }
+    }
+
+    #[test]
+    fn test_newer_version_is_refused() {
+        let json = format!("{{\"version\":{},\"records\":[]}}", TagRepr::version() + 1);
+        assert!(from_json::<TagRepr>(&json).is_err());
+    }
+}
@@ -0,0 +1,164 @@
+use std::collections::{BTreeMap, HashMap};
+
+use super::{
+    letter::{Letter, LetterRepr},
+    make_ref,
+};
+
+/// How much a word counts in each part of a letter.
+const TOPIC_WEIGHT: u32 = 4;
+const NAME_WEIGHT: u32 = 2;
+const TEXT_WEIGHT: u32 = 1;
+
+/// The words of the letters' topics, texts, attachment names and recipients.
+///
+/// Every word of a query must begin a word of the letter, so "диссерт"
+/// finds "диссертационного"; a word in the topic weighs more than one in
+/// attachment names or recipients, and those more than one in the text.
+#[derive(Debug, Clone)]
+pub struct SearchIndex {
+    letters: Vec<Letter>,
+    /// For every word the letters it occurs in with the weight of each time.
+    words: BTreeMap<String, Vec<(usize, u32)>>,
+}
+
+impl SearchIndex {
+    /// `names` gives persona names by e-mail, so recipients are found by
+    /// name as well as by address.
+    pub fn build(letters: Vec<Letter>, names: &HashMap<String, String>) -> Self {
+        let mut index = Self {
+            letters: vec![],
+            words: BTreeMap::new(),
+        };
+        for (number, letter) in letters.iter().enumerate() {
+            index.add_letter(number, &make_ref(letter), names);
+        }
+        index.letters = letters;
+        index
+    }
+
+    fn add_letter(&mut self, number: usize, letter: &LetterRepr, names: &HashMap<String, String>) {
+        self.add_text(number, letter.get_topic(), TOPIC_WEIGHT);
+        self.add_text(number, letter.get_text(), TEXT_WEIGHT);
+        for attached_file in letter.attachments() {
+            self.add_text(number, attached_file.get_filename(), NAME_WEIGHT);
+        }
+        let scheduled = letter
+            .schedule()
+            .map(|schedule| [&schedule.to[..], &schedule.cc[..], &schedule.bcc[..]].concat())
+            .unwrap_or_default();
+        for address in letter.recipients().iter().chain(scheduled.iter()) {
+            self.add_text(number, address, NAME_WEIGHT);
+            if let Some(name) = names.get(address) {
+                self.add_text(number, name, NAME_WEIGHT);
+            }
+        }
+    }
+
+    fn add_text(&mut self, number: usize, text: &str, weight: u32) {
+        for word in words(text) {
+            self.words.entry(word).or_default().push((number, weight));
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.letters.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.letters.is_empty()
+    }
+
+    /// Letters with every word of `query`, best first and newer first
+    /// among equals; nothing for an empty query.
+    pub fn search(&self, query: &str) -> Vec<Letter> {
+        let mut scores: Option<HashMap<usize, u32>> = None;
+        for word in words(query) {
+            let mut found = HashMap::<usize, u32>::new();
+            let matching = self
+                .words
+                .range(word.clone()..)
+                .take_while(|(indexed, _)| indexed.starts_with(&word));
+            for (_, occurrences) in matching {
+                for &(number, weight) in occurrences {
+                    *found.entry(number).or_default() += weight;
+                }
+            }
+            scores = Some(match scores {
+                None => found,
+                Some(scores) => scores
+                    .into_iter()
+                    .filter_map(|(number, score)| {
+                        found.get(&number).map(|more| (number, score + more))
+                    })
+                    .collect(),
+            });
+        }
+        let mut ranked = scores.unwrap_or_default().into_iter().collect::<Vec<_>>();
+        ranked.sort_by(|a, b| b.1.cmp(&a.1).then(b.0.cmp(&a.0)));
+        ranked
+            .into_iter()
+            .map(|(number, _)| self.letters[number].clone())
+            .collect()
+    }
+}
+
+/// Lowercase words with "ё" read as "е".
+fn words(text: &str) -> impl Iterator<Item = String> + '_ {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(|word| word.to_lowercase().replace('ё', "е"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data_handler::{letter::new_letter, make_mut};
+
+    fn letter(topic: &str, text: &str, recipients: &[&str]) -> Letter {
+        let letter = new_letter();
+        let mut repr = make_mut(&letter);
+        repr.set_topic(topic);
+        repr.set_text(text);
+        let recipients = recipients.iter().map(|x| x.to_string()).collect::<Vec<_>>();
+        repr.add_recipients(&recipients);
+        drop(repr);
+        letter
+    }
+
+    fn topics(letters: Vec<Letter>) -> Vec<String> {
+        letters
+            .iter()
+            .map(|letter| make_ref(letter).get_topic().to_owned())
+            .collect()
+    }
+
+    #[test]
+    fn test_search_ranks_topic_first() {
+        let letters = vec![
+            letter("Семинар", "Обсудим диссертационный совет.", &[]),
+            letter(
+                "Диссертационный совет",
+                "Заседание весной.",
+                &["ivanov@mipt.ru"],
+            ),
+            letter("Отчёт", "Сдайте отчёты.", &[]),
+        ];
+        let names = HashMap::from([("ivanov@mipt.ru".into(), "Иванов Иван".into())]);
+        let index = SearchIndex::build(letters, &names);
+        assert_eq!(
+            topics(index.search("диссерт совет")),
+            vec!["Диссертационный совет", "Семинар"]
+        );
+        assert_eq!(
+            topics(index.search("совет весной")),
+            vec!["Диссертационный совет"]
+        );
+        assert_eq!(
+            topics(index.search("Иванов")),
+            vec!["Диссертационный совет"]
+        );
+        assert_eq!(topics(index.search("отчет")), vec!["Отчёт"]);
+        assert!(index.search("").is_empty());
+    }
+}
@@ -0,0 +1,280 @@
+use std::{collections::HashSet, fmt};
+
+use super::{make_ref, tag::Membership, Identity, Represent};
+
+/// A choice of personas made of tags and single personas.
+///
+/// `|` is union, `&` intersection and `!` everybody except; parentheses
+/// group. A name is a tag label, a persona or an e-mail, quoted with `"` if
+/// it holds one of the operators: `(Кафедра А | Кафедра Б) & !Заведующие`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Selection {
+    Name(String),
+    Union(Box<Selection>, Box<Selection>),
+    Intersection(Box<Selection>, Box<Selection>),
+    Except(Box<Selection>),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SelectionError {
+    Syntax(String),
+    UnknownName(String),
+}
+
+impl fmt::Display for SelectionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SelectionError::Syntax(info) => write!(f, "ошибка в выражении: {}", info),
+            SelectionError::UnknownName(name) => {
+                write!(
+                    f,
+                    "нет ни метки, ни персоны, ни персоны с e-mail {:?}",
+                    name
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for SelectionError {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Or,
+    And,
+    Not,
+    Open,
+    Close,
+    Name(String),
+}
+
+impl Selection {
+    pub fn parse(text: &str) -> Result<Self, SelectionError> {
+        let tokens = tokenize(text)?;
+        let mut parser = Parser { tokens, next: 0 };
+        let selection = parser.union()?;
+        match parser.tokens.get(parser.next) {
+            None => Ok(selection),
+            Some(token) => Err(SelectionError::Syntax(format!(
+                "лишнее {}",
+                describe(token)
+            ))),
+        }
+    }
+
+    /// The chosen personas in the order of the persona list.
+    pub fn resolve(&self, membership: &mut Membership) -> Result<Vec<Identity>, SelectionError> {
+        let chosen = self.evaluate(membership)?;
+        Ok(membership
+            .people()
+            .idendities()
+            .filter(|id| chosen.contains(*id))
+            .cloned()
+            .collect())
+    }
+
+    fn evaluate(&self, membership: &mut Membership) -> Result<HashSet<Identity>, SelectionError> {
+        Ok(match self {
+            Selection::Name(name) => resolve_name(name, membership)?,
+            Selection::Union(left, right) => {
+                let mut result = left.evaluate(membership)?;
+                result.extend(right.evaluate(membership)?);
+                result
+            }
+            Selection::Intersection(left, right) => {
+                let right = right.evaluate(membership)?;
+                let mut result = left.evaluate(membership)?;
+                result.retain(|id| right.contains(id));
+                result
+            }
+            Selection::Except(inner) => {
+                let inner = inner.evaluate(membership)?;
+                membership
+                    .people()
+                    .idendities()
+                    .filter(|id| !inner.contains(*id))
+                    .cloned()
+                    .collect()
+            }
+        })
+    }
+}
+
+/// A tag first, then a persona, then an e-mail.
+fn resolve_name(
+    name: &str,
+    membership: &mut Membership,
+) -> Result<HashSet<Identity>, SelectionError> {
+    if membership.tags().get(name).is_some() {
+        return Ok(membership.members(name).into_iter().collect());
+    }
+    let people = membership.people();
+    if people.get(name).is_some() {
+        return Ok(HashSet::from([name.to_owned()]));
+    }
+    let by_email = people
+        .all_representations()
+        .filter(|persona| make_ref(persona).get_email().eq_ignore_ascii_case(name))
+        .map(|persona| make_ref(&persona).identity())
+        .collect::<HashSet<_>>();
+    if by_email.is_empty() {
+        Err(SelectionError::UnknownName(name.to_owned()))
+    } else {
+        Ok(by_email)
+    }
+}
+
+fn tokenize(text: &str) -> Result<Vec<Token>, SelectionError> {
+    let mut tokens = vec![];
+    let mut chars = text.chars();
+    let mut name = String::new();
+    let flush = |name: &mut String, tokens: &mut Vec<Token>| {
+        if !name.trim().is_empty() {
+            tokens.push(Token::Name(name.trim().to_owned()));
+        }
+        name.clear();
+    };
+    while let Some(c) = chars.next() {
+        let token = match c {
+            '|' => Token::Or,
+            '&' => Token::And,
+            '!' => Token::Not,
+            '(' => Token::Open,
+            ')' => Token::Close,
+            '"' => {
+                let quoted = chars.by_ref().take_while(|c| *c != '"').collect::<String>();
+                Token::Name(quoted)
+            }
+            _ => {
+                name.push(c);
+                continue;
+            }
+        };
+        flush(&mut name, &mut tokens);
+        tokens.push(token);
+    }
+    flush(&mut name, &mut tokens);
+    if tokens.is_empty() {
+        return Err(SelectionError::Syntax("пустое выражение".into()));
+    }
+    Ok(tokens)
+}
+
+fn describe(token: &Token) -> String {
+    match token {
+        Token::Or => "\"|\"".into(),
+        Token::And => "\"&\"".into(),
+        Token::Not => "\"!\"".into(),
+        Token::Open => "\"(\"".into(),
+        Token::Close => "\")\"".into(),
+        Token::Name(name) => format!("{:?}", name),
+    }
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    next: usize,
+}
+
+impl Parser {
+    fn union(&mut self) -> Result<Selection, SelectionError> {
+        let mut result = self.intersection()?;
+        while self.accept(&Token::Or) {
+            result = Selection::Union(Box::new(result), Box::new(self.intersection()?));
+        }
+        Ok(result)
+    }
+
+    fn intersection(&mut self) -> Result<Selection, SelectionError> {
+        let mut result = self.operand()?;
+        while self.accept(&Token::And) {
+            result = Selection::Intersection(Box::new(result), Box::new(self.operand()?));
+        }
+        Ok(result)
+    }
+
+    fn operand(&mut self) -> Result<Selection, SelectionError> {
+        let token = self.tokens.get(self.next).cloned();
+        self.next += 1;
+        match token {
+            Some(Token::Not) => Ok(Selection::Except(Box::new(self.operand()?))),
+            Some(Token::Open) => {
+                let inner = self.union()?;
+                if self.accept(&Token::Close) {
+                    Ok(inner)
+                } else {
+                    Err(SelectionError::Syntax("не закрыта скобка".into()))
+                }
+            }
+            Some(Token::Name(name)) => Ok(Selection::Name(name)),
+            Some(token) => Err(SelectionError::Syntax(format!(
+                "неожиданное {}",
+                describe(&token)
+            ))),
+            None => Err(SelectionError::Syntax("выражение оборвано".into())),
+        }
+    }
+
+    fn accept(&mut self, token: &Token) -> bool {
+        let found = self.tokens.get(self.next) == Some(token);
+        if found {
+            self.next += 1;
+        }
+        found
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, rc::Rc};
+
+    use super::*;
+    use crate::data_handler::{
+        persona::{PersonaContainer, PersonaRepr},
+        tag::{new_tag, TagContainer},
+    };
+
+    #[test]
+    fn test_selection_algebra() {
+        let mut people = PersonaContainer::new();
+        for (family, name, email) in [
+            ("Иванов", "Иван", "ivanov@mipt.ru"),
+            ("Петров", "Пётр", "petrov@mipt.ru"),
+            ("Сидоров", "Сидор", "sidorov@mipt.ru"),
+        ] {
+            let persona = PersonaRepr::new(family, name, "", email);
+            people.insert_or_update(Rc::new(RefCell::new(persona)));
+        }
+        let mut tags = TagContainer::new();
+        for (label, members) in [
+            ("Кафедра А", vec!["Иванов Иван ", "Петров Пётр "]),
+            ("Кафедра Б", vec!["Сидоров Сидор "]),
+            ("Заведующие", vec!["Петров Пётр "]),
+        ] {
+            let tag = new_tag(label);
+            tag.borrow_mut()
+                .set_persona_ids(members.into_iter().map(str::to_owned).collect());
+            tags.insert_or_update(tag);
+        }
+        let resolve = |text: &str| {
+            Selection::parse(text)
+                .and_then(|selection| selection.resolve(&mut Membership::new(&tags, &people)))
+        };
+        assert_eq!(
+            resolve("(Кафедра А | Кафедра Б) & !Заведующие").unwrap(),
+            vec!["Иванов Иван ", "Сидоров Сидор "]
+        );
+        assert_eq!(
+            resolve("Кафедра А & SIDOROV@mipt.ru | \"Петров Пётр \"").unwrap(),
+            vec!["Петров Пётр "]
+        );
+        assert_eq!(
+            resolve("Кафедра В"),
+            Err(SelectionError::UnknownName("Кафедра В".into()))
+        );
+        assert!(matches!(
+            resolve("(Кафедра А |"),
+            Err(SelectionError::Syntax(_))
+        ));
+    }
+}
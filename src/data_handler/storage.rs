@@ -0,0 +1,427 @@
+use std::{
+    cell::RefCell,
+    collections::BTreeMap,
+    fmt,
+    fs::{self, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    rc::Rc,
+};
+
+use redb::{ReadableDatabase, ReadableTable, TableDefinition};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use super::{
+    backup,
+    data_container::DataContainer,
+    make_ref,
+    schema::{self, Versioned},
+    Identity, Represent,
+};
+
+/// How the containers are kept on disk.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StorageKind {
+    /// The whole container is rewritten as one JSON array.
+    #[default]
+    Json,
+    /// Changed records are appended as JSON lines.
+    Journal,
+    /// Records are kept in an embedded `redb` database and updated in place.
+    Database,
+}
+
+impl StorageKind {
+    pub fn extension(self) -> &'static str {
+        match self {
+            StorageKind::Json => "json",
+            StorageKind::Journal => "journal",
+            StorageKind::Database => "redb",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "json" => Some(StorageKind::Json),
+            "journal" => Some(StorageKind::Journal),
+            "database" => Some(StorageKind::Database),
+            _ => None,
+        }
+    }
+
+    pub fn open<Repr>(self, path: PathBuf) -> Box<dyn Storage<Repr>>
+    where
+        Repr: Serialize + DeserializeOwned + Represent + Versioned + 'static,
+    {
+        match self {
+            StorageKind::Json => Box::new(JsonFile::new(path)),
+            StorageKind::Journal => Box::new(Journal::new(path)),
+            StorageKind::Database => Box::new(Database::new(path)),
+        }
+    }
+}
+
+/// A backend that loads and saves one container.
+pub trait Storage<Repr>: fmt::Debug {
+    /// Reads `path`, which is the file of this storage or a backup of it,
+    /// upgrading older records; returns the version the file was written with.
+    fn load_from(&mut self, path: &Path) -> io::Result<(DataContainer<Repr>, u32)>;
+
+    /// Writes the changes since the last load or save. The previous file is
+    /// backed up only if `keep_backup` is set, which the data handler does
+    /// once a session, so appends stay cheap.
+    fn save(&mut self, container: &DataContainer<Repr>, keep_backup: bool) -> io::Result<()>;
+}
+
+#[derive(Debug)]
+pub struct JsonFile {
+    path: PathBuf,
+}
+
+impl JsonFile {
+    pub fn new(path: PathBuf) -> Self {
+        Self { path }
+    }
+}
+
+impl<Repr: Serialize + DeserializeOwned + Represent + Versioned> Storage<Repr> for JsonFile {
+    fn load_from(&mut self, path: &Path) -> io::Result<(DataContainer<Repr>, u32)> {
+        DataContainer::restore(path)
+    }
+
+    fn save(&mut self, container: &DataContainer<Repr>, keep_backup: bool) -> io::Result<()> {
+        backup::save_with(&self.path, &container.to_json(), keep_backup)
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum Entry {
+    Put(serde_json::Value),
+    Remove(Identity),
+}
+
+/// The first line of a journal.
+#[derive(Serialize, Deserialize)]
+struct Header {
+    version: u32,
+}
+
+/// An append-only journal of JSON lines after a version header, compacted
+/// once it holds far more lines than records.
+#[derive(Debug)]
+pub struct Journal {
+    path: PathBuf,
+    /// Every record as it was last written.
+    saved: BTreeMap<Identity, String>,
+    lines: usize,
+    /// The file does not match `saved` and has to be written anew.
+    rewrite: bool,
+}
+
+impl Journal {
+    const SLACK: usize = 100;
+
+    pub fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            saved: BTreeMap::new(),
+            lines: 0,
+            rewrite: true,
+        }
+    }
+
+    fn compact(
+        &mut self,
+        version: u32,
+        records: BTreeMap<Identity, String>,
+        keep_backup: bool,
+    ) -> io::Result<()> {
+        let mut contents = format!("{}\n", serde_json::json!(Header { version }));
+        for record in records.values() {
+            contents.push_str(&format!("{{\"put\":{}}}\n", record));
+        }
+        if keep_backup && self.path.exists() {
+            backup::keep_backup(&self.path)?;
+        }
+        backup::write_atomically(&self.path, &contents)?;
+        self.lines = records.len() + 1;
+        self.saved = records;
+        self.rewrite = false;
+        Ok(())
+    }
+}
+
+impl<Repr: Serialize + DeserializeOwned + Represent + Versioned> Storage<Repr> for Journal {
+    /// A torn last line, left by a crash in the middle of an append, is
+    /// dropped; any other broken line is an error. A journal without a
+    /// header has version 0.
+    fn load_from(&mut self, path: &Path) -> io::Result<(DataContainer<Repr>, u32)> {
+        let text = fs::read_to_string(path)?;
+        let lines = text
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .collect::<Vec<_>>();
+        let version = match lines
+            .first()
+            .map(|line| serde_json::from_str::<Header>(line))
+        {
+            Some(Ok(header)) => header.version,
+            _ => 0,
+        };
+        schema::check_version::<Repr>(version)?;
+        let mut records = BTreeMap::new();
+        let mut torn = false;
+        for (n, line) in lines.iter().enumerate().skip((version > 0) as usize) {
+            match serde_json::from_str::<Entry>(line) {
+                Ok(Entry::Put(record)) => {
+                    let repr = schema::upgrade::<Repr>(record, version)?;
+                    records.insert(repr.identity(), repr);
+                }
+                Ok(Entry::Remove(identity)) => {
+                    records.remove(&identity);
+                }
+                Err(_) if n + 1 == lines.len() => torn = true,
+                Err(e) => return Err(io::Error::new(io::ErrorKind::InvalidData, e)),
+            }
+        }
+        let mut container = DataContainer::new();
+        for repr in records.into_values() {
+            container.insert_or_update(Rc::new(RefCell::new(repr)));
+        }
+        if path == self.path && !torn && version == Repr::version() {
+            self.saved = serialize_records(&container)?;
+            self.lines = lines.len();
+            self.rewrite = false;
+        } else {
+            self.rewrite = true;
+        }
+        Ok((container, version))
+    }
+
+    fn save(&mut self, container: &DataContainer<Repr>, keep_backup: bool) -> io::Result<()> {
+        let records = serialize_records(container)?;
+        if self.rewrite || !self.path.exists() || self.lines > 2 * records.len() + Self::SLACK {
+            return self.compact(Repr::version(), records, keep_backup);
+        }
+        let mut appended = String::new();
+        for (identity, record) in records.iter() {
+            if self.saved.get(identity) != Some(record) {
+                appended.push_str(&format!("{{\"put\":{}}}\n", record));
+                self.lines += 1;
+            }
+        }
+        for identity in self.saved.keys().filter(|id| !records.contains_key(*id)) {
+            appended.push_str(&format!("{}\n", serde_json::json!({ "remove": identity })));
+            self.lines += 1;
+        }
+        if appended.is_empty() {
+            return Ok(());
+        }
+        if keep_backup {
+            backup::keep_backup(&self.path)?;
+        }
+        let mut file = OpenOptions::new().append(true).open(&self.path)?;
+        file.write_all(appended.as_bytes())?;
+        file.sync_data()?;
+        self.saved = records;
+        Ok(())
+    }
+}
+
+const RECORDS: TableDefinition<&str, &str> = TableDefinition::new("records");
+const META: TableDefinition<&str, u32> = TableDefinition::new("meta");
+const VERSION: &str = "version";
+
+/// An embedded database with a JSON record per identity; a save writes only
+/// the changed records, in one transaction.
+#[derive(Debug)]
+pub struct Database {
+    path: PathBuf,
+    /// Every record as it was last read or written at the current version;
+    /// `None` until then, when a save has to read the file first.
+    saved: Option<BTreeMap<Identity, String>>,
+}
+
+impl Database {
+    pub fn new(path: PathBuf) -> Self {
+        Self { path, saved: None }
+    }
+
+    /// The stored version and records. The file is opened read-only, so
+    /// reading does not touch it.
+    fn read(path: &Path) -> io::Result<(u32, BTreeMap<Identity, String>)> {
+        fs::metadata(path)?;
+        let db = redb::ReadOnlyDatabase::open(path).map_err(database_error)?;
+        let txn = db.begin_read().map_err(database_error)?;
+        let version = match txn.open_table(META) {
+            Ok(meta) => meta
+                .get(VERSION)
+                .map_err(database_error)?
+                .map(|version| version.value())
+                .unwrap_or(0),
+            Err(redb::TableError::TableDoesNotExist(_)) => 0,
+            Err(e) => return Err(database_error(e)),
+        };
+        let mut records = BTreeMap::new();
+        match txn.open_table(RECORDS) {
+            Ok(table) => {
+                for entry in table.iter().map_err(database_error)? {
+                    let (identity, record) = entry.map_err(database_error)?;
+                    records.insert(identity.value().to_owned(), record.value().to_owned());
+                }
+            }
+            Err(redb::TableError::TableDoesNotExist(_)) => {}
+            Err(e) => return Err(database_error(e)),
+        }
+        Ok((version, records))
+    }
+}
+
+impl<Repr: Serialize + DeserializeOwned + Represent + Versioned> Storage<Repr> for Database {
+    fn load_from(&mut self, path: &Path) -> io::Result<(DataContainer<Repr>, u32)> {
+        let (version, records) = Self::read(path)?;
+        schema::check_version::<Repr>(version)?;
+        let mut container = DataContainer::new();
+        for record in records.values() {
+            let record = serde_json::from_str(record)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+            let repr = schema::upgrade::<Repr>(record, version)?;
+            container.insert_or_update(Rc::new(RefCell::new(repr)));
+        }
+        if path == self.path {
+            self.saved = (version == Repr::version()).then_some(records);
+        }
+        Ok((container, version))
+    }
+
+    fn save(&mut self, container: &DataContainer<Repr>, keep_backup: bool) -> io::Result<()> {
+        let records = serialize_records(container)?;
+        let (version, saved) = match self.saved.take() {
+            Some(saved) => (Repr::version(), saved),
+            None => match Self::read(&self.path) {
+                Ok(stored) => stored,
+                Err(e) if e.kind() == io::ErrorKind::NotFound => (0, BTreeMap::new()),
+                Err(e) => return Err(e),
+            },
+        };
+        if version == Repr::version() && saved == records {
+            self.saved = Some(saved);
+            return Ok(());
+        }
+        if keep_backup && self.path.exists() {
+            backup::keep_backup(&self.path)?;
+        }
+        let db = redb::Database::create(&self.path).map_err(database_error)?;
+        let txn = db.begin_write().map_err(database_error)?;
+        {
+            let mut table = txn.open_table(RECORDS).map_err(database_error)?;
+            for (identity, record) in records.iter() {
+                if saved.get(identity) != Some(record) {
+                    table
+                        .insert(identity.as_str(), record.as_str())
+                        .map_err(database_error)?;
+                }
+            }
+            for identity in saved.keys().filter(|id| !records.contains_key(*id)) {
+                table.remove(identity.as_str()).map_err(database_error)?;
+            }
+            let mut meta = txn.open_table(META).map_err(database_error)?;
+            meta.insert(VERSION, Repr::version())
+                .map_err(database_error)?;
+        }
+        txn.commit().map_err(database_error)?;
+        self.saved = Some(records);
+        Ok(())
+    }
+}
+
+fn database_error(e: impl Into<redb::Error>) -> io::Error {
+    match e.into() {
+        redb::Error::Io(e) => e,
+        e => io::Error::other(e),
+    }
+}
+
+fn serialize_records<Repr: Serialize + Represent>(
+    container: &DataContainer<Repr>,
+) -> io::Result<BTreeMap<Identity, String>> {
+    container
+        .all_representations()
+        .map(|repr| {
+            let repr = make_ref(&repr);
+            serde_json::to_string(&*repr)
+                .map(|json| (repr.identity(), json))
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data_handler::tag::{new_tag, TagRepr};
+    use crate::test_utils::TempDir;
+
+    #[test]
+    fn test_journal_appends_changes() {
+        let dir = TempDir::new("storage");
+        let path = dir.join("tag.journal");
+        let mut container = DataContainer::<TagRepr>::new();
+        container.insert_or_update(new_tag("Совет"));
+        container.insert_or_update(new_tag("Кафедра"));
+        let mut journal = StorageKind::Journal.open::<TagRepr>(path.clone());
+        journal.save(&container, false).unwrap();
+        container.remove_identity("Совет");
+        container.insert_or_update(new_tag("Ректорат"));
+        journal.save(&container, false).unwrap();
+
+        let text = fs::read_to_string(&path).unwrap();
+        assert_eq!(text.lines().count(), 5);
+        assert!(text.lines().nth(4).unwrap().contains("remove"));
+
+        fs::write(&path, format!("{}{{\"put\":{{\"lab", text)).unwrap();
+        let mut reopened = StorageKind::Journal.open::<TagRepr>(path.clone());
+        let (loaded, version) = reopened.load_from(&path).unwrap();
+        assert_eq!(version, TagRepr::version());
+        assert_eq!(
+            loaded.idendities().cloned().collect::<Vec<_>>(),
+            vec!["Кафедра", "Ректорат"]
+        );
+        reopened.save(&loaded, false).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap().lines().count(), 3);
+    }
+
+    #[test]
+    fn test_database_keeps_changes() {
+        let dir = TempDir::new("storage");
+        let path = dir.join("tag.redb");
+        let mut container = DataContainer::<TagRepr>::new();
+        container.insert_or_update(new_tag("Совет"));
+        container.insert_or_update(new_tag("Кафедра"));
+        let mut database = StorageKind::Database.open::<TagRepr>(path.clone());
+        database.save(&container, true).unwrap();
+        container.remove_identity("Совет");
+        container.insert_or_update(new_tag("Ректорат"));
+        database.save(&container, true).unwrap();
+        let stamp = fs::metadata(&path).unwrap().modified().unwrap();
+        database.save(&container, true).unwrap();
+        assert_eq!(fs::metadata(&path).unwrap().modified().unwrap(), stamp);
+
+        let mut reopened = StorageKind::Database.open::<TagRepr>(path.clone());
+        let (loaded, version) = reopened.load_from(&path).unwrap();
+        assert_eq!(version, TagRepr::version());
+        assert_eq!(
+            loaded.idendities().cloned().collect::<Vec<_>>(),
+            vec!["Кафедра", "Ректорат"]
+        );
+        let backups = backup::list_backups(&path).unwrap();
+        assert_eq!(backups.len(), 1);
+        let (old, _) = reopened.load_from(&backups[0]).unwrap();
+        assert_eq!(
+            old.idendities().cloned().collect::<Vec<_>>(),
+            vec!["Кафедра", "Совет"]
+        );
+    }
+}
@@ -1,8 +1,20 @@
-use std::{cell::RefCell, rc::Rc};
+use std::{
+    cell::RefCell,
+    collections::{HashMap, HashSet},
+    rc::Rc,
+};
 
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
 
-use super::{data_container::DataContainer, Identity, Represent};
+use super::{
+    data_container::DataContainer,
+    make_ref,
+    persona::PersonaContainer,
+    rule::Rule,
+    schema::{self, Migration, Versioned},
+    Identity, Represent,
+};
 
 pub type TagContainer = DataContainer<TagRepr>;
 pub type Tag = Rc<RefCell<TagRepr>>;
@@ -10,6 +22,10 @@ pub type Tag = Rc<RefCell<TagRepr>>;
 pub struct TagRepr {
     label: String,
     persona_ids: Vec<Identity>,
+    /// The label of the enclosing tag.
+    parent: Option<Identity>,
+    /// Personas matching the rule are members too.
+    rule: Option<Rule>,
 }
 
 impl TagRepr {
@@ -17,6 +33,8 @@ impl TagRepr {
         Self {
             label: label.to_string(),
             persona_ids: Vec::new(),
+            parent: None,
+            rule: None,
         }
     }
 
@@ -35,6 +53,235 @@ impl TagRepr {
     pub fn set_persona_ids(&mut self, ids: Vec<Identity>) {
         self.persona_ids = ids;
     }
+
+    pub fn parent(&self) -> Option<&str> {
+        self.parent.as_deref()
+    }
+
+    pub fn set_parent(&mut self, parent: Option<Identity>) {
+        self.parent = parent;
+    }
+
+    pub fn rule(&self) -> Option<&Rule> {
+        self.rule.as_ref()
+    }
+
+    pub fn set_rule(&mut self, rule: Option<Rule>) {
+        self.rule = rule;
+    }
+}
+
+impl TagContainer {
+    pub fn children<'a>(&'a self, label: &'a str) -> impl Iterator<Item = Tag> + 'a {
+        self.all_representations()
+            .filter(move |tag| make_ref(tag).parent() == Some(label))
+    }
+
+    /// Every tag with its depth, each parent followed by its children. Tags
+    /// whose parent is gone are roots.
+    pub fn tree(&self) -> Vec<(usize, Tag)> {
+        let roots = self.all_representations().filter(|tag| {
+            make_ref(tag)
+                .parent()
+                .map(|parent| self.get(parent).is_none())
+                .unwrap_or(true)
+        });
+        let mut result = vec![];
+        let mut visited = HashSet::new();
+        // Tags caught in a loop have no root; they are listed last.
+        for tag in roots.chain(self.all_representations()) {
+            self.walk(tag, 0, &mut visited, &mut result);
+        }
+        result
+    }
+
+    /// The labels of the tag and of every tag below it.
+    pub fn descendants(&self, label: &str) -> Vec<Identity> {
+        let mut result = vec![];
+        if let Some(tag) = self.get(label) {
+            self.walk(tag, 0, &mut HashSet::new(), &mut result);
+        }
+        result
+            .into_iter()
+            .map(|(_, tag)| make_ref(&tag).identity())
+            .collect()
+    }
+
+    /// Members of the tag and of all its descendants, each once.
+    pub fn members(&self, label: &str, people: &PersonaContainer) -> Vec<Identity> {
+        Membership::new(self, people).members(label)
+    }
+
+    /// Points the children and rules referring to a renamed tag at its new label.
+    pub fn rename_references(&mut self, old: &str, new: &str) {
+        if old == new {
+            return;
+        }
+        for tag in self.all_representations() {
+            let mut tag = tag.borrow_mut();
+            if tag.parent() == Some(old) {
+                tag.set_parent(Some(new.to_owned()));
+            }
+            if let Some(ref mut rule) = tag.rule {
+                rule.rename_tag(old, new);
+            }
+        }
+    }
+
+    /// Lists the persona, formerly known as `old`, in exactly the given tags.
+    pub fn set_memberships(&mut self, old: &str, persona: &str, labels: &[Identity]) {
+        for tag in self.all_representations() {
+            let mut tag = tag.borrow_mut();
+            let mut ids = tag
+                .persona_ids()
+                .filter(|id| *id != old && *id != persona)
+                .cloned()
+                .collect::<Vec<_>>();
+            if labels.contains(&tag.identity()) {
+                ids.push(persona.to_owned());
+            }
+            if !ids.iter().eq(tag.persona_ids()) {
+                tag.set_persona_ids(ids);
+            }
+        }
+    }
+
+    fn walk(
+        &self,
+        tag: Tag,
+        depth: usize,
+        visited: &mut HashSet<Identity>,
+        result: &mut Vec<(usize, Tag)>,
+    ) {
+        let label = make_ref(&tag).identity();
+        if !visited.insert(label.to_owned()) {
+            return;
+        }
+        result.push((depth, tag));
+        for child in self.children(&label).collect::<Vec<_>>() {
+            self.walk(child, depth + 1, visited, result);
+        }
+    }
+}
+
+/// Evaluates tag members, remembering the tags already worked out.
+pub struct Membership<'a> {
+    tags: &'a TagContainer,
+    people: &'a PersonaContainer,
+    known: HashMap<Identity, Vec<Identity>>,
+    /// Tags being worked out; a rule referring back to one of them sees it empty.
+    pending: HashSet<Identity>,
+    /// Pending tags seen empty by the tags being worked out. A result that
+    /// depends on another tag's pending state is not remembered, so the
+    /// members do not depend on the order the tags are asked for.
+    cut: HashSet<Identity>,
+}
+
+impl<'a> Membership<'a> {
+    pub fn new(tags: &'a TagContainer, people: &'a PersonaContainer) -> Self {
+        Self {
+            tags,
+            people,
+            known: HashMap::new(),
+            pending: HashSet::new(),
+            cut: HashSet::new(),
+        }
+    }
+
+    pub fn tags(&self) -> &'a TagContainer {
+        self.tags
+    }
+
+    pub fn people(&self) -> &'a PersonaContainer {
+        self.people
+    }
+
+    /// Members listed in the tag, then those matching its rule, then the same
+    /// for every tag below it, each once.
+    pub fn members(&mut self, label: &str) -> Vec<Identity> {
+        if let Some(members) = self.known.get(label) {
+            return members.clone();
+        }
+        if !self.pending.insert(label.to_owned()) {
+            self.cut.insert(label.to_owned());
+            return vec![];
+        }
+        let outer_cut = std::mem::take(&mut self.cut);
+        let mut seen = HashSet::new();
+        let mut result = vec![];
+        for label in self.tags.descendants(label) {
+            for id in self.own_members(&label) {
+                if seen.insert(id.to_owned()) {
+                    result.push(id);
+                }
+            }
+        }
+        self.pending.remove(label);
+        self.cut.remove(label);
+        if self.cut.is_empty() {
+            self.known.insert(label.to_owned(), result.clone());
+        }
+        self.cut.extend(outer_cut);
+        result
+    }
+
+    fn own_members(&mut self, label: &str) -> Vec<Identity> {
+        let Some(tag) = self.tags.get(label) else {
+            return vec![];
+        };
+        let tag = make_ref(&tag);
+        let mut result = tag.persona_ids().cloned().collect::<Vec<_>>();
+        if let Some(rule) = tag.rule() {
+            let people = self.people;
+            for persona in people.all_representations() {
+                let persona = make_ref(&persona);
+                let id = persona.identity();
+                if rule.matches(&persona, |other| self.members(other).contains(&id)) {
+                    result.push(id);
+                }
+            }
+        }
+        result
+    }
+}
+
+/// Whether making `parent` the parent of `label` closes a loop, that is
+/// `parent` is `label` or lies below it. `parent_of` looks up the current parents.
+pub fn creates_cycle(
+    label: &str,
+    parent: &str,
+    parent_of: impl Fn(&str) -> Option<Identity>,
+) -> bool {
+    let mut seen = HashSet::new();
+    let mut current = Some(parent.to_owned());
+    while let Some(tag) = current {
+        if tag == label {
+            return true;
+        }
+        if !seen.insert(tag.to_owned()) {
+            return false;
+        }
+        current = parent_of(&tag);
+    }
+    false
+}
+
+impl Versioned for TagRepr {
+    const MIGRATIONS: &'static [Migration] = &[schema::add_envelope, add_parent, add_rule];
+}
+
+/// Tags of version 1 were all top-level.
+fn add_parent(record: &mut Value) {
+    if let Some(tag) = record.as_object_mut() {
+        tag.entry("parent").or_insert(Value::Null);
+    }
+}
+
+/// Tags of version 2 were lists only.
+fn add_rule(record: &mut Value) {
+    if let Some(tag) = record.as_object_mut() {
+        tag.entry("rule").or_insert(Value::Null);
+    }
 }
 
 impl Represent for TagRepr {
@@ -46,3 +293,127 @@ impl Represent for TagRepr {
 pub fn new_tag(label: impl ToString) -> Tag {
     Rc::new(RefCell::new(TagRepr::new(label)))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data_handler::persona::PersonaRepr;
+
+    fn tags(pairs: &[(&str, Option<&str>, &[&str])]) -> TagContainer {
+        let mut container = TagContainer::new();
+        for (label, parent, members) in pairs {
+            let tag = new_tag(label);
+            tag.borrow_mut().set_parent(parent.map(str::to_owned));
+            tag.borrow_mut()
+                .set_persona_ids(members.iter().map(|id| id.to_string()).collect());
+            container.insert_or_update(tag);
+        }
+        container
+    }
+
+    #[test]
+    fn test_tree_and_members() {
+        let container = tags(&[
+            ("Институт", None, &["Ректор"]),
+            ("Кафедра", Some("Институт"), &["Иванов", "Ректор"]),
+            ("Лаборатория", Some("Кафедра"), &["Петров"]),
+            ("Совет", Some("Удалённая"), &[]),
+        ]);
+        let tree = container
+            .tree()
+            .into_iter()
+            .map(|(depth, tag)| (depth, make_ref(&tag).identity()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            tree,
+            vec![
+                (0, "Институт".to_owned()),
+                (1, "Кафедра".to_owned()),
+                (2, "Лаборатория".to_owned()),
+                (0, "Совет".to_owned()),
+            ]
+        );
+        let people = PersonaContainer::new();
+        assert_eq!(
+            container.members("Институт", &people),
+            vec!["Ректор", "Иванов", "Петров"]
+        );
+        assert_eq!(container.members("Лаборатория", &people), vec!["Петров"]);
+    }
+
+    #[test]
+    fn test_set_memberships() {
+        let mut container = tags(&[
+            ("Институт", None, &["Иванов", "Петров"]),
+            ("Кафедра", None, &["Петров"]),
+            ("Совет", None, &[]),
+        ]);
+        container.set_memberships("Иванов", "Иванов-Петров", &["Совет".to_owned()]);
+        let members = |label: &str| {
+            make_ref(&container.get(label).unwrap())
+                .persona_ids()
+                .cloned()
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(members("Институт"), vec!["Петров"]);
+        assert_eq!(members("Кафедра"), vec!["Петров"]);
+        assert_eq!(members("Совет"), vec!["Иванов-Петров"]);
+    }
+
+    #[test]
+    fn test_cycle_is_detected() {
+        let container = tags(&[
+            ("Институт", None, &[]),
+            ("Кафедра", Some("Институт"), &[]),
+            ("Лаборатория", Some("Кафедра"), &[]),
+        ]);
+        let parent_of = |label: &str| {
+            container
+                .get(label)
+                .and_then(|tag| make_ref(&tag).parent().map(str::to_owned))
+        };
+        assert!(creates_cycle("Институт", "Лаборатория", parent_of));
+        assert!(creates_cycle("Кафедра", "Кафедра", parent_of));
+        assert!(!creates_cycle("Лаборатория", "Институт", parent_of));
+    }
+
+    #[test]
+    fn test_smart_tag_members() {
+        let mut people = PersonaContainer::new();
+        for (family, email, position) in [
+            ("Иванов", "ivanov@phystech.edu", "профессор"),
+            ("Петров", "petrov@mipt.ru", "Профессор кафедры"),
+            ("Сидоров", "sidorov@mipt.ru", "доцент"),
+        ] {
+            let mut persona = PersonaRepr::new(family, "", "", email);
+            persona.set_position(position);
+            people.insert_or_update(Rc::new(RefCell::new(persona)));
+        }
+        let container = tags(&[
+            ("Профессора", None, &[]),
+            ("Физтех", None, &["Сидоров  "]),
+            ("Петля", None, &[]),
+        ]);
+        let set_rule = |label: &str, rule: &str| {
+            let tag = container.get(label).unwrap();
+            tag.borrow_mut().set_rule(Some(rule.parse().unwrap()));
+        };
+        set_rule("Профессора", "position:профессор");
+        set_rule("Физтех", "domain:phystech.edu | tag:Петля");
+        set_rule("Петля", "tag:Физтех & !tag:Профессора");
+
+        assert_eq!(
+            container.members("Профессора", &people),
+            vec!["Иванов  ", "Петров  "]
+        );
+        assert_eq!(
+            container.members("Физтех", &people),
+            vec!["Сидоров  ", "Иванов  "]
+        );
+        assert_eq!(container.members("Петля", &people), vec!["Сидоров  "]);
+
+        let mut membership = Membership::new(&container, &people);
+        assert_eq!(membership.members("Физтех"), vec!["Сидоров  ", "Иванов  "]);
+        assert_eq!(membership.members("Петля"), vec!["Сидоров  "]);
+    }
+}
@@ -0,0 +1,133 @@
+use std::{cell::RefCell, rc::Rc};
+
+use serde::{Deserialize, Serialize};
+
+use super::{
+    attached_file::{self, AttachedFile},
+    data_container::DataContainer,
+    letter::LetterRepr,
+    schema::{self, Migration, Versioned},
+    Identity, Represent,
+};
+
+pub type TemplateContainer = DataContainer<TemplateRepr>;
+pub type Template = Rc<RefCell<TemplateRepr>>;
+
+/// A letter to start from, kept under its own name.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct TemplateRepr {
+    name: String,
+    topic: String,
+    text: String,
+    attachment: Vec<AttachedFile>,
+    /// The letters start addressed to the members of this tag.
+    tag: Option<Identity>,
+}
+
+impl TemplateRepr {
+    pub fn new(name: impl ToString) -> Self {
+        Self {
+            name: name.to_string(),
+            ..Default::default()
+        }
+    }
+
+    /// Named after the topic of the letter.
+    pub fn from_letter(letter: &LetterRepr) -> Self {
+        let name = match letter.get_topic().trim() {
+            "" => "Шаблон",
+            topic => topic,
+        };
+        Self {
+            name: name.to_owned(),
+            topic: letter.get_topic().to_owned(),
+            text: letter.get_text().to_owned(),
+            attachment: letter.attachments().cloned().collect(),
+            tag: None,
+        }
+    }
+
+    /// A new letter with the topic, text and attachments of the template.
+    pub fn to_letter(&self) -> LetterRepr {
+        let mut letter = LetterRepr::new();
+        letter.set_topic(&self.topic);
+        letter.set_text(&self.text);
+        letter.set_attachments(self.attachment.clone());
+        letter
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn set_name(&mut self, name: impl ToString) {
+        self.name = name.to_string()
+    }
+
+    pub fn topic(&self) -> &str {
+        &self.topic
+    }
+
+    pub fn set_topic(&mut self, topic: impl ToString) {
+        self.topic = topic.to_string()
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    pub fn set_text(&mut self, text: impl ToString) {
+        self.text = text.to_string()
+    }
+
+    pub fn tag(&self) -> Option<&str> {
+        self.tag.as_deref()
+    }
+
+    pub fn set_tag(&mut self, tag: Option<Identity>) {
+        self.tag = tag
+    }
+
+    pub fn attachment_info(&self) -> String {
+        attached_file::attachment_info(self.attachment.iter())
+    }
+
+    pub fn clear_attachment(&mut self) {
+        self.attachment.clear();
+    }
+}
+
+impl Versioned for TemplateRepr {
+    const MIGRATIONS: &'static [Migration] = &[schema::add_envelope];
+}
+
+impl Represent for TemplateRepr {
+    fn identity(&self) -> Identity {
+        self.name.to_owned()
+    }
+}
+
+pub fn new_template(template: TemplateRepr) -> Template {
+    Rc::new(RefCell::new(template))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_template_roundtrip() {
+        let mut letter = LetterRepr::new();
+        letter.set_topic("Семинар");
+        letter.set_text("Приглашаем на семинар.");
+        let template = TemplateRepr::from_letter(&letter);
+        assert_eq!(template.identity(), "Семинар");
+        let copy = template.to_letter();
+        assert_eq!(copy.get_topic(), "Семинар");
+        assert_eq!(copy.get_text(), "Приглашаем на семинар.");
+        assert_eq!(
+            TemplateRepr::from_letter(&LetterRepr::new()).name(),
+            "Шаблон"
+        );
+    }
+}
@@ -0,0 +1,111 @@
+use std::{cell::RefCell, fs, io, path::Path, rc::Rc};
+
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use super::{
+    backup, data_container::DataContainer, letter::DateTime, make_ref, Identity, Represent,
+};
+
+/// A removed record and the time it was removed.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Trashed<Repr> {
+    pub removed: DateTime,
+    pub item: Rc<RefCell<Repr>>,
+}
+
+/// Removed records of one container, oldest first.
+#[derive(Debug)]
+pub struct Trash<Repr> {
+    items: Vec<Trashed<Repr>>,
+}
+
+impl<Repr> Trash<Repr> {
+    pub fn new() -> Self {
+        Self { items: vec![] }
+    }
+
+    pub fn items(&self) -> impl Iterator<Item = &Trashed<Repr>> + '_ {
+        self.items.iter()
+    }
+
+    pub fn size(&self) -> usize {
+        self.items.len()
+    }
+
+    /// When the newest item was removed.
+    pub fn last_removed(&self) -> Option<&DateTime> {
+        self.items.last().map(|trashed| &trashed.removed)
+    }
+
+    pub fn purge(&mut self, index: usize) -> Option<Rc<RefCell<Repr>>> {
+        (index < self.items.len()).then(|| self.items.remove(index).item)
+    }
+
+    pub fn purge_all(&mut self) {
+        self.items.clear();
+    }
+}
+
+impl<Repr> Default for Trash<Repr> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<Repr: Represent> Trash<Repr> {
+    /// Moves the record out of `container`; returns `false` if it was not there.
+    pub fn take_from(
+        &mut self,
+        container: &mut DataContainer<Repr>,
+        identity: impl AsRef<str>,
+    ) -> bool {
+        self.take_all_from(container, [identity]) == 1
+    }
+
+    /// Moves the records out of `container` as one batch, removed at the
+    /// same time; returns how many of them were there.
+    pub fn take_all_from(
+        &mut self,
+        container: &mut DataContainer<Repr>,
+        identities: impl IntoIterator<Item = impl AsRef<str>>,
+    ) -> usize {
+        let removed = chrono::Local::now();
+        let before = self.items.len();
+        for identity in identities {
+            if let Some(item) = container.remove_identity(identity) {
+                self.items.push(Trashed { removed, item });
+            }
+        }
+        self.items.len() - before
+    }
+
+    /// Puts the item back into `container`, replacing a record that took its
+    /// identity meanwhile.
+    pub fn restore_into(
+        &mut self,
+        container: &mut DataContainer<Repr>,
+        index: usize,
+    ) -> Option<Identity> {
+        let item = self.purge(index)?;
+        let identity = make_ref(&item).identity();
+        container.insert_or_update(item);
+        Some(identity)
+    }
+}
+
+impl<Repr: Serialize> Trash<Repr> {
+    pub fn finalize(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let json = serde_json::to_string(&self.items)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        backup::save_with(path, &json, false)
+    }
+}
+
+impl<Repr: DeserializeOwned> Trash<Repr> {
+    pub fn restore(path: impl AsRef<Path>) -> io::Result<Self> {
+        let json = fs::read_to_string(path)?;
+        let items = serde_json::from_str(&json)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        Ok(Self { items })
+    }
+}
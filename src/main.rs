@@ -1,9 +1,19 @@
 pub mod app;
+pub mod cli;
 pub mod controller;
 pub mod data_handler;
 pub mod ui;
 
+#[cfg(test)]
+mod test_utils;
+
 fn main() {
-    let app = app::App::new();
-    app.go();
+    let args = std::env::args().skip(1).collect::<Vec<_>>();
+    if args.is_empty() {
+        let app = app::App::new();
+        app.go();
+    } else if let Err(e) = cli::run(&args) {
+        eprintln!("letcom: {}", e);
+        std::process::exit(1);
+    }
 }
@@ -0,0 +1,37 @@
+use std::{
+    env, fs,
+    ops::Deref,
+    path::{Path, PathBuf},
+};
+
+/// A fresh directory under the system temporary directory, removed on drop.
+pub struct TempDir(PathBuf);
+
+impl TempDir {
+    /// `name` tells which test module the directory belongs to.
+    pub fn new(name: &str) -> Self {
+        let dir = env::temp_dir().join(format!("letcom-{}-{}", name, uuid::Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        Self(dir)
+    }
+}
+
+impl Deref for TempDir {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl AsRef<Path> for TempDir {
+    fn as_ref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        fs::remove_dir_all(&self.0).ok();
+    }
+}
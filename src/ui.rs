@@ -1,4 +1,4 @@
-use std::sync::mpsc;
+use std::{path::PathBuf, sync::mpsc};
 
 use cursive::{
     event::{Event, Key},
@@ -8,8 +8,20 @@ use cursive::{
 };
 
 use crate::{
-    controller::{settings::Settings, ControllerSignal},
-    data_handler::{letter::Letter, persona::Persona, tag::Tag, Identity},
+    controller::{
+        presenter::Presenter, settings::Settings, worker::SendProgress, ControllerSignal,
+    },
+    data_handler::{
+        drafts::Draft,
+        handler::{DataFile, TrashItem},
+        letter::{Letter, LetterFilter},
+        persona::Persona,
+        report::ContactReport,
+        search::SearchIndex,
+        tag::Tag,
+        template::Template,
+        Identity,
+    },
 };
 
 pub struct Ui {
@@ -30,8 +42,10 @@ impl Ui {
             controller_tx,
         }
     }
+}
 
-    pub fn step_next(&mut self) {
+impl Presenter for Ui {
+    fn step_next(&mut self) {
         if self.runner.is_running() {
             self.runner.step();
             self.runner.refresh();
@@ -39,38 +53,57 @@ impl Ui {
             self.controller_tx.send(ControllerSignal::Quit).unwrap();
         }
     }
-}
 
-impl Ui {
-    pub(crate) fn settings_form(&mut self, settings: Settings) {
-        self.runner.add_layer(forms::settings::SettingsForm::new(
-            settings,
-            &self.controller_tx,
-        ))
+    fn settings_form(&mut self, settings: Settings) {
+        self.runner.add_layer(
+            forms::settings::SettingsForm::new(settings, &self.controller_tx)
+                .with_name(forms::settings::SettingsForm::NAME),
+        )
     }
 
-    pub(crate) fn select_tag_form(&mut self, tags: Vec<Tag>) {
+    fn select_tag_form(&mut self, tree: Vec<(usize, Tag, usize)>) {
         self.runner.add_layer(forms::selecttag::SelectTagForm::new(
-            tags,
+            tree,
             &self.controller_tx,
         ));
     }
 
-    pub(crate) fn tag_form(&mut self, key: Identity, tag: Tag, persona_list: &[Identity]) {
+    fn tag_form(&mut self, key: Identity, tag: Tag, persona_list: &[Identity], tags: Vec<Tag>) {
         self.runner.add_layer(forms::tag::TagForm::new(
             key,
             tag,
             persona_list,
+            tags,
             &self.controller_tx,
         ))
     }
 
-    pub(crate) fn remove_tag_dialog(&mut self, tag: Tag) {
+    fn remove_tag_dialog(&mut self, tag: Tag) {
+        self.runner
+            .add_layer(dialogs::remove_alerts::remove_tag_alert(
+                tag,
+                &self.controller_tx,
+            ));
+    }
+
+    fn select_template_form(&mut self, templates: Vec<Template>) {
         self.runner
-            .add_layer(dialogs::remove_alerts::remove_tag_alert(tag));
+            .add_layer(forms::selecttemplate::SelectTemplateForm::new(
+                templates,
+                &self.controller_tx,
+            ));
+    }
+
+    fn template_form(&mut self, key: Identity, template: Template, tags: Vec<Identity>) {
+        self.runner.add_layer(forms::template::TemplateForm::new(
+            key,
+            template,
+            tags,
+            &self.controller_tx,
+        ));
     }
 
-    pub(crate) fn letter_form(&mut self, key: Identity, letter: Letter) {
+    fn letter_form(&mut self, key: Identity, letter: Letter) {
         let form_name = key.to_string();
         if let Some(_form) = self
             .runner
@@ -90,16 +123,69 @@ impl Ui {
         }
     }
 
-    pub(crate) fn send_letter_form(&mut self, letter: Letter, people: Vec<Persona>) {
+    fn recover_drafts_dialog(&mut self, drafts: Vec<Draft>) {
+        self.runner
+            .add_layer(dialogs::recover_drafts::recover_drafts_dialog(
+                drafts,
+                &self.controller_tx,
+            ));
+    }
+
+    fn select_letter_form(&mut self, letters: Vec<Letter>, filter: LetterFilter) {
+        self.runner
+            .add_layer(forms::selectletter::SelectLetterForm::new(
+                letters,
+                filter,
+                &self.controller_tx,
+            ));
+    }
+
+    fn search_form(&mut self, index: SearchIndex) {
+        self.runner
+            .add_layer(forms::search::SearchForm::new(index, &self.controller_tx));
+    }
+
+    fn report_form(&mut self, report: ContactReport) {
+        self.runner
+            .add_layer(forms::report::ReportForm::new(report, &self.controller_tx));
+    }
+
+    fn schedule_form(&mut self, letters: Vec<Letter>) {
+        self.runner.add_layer(forms::schedule::ScheduleForm::new(
+            letters,
+            &self.controller_tx,
+        ));
+    }
+
+    fn overdue_dialog(&mut self, letters: Vec<Letter>) {
+        self.runner.add_layer(dialogs::overdue::overdue_dialog(
+            letters,
+            &self.controller_tx,
+        ));
+    }
+
+    fn send_letter_form(
+        &mut self,
+        letter: Letter,
+        people: Vec<Persona>,
+        tags: Vec<Tag>,
+        profiles: Vec<String>,
+        default_profile: String,
+        to: Vec<Identity>,
+    ) {
         self.runner
             .add_layer(forms::sendletter::SendLetterForm::new(
                 letter,
                 people,
+                tags,
+                profiles,
+                default_profile,
+                to,
                 &self.controller_tx,
             ));
     }
 
-    pub(crate) fn select_persona_form(&mut self, persona: Vec<Persona>) {
+    fn select_persona_form(&mut self, persona: Vec<Persona>, tags: Vec<Tag>) {
         if persona.is_empty() {
             self.controller_tx
                 .send(ControllerSignal::Log(
@@ -110,21 +196,23 @@ impl Ui {
             self.runner
                 .add_layer(forms::selectpersona::SelectPersonaForm::new(
                     persona,
+                    tags,
                     &self.controller_tx,
                 ));
         }
     }
 
-    pub(crate) fn edit_persona_form(&mut self, key: Identity, persona: Persona) {
+    fn edit_persona_form(&mut self, key: Identity, persona: Persona, tags: Vec<(usize, Tag)>) {
         self.runner
             .add_layer(forms::editpersona::EditPersonaForm::new(
                 key,
                 persona,
+                tags,
                 &self.controller_tx,
             ));
     }
 
-    pub(crate) fn remove_persona_dialog(&mut self, persona: Persona) {
+    fn remove_persona_dialog(&mut self, persona: Vec<Persona>) {
         self.runner
             .add_layer(dialogs::remove_alerts::remove_persona_alert(
                 persona,
@@ -132,8 +220,64 @@ impl Ui {
             ));
     }
 
-    pub(crate) fn present_info(&mut self, info: impl AsRef<str>) {
-        self.runner.add_layer(Dialog::info(info.as_ref()));
+    fn unlock_secrets_dialog(&mut self, is_new: bool) {
+        self.runner
+            .add_layer(dialogs::unlock::unlock_secrets_dialog(
+                is_new,
+                &self.controller_tx,
+            ));
+    }
+
+    fn send_progress_dialog(&mut self, progress: &SendProgress) {
+        self.runner
+            .add_layer(dialogs::send_progress::send_progress_dialog(
+                progress,
+                &self.controller_tx,
+            ));
+    }
+
+    fn send_progress(&mut self, progress: &SendProgress) {
+        // A closed dialog stays closed until the next mailing.
+        dialogs::send_progress::update_send_progress(&mut self.runner, progress);
+    }
+
+    fn read_only_dialog(&mut self, info: &str) {
+        let tx = self.controller_tx.clone();
+        self.runner.add_layer(
+            Dialog::around(TextView::new(info))
+                .title("Только чтение")
+                .button("Read-only", |c| {
+                    c.pop_layer();
+                })
+                .button("Quit", move |_| tx.send(ControllerSignal::Quit).unwrap()),
+        );
+    }
+
+    fn load_error_dialog(&mut self, file: DataFile, info: &str, backup: Option<PathBuf>) {
+        self.runner
+            .add_layer(dialogs::load_error::load_error_dialog(
+                file,
+                info,
+                backup,
+                &self.controller_tx,
+            ));
+    }
+
+    fn restore_backup_form(&mut self, backups: Vec<(DataFile, PathBuf)>) {
+        self.runner
+            .add_layer(dialogs::restore_backup::restore_backup_dialog(
+                backups,
+                &self.controller_tx,
+            ));
+    }
+
+    fn trash_form(&mut self, items: Vec<TrashItem>) {
+        self.runner
+            .add_layer(dialogs::trash::trash_dialog(items, &self.controller_tx));
+    }
+
+    fn present_info(&mut self, info: &str) {
+        self.runner.add_layer(Dialog::info(info));
     }
 }
 
@@ -0,0 +1,58 @@
+use std::{path::PathBuf, sync::mpsc};
+
+use cursive::{
+    view::{Nameable, Resizable},
+    views::{Dialog, EditView, LinearLayout, TextView},
+    View,
+};
+
+use crate::{controller::ControllerSignal, data_handler::handler::DataFile};
+
+pub fn load_error_dialog(
+    file: DataFile,
+    info: &str,
+    backup: Option<PathBuf>,
+    controller_tx: &mpsc::Sender<ControllerSignal>,
+) -> impl View {
+    let backup_name = format!("load_backup_{}", file.file_name());
+    let retry_tx = controller_tx.clone();
+    let backup_tx = controller_tx.clone();
+    let empty_tx = controller_tx.clone();
+    Dialog::around(
+        LinearLayout::vertical()
+            .child(TextView::new(info))
+            .child(TextView::new("\nРезервная копия:"))
+            .child(
+                EditView::new()
+                    .content(
+                        backup
+                            .map(|backup| backup.to_string_lossy().into_owned())
+                            .unwrap_or_default(),
+                    )
+                    .with_name(backup_name.to_owned())
+                    .full_width(),
+            ),
+    )
+    .title(format!("Ошибка загрузки: {}", file))
+    .button("Retry", move |c| {
+        retry_tx.send(ControllerSignal::RetryLoad(file)).unwrap();
+        c.pop_layer();
+    })
+    .button("Open backup", move |c| {
+        let path = c
+            .call_on_name(&backup_name, |v: &mut EditView| v.get_content())
+            .unwrap();
+        backup_tx
+            .send(ControllerSignal::LoadBackup {
+                file,
+                path: path.to_string(),
+            })
+            .unwrap();
+        c.pop_layer();
+    })
+    .button("Start empty", move |c| {
+        empty_tx.send(ControllerSignal::StartEmpty(file)).unwrap();
+        c.pop_layer();
+    })
+    .max_width(80)
+}
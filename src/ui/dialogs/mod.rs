@@ -2,5 +2,12 @@ pub trait SetData {
     fn set_data(&mut self, data: String);
 }
 
+pub mod load_error;
 pub mod open_file;
+pub mod overdue;
+pub mod recover_drafts;
 pub mod remove_alerts;
+pub mod restore_backup;
+pub mod send_progress;
+pub mod trash;
+pub mod unlock;
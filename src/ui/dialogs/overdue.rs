@@ -0,0 +1,70 @@
+use std::sync::mpsc;
+
+use cursive::{
+    views::{Dialog, TextView},
+    View,
+};
+
+use crate::{
+    controller::ControllerSignal,
+    data_handler::{
+        letter::{Letter, LetterRepr, LetterState},
+        make_ref,
+    },
+    ui::utils::TIME_FORMAT,
+};
+
+/// "Later" opens the schedule; the mailings stay held until they are
+/// rescheduled or sent on the user's word.
+pub fn overdue_dialog(
+    letters: Vec<Letter>,
+    controller_tx: &mpsc::Sender<ControllerSignal>,
+) -> impl View {
+    let list = letters
+        .iter()
+        .map(|letter| schedule_line(&make_ref(letter)))
+        .collect::<Vec<_>>()
+        .join("\n");
+    let send_tx = controller_tx.clone();
+    let cancel_tx = controller_tx.clone();
+    let schedule_tx = controller_tx.clone();
+    Dialog::around(TextView::new(format!(
+        "Пока программа была закрыта, наступило время рассылок:\n{}",
+        list
+    )))
+    .title("Просроченные рассылки")
+    .button("Send now", move |c| {
+        send_tx.send(ControllerSignal::SendOverdue).unwrap();
+        c.pop_layer();
+    })
+    .button("Cancel them", move |c| {
+        cancel_tx.send(ControllerSignal::CancelOverdue).unwrap();
+        c.pop_layer();
+    })
+    .button("Later", move |c| {
+        schedule_tx.send(ControllerSignal::OpenSchedule).unwrap();
+        c.pop_layer();
+    })
+}
+
+/// `<time> — <topic> (<n> адресатов)`, marked when the mailing failed to start.
+pub fn schedule_line(letter: &LetterRepr) -> String {
+    let topic = match letter.get_topic() {
+        "" => "(без темы)",
+        topic => topic,
+    };
+    let failed = match letter.state() {
+        LetterState::Failed => ", не отправлено",
+        _ => "",
+    };
+    match letter.schedule() {
+        Some(schedule) => format!(
+            "{} — {} ({} адресатов{})",
+            schedule.at.format(TIME_FORMAT),
+            topic,
+            schedule.count(),
+            failed
+        ),
+        None => topic.to_owned(),
+    }
+}
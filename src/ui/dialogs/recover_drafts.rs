@@ -0,0 +1,44 @@
+use std::sync::mpsc;
+
+use cursive::{
+    views::{Dialog, TextView},
+    View,
+};
+
+use crate::{controller::ControllerSignal, data_handler::drafts::Draft};
+
+pub fn recover_drafts_dialog(
+    drafts: Vec<Draft>,
+    controller_tx: &mpsc::Sender<ControllerSignal>,
+) -> impl View {
+    let list = drafts
+        .iter()
+        .map(|draft| {
+            let topic = if draft.topic.is_empty() {
+                "(без темы)"
+            } else {
+                draft.topic.as_str()
+            };
+            format!("{} — {}", draft.saved.format("%d.%m.%Y %H:%M"), topic)
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+    let recover_tx = controller_tx.clone();
+    let discard_tx = controller_tx.clone();
+    Dialog::around(TextView::new(format!(
+        "Остались несохранённые черновики писем:\n{}",
+        list
+    )))
+    .title("Черновики")
+    .button("Recover", move |c| {
+        recover_tx.send(ControllerSignal::RecoverDrafts).unwrap();
+        c.pop_layer();
+    })
+    .button("Discard", move |c| {
+        discard_tx.send(ControllerSignal::DiscardDrafts).unwrap();
+        c.pop_layer();
+    })
+    .button("Later", |c| {
+        c.pop_layer();
+    })
+}
@@ -10,36 +10,60 @@ use crate::{
     data_handler::{make_ref, persona::Persona, tag::Tag, Represent},
 };
 
+/// One confirmation for all the personas.
 pub fn remove_persona_alert(
-    persona: Persona,
+    persona: Vec<Persona>,
     controller_tx: &mpsc::Sender<ControllerSignal>,
 ) -> impl View {
+    const LISTED: usize = 10;
     let tx = controller_tx.clone();
-    let persona_identity = make_ref(&persona).identity();
-    Dialog::around(TextView::new(format!(
-        "Вы уверены, что хотите удалить\n{:?}?",
-        persona_identity
-    )))
-    .title(format!("Удаляем {}", persona_identity))
-    .button("Yes", move |c| {
-        tx.send(ControllerSignal::RemovePersona(persona.clone()))
-            .unwrap();
-        c.pop_layer();
-    })
-    .button("No", |c| {
-        c.pop_layer();
-    })
-    .button("Cancel", |c| {
-        c.pop_layer();
-    })
+    let identities = persona
+        .iter()
+        .map(|persona| format!("{:?}", make_ref(persona).identity()))
+        .collect::<Vec<_>>();
+    let (question, title) = match &identities[..] {
+        [identity] => (
+            format!("Вы уверены, что хотите удалить\n{}?", identity),
+            format!("Удаляем {}", identity.trim_matches('"')),
+        ),
+        _ => {
+            let mut listed = identities[..identities.len().min(LISTED)].join("\n");
+            if identities.len() > LISTED {
+                listed.push_str(&format!("\nи ещё {}", identities.len() - LISTED));
+            }
+            (
+                format!(
+                    "Вы уверены, что хотите удалить {} персон?\n{}",
+                    identities.len(),
+                    listed
+                ),
+                format!("Удаляем {} персон", identities.len()),
+            )
+        }
+    };
+    Dialog::around(TextView::new(question))
+        .title(title)
+        .button("Yes", move |c| {
+            tx.send(ControllerSignal::RemovePersona(persona.clone()))
+                .unwrap();
+            c.pop_layer();
+        })
+        .button("No", |c| {
+            c.pop_layer();
+        })
+        .button("Cancel", |c| {
+            c.pop_layer();
+        })
 }
 
-pub fn remove_tag_alert(tag: Tag) -> impl View {
+pub fn remove_tag_alert(tag: Tag, controller_tx: &mpsc::Sender<ControllerSignal>) -> impl View {
+    let tx = controller_tx.clone();
     Dialog::around(TextView::new(format!(
         "Вы уверены, что хотите удалить метку:\n{:?}",
         make_ref(&tag).label()
     )))
-    .button("Yes", |c| {
+    .button("Yes", move |c| {
+        tx.send(ControllerSignal::RemoveTag(tag.clone())).unwrap();
         c.pop_layer();
     })
     .button("No", |c| {
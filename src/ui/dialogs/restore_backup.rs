@@ -0,0 +1,37 @@
+use std::{path::PathBuf, sync::mpsc};
+
+use cursive::{
+    view::{Resizable, Scrollable},
+    views::{Dialog, SelectView},
+    View,
+};
+
+use crate::{
+    controller::ControllerSignal,
+    data_handler::{backup::backup_time, handler::DataFile},
+};
+
+pub fn restore_backup_dialog(
+    backups: Vec<(DataFile, PathBuf)>,
+    controller_tx: &mpsc::Sender<ControllerSignal>,
+) -> impl View {
+    let tx = controller_tx.clone();
+    let mut select = SelectView::new();
+    for (file, backup) in backups {
+        let label = format!("{:<8} {}", file.to_string(), backup_time(&backup));
+        select.add_item(label, (file, backup));
+    }
+    select.set_on_submit(move |c, (file, backup): &(DataFile, PathBuf)| {
+        tx.send(ControllerSignal::LoadBackup {
+            file: *file,
+            path: backup.to_string_lossy().into_owned(),
+        })
+        .unwrap();
+        c.pop_layer();
+    });
+    Dialog::around(select.scrollable().max_height(20))
+        .title("Восстановить из резервной копии")
+        .button("Cancel", |c| {
+            c.pop_layer();
+        })
+}
@@ -0,0 +1,66 @@
+use std::{sync::mpsc, time::Duration};
+
+use cursive::{
+    view::{Nameable, Resizable, Scrollable},
+    views::{Dialog, TextView},
+    Cursive, View,
+};
+
+use crate::controller::{worker::SendProgress, ControllerSignal};
+
+pub const DIALOG_NAME: &str = "send_progress";
+const TEXT_NAME: &str = "send_progress_text";
+
+pub fn send_progress_dialog(
+    progress: &SendProgress,
+    controller_tx: &mpsc::Sender<ControllerSignal>,
+) -> impl View {
+    let cancel_tx = controller_tx.clone();
+    Dialog::around(
+        TextView::new(progress.describe())
+            .with_name(TEXT_NAME)
+            .scrollable()
+            .max_height(20)
+            .min_width(50),
+    )
+    .title(title(progress))
+    .button("Cancel", move |_| {
+        cancel_tx.send(ControllerSignal::CancelSending).unwrap();
+    })
+    .button("Close", |c| {
+        c.pop_layer();
+    })
+    .with_name(DIALOG_NAME)
+}
+
+/// Refreshes the progress dialog if it is still open.
+pub fn update_send_progress(c: &mut Cursive, progress: &SendProgress) {
+    c.call_on_name(TEXT_NAME, |v: &mut TextView| {
+        v.set_content(progress.describe())
+    });
+    c.call_on_name(DIALOG_NAME, |v: &mut Dialog| v.set_title(title(progress)));
+}
+
+fn title(progress: &SendProgress) -> String {
+    let title = format!(
+        "Отправка {:?}: {}/{}",
+        progress.topic,
+        progress.done(),
+        progress.jobs.len()
+    );
+    match progress.remaining {
+        Some(remaining) if !progress.finished && !remaining.is_zero() => {
+            format!("{}, осталось {}", title, describe_duration(remaining))
+        }
+        _ => title,
+    }
+}
+
+fn describe_duration(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    match seconds {
+        0..=59 => format!("~{} с", seconds.max(1)),
+        60..=3599 => format!("~{} мин", seconds.div_ceil(60)),
+        _ => format!("~{} ч {} мин", seconds / 3600, seconds % 3600 / 60),
+    }
+}
@@ -0,0 +1,71 @@
+use std::sync::mpsc;
+
+use cursive::{
+    view::{Nameable, Resizable, Scrollable},
+    views::{Dialog, SelectView},
+    Cursive, View,
+};
+
+use crate::{
+    controller::ControllerSignal,
+    data_handler::handler::{DataFile, TrashItem},
+};
+
+const SELECT_NAME: &str = "trash_items";
+
+pub fn trash_dialog(
+    items: Vec<TrashItem>,
+    controller_tx: &mpsc::Sender<ControllerSignal>,
+) -> impl View {
+    let mut select = SelectView::new();
+    for item in items {
+        let label = format!(
+            "{:<8} {}  (удалено {})",
+            item.file.to_string(),
+            item.label,
+            item.removed.format("%d.%m.%Y %H:%M")
+        );
+        select.add_item(label, (item.file, item.index));
+    }
+    let restore_tx = controller_tx.clone();
+    let purge_tx = controller_tx.clone();
+    let empty_tx = controller_tx.clone();
+    Dialog::around(
+        select
+            .with_name(SELECT_NAME)
+            .scrollable()
+            .max_height(20)
+            .min_width(50),
+    )
+    .title("Корзина")
+    .button("Restore", move |c| {
+        if let Some((file, index)) = selected(c) {
+            restore_tx
+                .send(ControllerSignal::RestoreFromTrash { file, index })
+                .unwrap();
+            c.pop_layer();
+        }
+    })
+    .button("Purge", move |c| {
+        if let Some((file, index)) = selected(c) {
+            purge_tx
+                .send(ControllerSignal::PurgeFromTrash { file, index })
+                .unwrap();
+            c.pop_layer();
+        }
+    })
+    .button("Empty trash", move |c| {
+        empty_tx.send(ControllerSignal::EmptyTrash).unwrap();
+        c.pop_layer();
+    })
+    .button("Close", |c| {
+        c.pop_layer();
+    })
+}
+
+fn selected(c: &mut Cursive) -> Option<(DataFile, usize)> {
+    c.call_on_name(SELECT_NAME, |v: &mut SelectView<(DataFile, usize)>| {
+        v.selection().map(|item| *item)
+    })
+    .flatten()
+}
@@ -0,0 +1,54 @@
+use std::sync::mpsc;
+
+use cursive::{
+    view::{Nameable, Resizable},
+    views::{Dialog, EditView, LinearLayout, TextView},
+    Cursive, View,
+};
+
+use crate::controller::ControllerSignal;
+
+const PASSPHRASE_NAME: &str = "master_passphrase";
+
+pub fn unlock_secrets_dialog(
+    is_new: bool,
+    controller_tx: &mpsc::Sender<ControllerSignal>,
+) -> impl View {
+    let text = if is_new {
+        "Задайте мастер-пароль для хранилища SMTP-паролей.\nБез него пароли будут храниться только до конца сеанса."
+    } else {
+        "Введите мастер-пароль для хранилища SMTP-паролей."
+    };
+    let submit_tx = controller_tx.clone();
+    let unlock_tx = controller_tx.clone();
+    let session_tx = controller_tx.clone();
+    Dialog::around(
+        LinearLayout::vertical().child(TextView::new(text)).child(
+            EditView::new()
+                .secret()
+                .on_submit(move |c, _| unlock(c, &submit_tx))
+                .with_name(PASSPHRASE_NAME)
+                .fixed_width(32),
+        ),
+    )
+    .title("Хранилище паролей")
+    .button("Unlock", move |c| unlock(c, &unlock_tx))
+    .button("Session only", move |c| {
+        session_tx
+            .send(ControllerSignal::UnlockSecrets(None))
+            .unwrap();
+        c.pop_layer();
+    })
+}
+
+fn unlock(c: &mut Cursive, controller_tx: &mpsc::Sender<ControllerSignal>) {
+    let passphrase = c
+        .call_on_name(PASSPHRASE_NAME, |v: &mut EditView| v.get_content())
+        .unwrap();
+    controller_tx
+        .send(ControllerSignal::UnlockSecrets(Some(
+            passphrase.to_string(),
+        )))
+        .unwrap();
+    c.pop_layer();
+}
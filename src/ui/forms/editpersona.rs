@@ -2,21 +2,25 @@ use std::sync::mpsc::{self, Sender};
 
 use cursive::{
     event::{Event, EventResult, Key, MouseButton, MouseEvent},
-    view::ViewWrapper,
-    views::{Dialog, DialogFocus},
+    view::{Scrollable, ViewWrapper},
+    views::{
+        Checkbox, Dialog, DialogFocus, LinearLayout, ListChild, ListView, ScrollView, TextView,
+    },
     wrap_impl, View,
 };
 
 use crate::{
     controller::ControllerSignal,
-    data_handler::{make_mut, make_ref, persona::Persona, Identity, Represent},
-    ui::utils::{dismiss, form_view, get_text_from_form_entry},
+    data_handler::{make_mut, make_ref, persona::Persona, tag::Tag, Identity, Represent},
+    ui::utils::{dismiss, get_from_layout, get_text_from_form_entry, linear_layout_form},
 };
 
 pub struct EditPersonaForm {
     view: Dialog,
     key: Identity,
     persona: Persona,
+    /// Tag labels in the order of the tag checkboxes.
+    tags: Vec<Identity>,
     controller_tx: mpsc::Sender<ControllerSignal>,
 }
 
@@ -25,12 +29,25 @@ impl EditPersonaForm {
     const NAME_INDEX: usize = 1;
     const SURNAME_INDEX: usize = 2;
     const EMAIL_INDEX: usize = 3;
+    const POSITION_INDEX: usize = 4;
+    const DEGREE_INDEX: usize = 5;
+    const TAGS_INDEX: usize = 7;
 
-    pub fn new(key: Identity, persona: Persona, controller_tx: &Sender<ControllerSignal>) -> Self {
+    /// `tags` is the tag tree with the depth of every tag.
+    pub fn new(
+        key: Identity,
+        persona: Persona,
+        tags: Vec<(usize, Tag)>,
+        controller_tx: &Sender<ControllerSignal>,
+    ) -> Self {
         Self {
-            view: init_dialog(&persona),
+            view: init_dialog(&key, &persona, &tags),
             key,
             persona,
+            tags: tags
+                .iter()
+                .map(|(_, tag)| make_ref(tag).identity())
+                .collect(),
             controller_tx: controller_tx.clone(),
         }
     }
@@ -48,16 +65,21 @@ impl EditPersonaForm {
         let name = get_text_from_form_entry(&self.view, Self::NAME_INDEX);
         let surname = get_text_from_form_entry(&self.view, Self::SURNAME_INDEX);
         let email = get_text_from_form_entry(&self.view, Self::EMAIL_INDEX);
+        let position = get_text_from_form_entry(&self.view, Self::POSITION_INDEX);
+        let degree = get_text_from_form_entry(&self.view, Self::DEGREE_INDEX);
         let mut persona = make_mut(&self.persona);
         persona.set_family(family);
         persona.set_name(name);
         persona.set_surname(surname);
         persona.set_email(email);
+        persona.set_position(position);
+        persona.set_degree(degree);
         drop(persona);
         self.controller_tx
             .send(ControllerSignal::CompleteEditPersona {
                 key: self.key.clone(),
                 persona: self.persona.clone(),
+                tags: self.get_checked_tags(),
             })
             .unwrap();
         dismiss()
@@ -66,6 +88,26 @@ impl EditPersonaForm {
     fn event_cancel(&self) -> EventResult {
         dismiss()
     }
+
+    fn get_checked_tags(&self) -> Vec<Identity> {
+        let layout = self
+            .view
+            .get_content()
+            .downcast_ref::<ScrollView<LinearLayout>>()
+            .unwrap()
+            .get_inner();
+        let list = get_from_layout::<ListView>(layout, Self::TAGS_INDEX);
+        let checks = list.children().iter().filter_map(|child| match child {
+            ListChild::Row(_, view) => view.downcast_ref::<Checkbox>(),
+            ListChild::Delimiter => None,
+        });
+        self.tags
+            .iter()
+            .zip(checks)
+            .filter(|(_, check)| check.is_checked())
+            .map(|(label, _)| label.to_owned())
+            .collect()
+    }
 }
 
 impl ViewWrapper for EditPersonaForm {
@@ -103,19 +145,49 @@ impl ViewWrapper for EditPersonaForm {
     }
 }
 
-fn init_dialog(persona: &Persona) -> Dialog {
-    Dialog::around(init_view(persona))
+fn init_dialog(key: &str, persona: &Persona, tags: &[(usize, Tag)]) -> Dialog {
+    Dialog::around(init_view(key, persona, tags))
         .title(format!("Редактируем {}", make_ref(persona).identity()))
         .button("Ok", |_| {})
         .button("Cancel", |_| {})
 }
 
-fn init_view(persona: &Persona) -> impl View {
+fn init_view(key: &str, persona: &Persona, tags: &[(usize, Tag)]) -> impl View {
     let persona = make_ref(persona);
-    form_view(vec![
-        (" Фамилия:", persona.get_family()),
-        ("     Имя:", persona.get_name()),
-        ("Отчество:", persona.get_surname()),
-        ("  E-mail:", persona.get_email()),
+    linear_layout_form(vec![
+        ("  Фамилия:", persona.get_family()),
+        ("      Имя:", persona.get_name()),
+        (" Отчество:", persona.get_surname()),
+        ("   E-mail:", persona.get_email()),
+        ("Должность:", persona.get_position()),
+        ("  Степень:", persona.get_degree()),
     ])
+    .child(TextView::new("    Метки:"))
+    .child(init_tag_list(key, tags))
+    .scrollable()
+}
+
+/// Checked are the tags listing the persona by hand; a rule (`*`) may add
+/// it to more.
+fn init_tag_list(key: &str, tags: &[(usize, Tag)]) -> ListView {
+    let mut view = ListView::new();
+    for (depth, tag) in tags {
+        let tag = make_ref(tag);
+        let label = format!(
+            "{}{}{}",
+            "  ".repeat(*depth),
+            tag.identity(),
+            if tag.rule().is_some() { "*" } else { "" }
+        );
+        let check_box = Checkbox::new();
+        view.add_child(
+            &label,
+            if tag.persona_ids().any(|id| id == key) {
+                check_box.checked()
+            } else {
+                check_box.unchecked()
+            },
+        );
+    }
+    view
 }
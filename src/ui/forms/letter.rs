@@ -9,7 +9,7 @@ use cursive::{
 
 use crate::{
     controller::ControllerSignal,
-    data_handler::{letter::Letter, make_mut, make_ref, Identity},
+    data_handler::{drafts::Draft, letter::Letter, make_mut, make_ref, Identity},
     ui::{
         dialogs::{open_file::OpenFileDialog, SetData},
         utils::{dismiss, get_text_from_form_entry, linear_layout_form},
@@ -20,6 +20,8 @@ pub struct LetterForm {
     view: Dialog,
     key: Identity,
     letter: Letter,
+    /// Topic and text last reported as a draft.
+    draft: (String, String),
     controller_tx: mpsc::Sender<ControllerSignal>,
 }
 
@@ -29,10 +31,15 @@ impl LetterForm {
         letter: Letter,
         controller_tx: &mpsc::Sender<ControllerSignal>,
     ) -> Self {
+        let draft = {
+            let letter = make_ref(&letter);
+            (letter.get_topic().to_owned(), letter.get_text().to_owned())
+        };
         let mut form = Self {
             view: init_dialog(&letter),
             key,
             letter,
+            draft,
             controller_tx: controller_tx.clone(),
         };
         form.update_attachments();
@@ -71,22 +78,52 @@ impl LetterForm {
         self.get_attachment_view_mut().set_content(info);
     }
 
-    fn save_letter(&mut self) {
+    fn get_topic_and_text(&self) -> (String, String) {
         const TOPIC_INDEX: usize = 0;
         const TEXT_INDEX: usize = 1;
-        let topic = get_text_from_form_entry(&self.view, TOPIC_INDEX);
-        let text = get_text_from_form_entry(&self.view, TEXT_INDEX);
+        (
+            get_text_from_form_entry(&self.view, TOPIC_INDEX).to_owned(),
+            get_text_from_form_entry(&self.view, TEXT_INDEX).to_owned(),
+        )
+    }
+
+    fn save_letter(&mut self) {
+        let (topic, text) = self.get_topic_and_text();
         let mut letter = make_mut(&self.letter);
         letter.set_topic(topic);
         letter.set_text(text);
     }
 
+    /// Journals the form contents whenever they change.
+    fn update_draft(&mut self) {
+        let draft = self.get_topic_and_text();
+        if draft != self.draft {
+            self.controller_tx
+                .send(ControllerSignal::SaveDraft(Draft::new(
+                    self.key.to_string(),
+                    &draft.0,
+                    &draft.1,
+                )))
+                .unwrap();
+            self.draft = draft;
+        }
+    }
+
+    fn discard_draft(&self) {
+        self.controller_tx
+            .send(ControllerSignal::DiscardDraft(self.key.to_string()))
+            .unwrap();
+    }
+
     fn event_submit(&mut self) -> EventResult {
         self.complete_helper();
         dismiss()
     }
 
     fn event_cancel(&mut self) -> EventResult {
+        self.controller_tx
+            .send(ControllerSignal::CancelEditLetter(self.key.to_string()))
+            .unwrap();
         dismiss()
     }
 
@@ -111,8 +148,27 @@ impl LetterForm {
         dismiss()
     }
 
+    /// The letter goes to the trash; a letter never saved just closes.
+    fn event_remove(&mut self) -> EventResult {
+        self.discard_draft();
+        self.controller_tx
+            .send(ControllerSignal::RemoveLetter(self.letter.clone()))
+            .unwrap();
+        dismiss()
+    }
+
+    /// The letter stays open; the template is named and addressed in its own form.
+    fn event_save_as_template(&mut self) -> EventResult {
+        self.save_letter();
+        self.controller_tx
+            .send(ControllerSignal::SaveAsTemplate(self.letter.clone()))
+            .unwrap();
+        EventResult::consumed()
+    }
+
     fn complete_helper(&mut self) {
         self.save_letter();
+        self.discard_draft();
         self.controller_tx
             .send(ControllerSignal::CompleteEditLetter {
                 key: self.key.to_string(),
@@ -128,11 +184,23 @@ impl LetterForm {
             2 => self.event_open_file(),
             3 => self.event_clear_attachment(),
             4 => self.event_send(),
+            5 => self.event_remove(),
+            6 => self.event_save_as_template(),
             _ => EventResult::Ignored,
         }
     }
 }
 
+impl LetterForm {
+    fn edit_event(&mut self, event: Event) -> EventResult {
+        let result = self
+            .with_view_mut(|v| v.on_event(event))
+            .unwrap_or(EventResult::Ignored);
+        self.update_draft();
+        result
+    }
+}
+
 impl SetData for LetterForm {
     fn set_data(&mut self, data: String) {
         self.set_filename(&data)
@@ -162,14 +230,10 @@ impl ViewWrapper for LetterForm {
             }
             Event::Key(Key::Enter) => match self.view.focus() {
                 DialogFocus::Button(n) => self.button_event(n),
-                _ => self
-                    .with_view_mut(|v| v.on_event(event))
-                    .unwrap_or(EventResult::Ignored),
+                _ => self.edit_event(event),
             },
             Event::Key(Key::Esc) => self.event_cancel(),
-            _ => self
-                .with_view_mut(|v| v.on_event(event))
-                .unwrap_or(EventResult::Ignored),
+            _ => self.edit_event(event),
         }
     }
 }
@@ -182,6 +246,8 @@ fn init_dialog(letter: &Letter) -> Dialog {
         .button("Add file", |_| {})
         .button("Remove ALL files", |_| {})
         .button("Send", |_| {})
+        .button("Delete", |_| {})
+        .button("Template", |_| {})
 }
 
 fn init_form(letter: &Letter) -> impl View {
@@ -1,7 +1,14 @@
 pub mod editpersona;
 pub mod letter;
+pub mod profile;
+pub mod report;
+pub mod schedule;
+pub mod search;
+pub mod selectletter;
 pub mod selectpersona;
 pub mod selecttag;
+pub mod selecttemplate;
 pub mod sendletter;
 pub mod settings;
 pub mod tag;
+pub mod template;
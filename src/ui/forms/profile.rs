@@ -0,0 +1,239 @@
+use std::sync::mpsc;
+
+use cursive::{
+    event::{Event, EventResult, Key, MouseButton, MouseEvent},
+    view::{Finder, Nameable, Resizable, Scrollable, ViewWrapper},
+    views::{Checkbox, Dialog, DialogFocus, DummyView, EditView, LinearLayout, TextView},
+    wrap_impl, View,
+};
+
+use crate::{
+    controller::{
+        settings::{SendLimits, SenderProfile, Settings},
+        ControllerSignal,
+    },
+    data_handler::{make_mut, make_ref},
+    ui::utils::{dismiss, get_text_from_form_entry, linear_layout_form},
+};
+
+use super::settings::SettingsForm;
+
+pub struct ProfileForm {
+    view: Dialog,
+    key: String,
+    settings: Settings,
+    controller_tx: mpsc::Sender<ControllerSignal>,
+}
+
+impl ProfileForm {
+    const NAME_I: usize = 0;
+    const LETTER_FROM_I: usize = 1;
+    const REPLY_TO_I: usize = 2;
+    const SMTP_RELAY_I: usize = 3;
+    const SMTP_USER_I: usize = 4;
+    const SMTP_PASSWORD_I: usize = 5;
+    const PLURAL_TITLE_I: usize = 6;
+    const SIGNATURE_I: usize = 7;
+    const PER_MINUTE_I: usize = 8;
+    const PER_MESSAGE_I: usize = 9;
+    const BATCH_PAUSE_I: usize = 10;
+    const PASSWORD_NAME: &str = "profile_password";
+
+    /// An empty `key` means a new profile.
+    pub fn new(
+        key: String,
+        settings: Settings,
+        controller_tx: &mpsc::Sender<ControllerSignal>,
+    ) -> Self {
+        let profile = make_ref(&settings)
+            .get_profile(&key)
+            .cloned()
+            .unwrap_or_else(|| SenderProfile::new("Новый"));
+        Self {
+            view: init_dialog(&profile),
+            key,
+            settings,
+            controller_tx: controller_tx.clone(),
+        }
+    }
+}
+
+impl ProfileForm {
+    fn button_event(&mut self, n: usize) -> EventResult {
+        match n {
+            0 => self.event_submit(),
+            1 => self.event_cancel(),
+            _ => EventResult::Ignored,
+        }
+    }
+
+    fn event_submit(&mut self) -> EventResult {
+        let Some(profile) = self.get_profile() else {
+            return EventResult::consumed();
+        };
+        if profile.name.is_empty() {
+            self.log("Имя профиля не может быть пустым.".into());
+            return EventResult::consumed();
+        }
+        if profile.name != self.key
+            && make_ref(&self.settings)
+                .get_profile(&profile.name)
+                .is_some()
+        {
+            self.log(format!("Профиль {:?} уже существует.", profile.name));
+            return EventResult::consumed();
+        }
+        make_mut(&self.settings).update_profile(&self.key, profile);
+        self.controller_tx
+            .send(ControllerSignal::SaveSettings)
+            .unwrap();
+        EventResult::with_cb_once(|c| {
+            c.pop_layer();
+            if let Some(mut parent) = c.find_name::<SettingsForm>(SettingsForm::NAME) {
+                parent.update_profiles();
+            }
+        })
+    }
+
+    fn event_cancel(&mut self) -> EventResult {
+        dismiss()
+    }
+
+    /// `None` after logging a limit that is not a number.
+    fn get_profile(&mut self) -> Option<SenderProfile> {
+        let smtp_password = self
+            .view
+            .call_on_name(Self::PASSWORD_NAME, |v: &mut EditView| v.get_content())
+            .unwrap()
+            .to_string();
+        let get = |index| {
+            get_text_from_form_entry(&self.view, index)
+                .trim()
+                .to_owned()
+        };
+        let limits = SendLimits {
+            messages_per_minute: self.get_limit(Self::PER_MINUTE_I)? as usize,
+            recipients_per_message: self.get_limit(Self::PER_MESSAGE_I)? as usize,
+            batch_pause: self.get_limit(Self::BATCH_PAUSE_I)?,
+        };
+        Some(SenderProfile {
+            name: get(Self::NAME_I),
+            letter_from: get(Self::LETTER_FROM_I),
+            reply_to: get(Self::REPLY_TO_I),
+            smtp_relay: get(Self::SMTP_RELAY_I),
+            smtp_user: get(Self::SMTP_USER_I),
+            smtp_password,
+            plural_title: get(Self::PLURAL_TITLE_I),
+            letter_signature: get_text_from_form_entry(&self.view, Self::SIGNATURE_I).to_owned(),
+            limits,
+        })
+    }
+
+    /// An empty field is no limit.
+    fn get_limit(&self, index: usize) -> Option<u64> {
+        let text = get_text_from_form_entry(&self.view, index).trim();
+        if text.is_empty() {
+            return Some(0);
+        }
+        let limit = text.parse().ok();
+        if limit.is_none() {
+            self.log(format!("Ограничение {:?} должно быть целым числом.", text));
+        }
+        limit
+    }
+
+    fn log(&self, info: String) {
+        self.controller_tx
+            .send(ControllerSignal::Log(info))
+            .unwrap();
+    }
+}
+
+impl ViewWrapper for ProfileForm {
+    wrap_impl!(self.view: Dialog);
+
+    fn wrap_on_event(&mut self, event: Event) -> EventResult {
+        match event {
+            Event::Mouse {
+                offset: _,
+                position: _,
+                event: MouseEvent::Press(btn),
+            } => {
+                if btn == MouseButton::Left {
+                    self.with_view_mut(|v| v.on_event(event))
+                        .unwrap_or(EventResult::Ignored);
+                    match self.view.focus() {
+                        DialogFocus::Button(n) => self.button_event(n),
+                        _ => EventResult::Ignored,
+                    }
+                } else {
+                    EventResult::Ignored
+                }
+            }
+            Event::Key(Key::Enter) => match self.view.focus() {
+                DialogFocus::Button(n) => self.button_event(n),
+                _ => self
+                    .with_view_mut(|v| v.on_event(event))
+                    .unwrap_or(EventResult::Ignored),
+            },
+            Event::Key(Key::Esc) => self.event_cancel(),
+            _ => self
+                .with_view_mut(|v| v.on_event(event))
+                .unwrap_or(EventResult::Ignored),
+        }
+    }
+}
+
+fn init_dialog(profile: &SenderProfile) -> Dialog {
+    Dialog::around(init_form(profile))
+        .title(format!("Профиль отправителя {}", profile.name))
+        .button("OK", |_| {})
+        .button("Cancel", |_| {})
+}
+
+fn init_form(profile: &SenderProfile) -> impl View {
+    let limit = |value: u64| match value {
+        0 => String::new(),
+        value => value.to_string(),
+    };
+    let per_minute = limit(profile.limits.messages_per_minute as u64);
+    let per_message = limit(profile.limits.recipients_per_message as u64);
+    let batch_pause = limit(profile.limits.batch_pause);
+    let mut layout = linear_layout_form(vec![
+        ("Профиль:", &profile.name),
+        ("Отправитель:", &profile.letter_from),
+        ("Reply-To:", &profile.reply_to),
+        ("SMTP-сервер:", &profile.smtp_relay),
+        ("SMTP-пользователь:", &profile.smtp_user),
+        ("Обращение:", &profile.plural_title),
+        ("Подпись:", &profile.letter_signature),
+        ("Писем в минуту:", &per_minute),
+        ("Адресатов в письме:", &per_message),
+        ("Пауза между пачками, с:", &batch_pause),
+    ]);
+    layout.insert_child(
+        ProfileForm::SMTP_PASSWORD_I,
+        password_entry(&profile.smtp_password),
+    );
+    layout.scrollable()
+}
+
+fn password_entry(password: &str) -> LinearLayout {
+    LinearLayout::horizontal()
+        .child(TextView::new("SMTP-пароль:"))
+        .child(DummyView)
+        .child(
+            EditView::new()
+                .secret()
+                .content(password)
+                .with_name(ProfileForm::PASSWORD_NAME)
+                .full_width(),
+        )
+        .child(DummyView)
+        .child(Checkbox::new().on_change(|c, reveal| {
+            c.call_on_name(ProfileForm::PASSWORD_NAME, |v: &mut EditView| {
+                v.set_secret(!reveal)
+            });
+        }))
+        .child(TextView::new(" показать"))
+}
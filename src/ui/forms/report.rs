@@ -0,0 +1,112 @@
+use std::sync::mpsc::{self, Sender};
+
+use cursive::{
+    event::{Event, EventResult, Key, MouseButton, MouseEvent},
+    view::{Scrollable, ViewWrapper},
+    views::{Dialog, DialogFocus, TextView},
+    wrap_impl, View,
+};
+
+use crate::{
+    controller::ControllerSignal,
+    data_handler::report::{last_contact, ContactReport, ContactStats},
+    ui::utils::dismiss,
+};
+
+/// The contact report as two tables, personas and tags, with the ones never
+/// reached or with failed deliveries on top.
+pub struct ReportForm {
+    view: Dialog,
+    controller_tx: Sender<ControllerSignal>,
+}
+
+impl ReportForm {
+    pub fn new(report: ContactReport, controller_tx: &mpsc::Sender<ControllerSignal>) -> Self {
+        Self {
+            view: init_view(&report),
+            controller_tx: controller_tx.clone(),
+        }
+    }
+
+    fn button_event(&mut self, index: usize) -> EventResult {
+        match index {
+            0 => dismiss(),
+            1 => {
+                self.controller_tx
+                    .send(ControllerSignal::ExportReport)
+                    .unwrap();
+                EventResult::consumed()
+            }
+            _ => EventResult::consumed(),
+        }
+    }
+}
+
+impl ViewWrapper for ReportForm {
+    wrap_impl!(self.view: Dialog);
+
+    fn wrap_on_event(&mut self, event: Event) -> EventResult {
+        match event {
+            Event::Mouse {
+                offset: _,
+                position: _,
+                event: MouseEvent::Press(btn),
+            } => {
+                if btn == MouseButton::Left {
+                    self.with_view_mut(|v| v.on_event(event))
+                        .unwrap_or(EventResult::Ignored);
+                    match self.view.focus() {
+                        DialogFocus::Button(n) => self.button_event(n),
+                        _ => EventResult::Ignored,
+                    }
+                } else {
+                    EventResult::Ignored
+                }
+            }
+            Event::Key(Key::Enter) => match self.view.focus() {
+                DialogFocus::Button(n) => self.button_event(n),
+                _ => self
+                    .with_view_mut(|v| v.on_event(event))
+                    .unwrap_or(EventResult::Ignored),
+            },
+            Event::Key(Key::Esc) => dismiss(),
+            _ => self
+                .with_view_mut(|v| v.on_event(event))
+                .unwrap_or(EventResult::Ignored),
+        }
+    }
+}
+
+fn init_view(report: &ContactReport) -> Dialog {
+    let mut lines = vec![header("Персона")];
+    lines.extend(report.people.iter().map(row));
+    lines.push(String::new());
+    lines.push(header("Метка"));
+    lines.extend(report.tags.iter().map(row));
+    Dialog::around(TextView::new(lines.join("\n")).scrollable())
+        .title("Статистика рассылок")
+        .button("Close", |_| {})
+        .button("Export CSV", |_| {})
+}
+
+const NAME_WIDTH: usize = 32;
+
+fn header(kind: &str) -> String {
+    format!(
+        "{:<NAME_WIDTH$} {:>6} {:>6} {:>10} {:>6} {:>11}",
+        kind, "Персон", "Писем", "Последнее", "Ошибок", "Не получили"
+    )
+}
+
+fn row(stats: &ContactStats) -> String {
+    let name = stats.name.chars().take(NAME_WIDTH).collect::<String>();
+    format!(
+        "{:<NAME_WIDTH$} {:>6} {:>6} {:>10} {:>6} {:>11}",
+        name,
+        stats.people,
+        stats.letters,
+        last_contact(stats),
+        stats.failed,
+        stats.never_reached
+    )
+}
@@ -0,0 +1,154 @@
+use std::sync::mpsc::{self, Sender};
+
+use cursive::{
+    event::{Event, EventResult, Key, MouseButton, MouseEvent},
+    view::{Resizable, Scrollable, ViewWrapper},
+    views::{
+        Dialog, DialogFocus, DummyView, EditView, LinearLayout, ResizedView, ScrollView,
+        SelectView, TextView,
+    },
+    wrap_impl, View,
+};
+
+use crate::{
+    controller::ControllerSignal,
+    data_handler::{letter::Letter, make_ref},
+    ui::{
+        dialogs::overdue::schedule_line,
+        utils::{dismiss, future_time, get_view_from_dialog, no_selection_info},
+    },
+};
+
+pub struct ScheduleForm {
+    view: Dialog,
+    controller_tx: Sender<ControllerSignal>,
+}
+
+impl ScheduleForm {
+    const LIST_INDEX: usize = 1;
+    const TIME_LINE_INDEX: usize = 2;
+
+    pub fn new(letters: Vec<Letter>, controller_tx: &mpsc::Sender<ControllerSignal>) -> Self {
+        Self {
+            view: init_view(letters),
+            controller_tx: controller_tx.clone(),
+        }
+    }
+
+    fn button_event(&mut self, index: usize) -> EventResult {
+        match index {
+            0 => dismiss(),
+            1 => self.event_reschedule(),
+            2 => self.event_unschedule(),
+            _ => EventResult::consumed(),
+        }
+    }
+
+    fn event_reschedule(&mut self) -> EventResult {
+        let Some(letter) = self.get_selected_letter() else {
+            no_selection_info(&self.controller_tx, "переноса", "рассылку");
+            return EventResult::consumed();
+        };
+        let Some(at) = future_time(&self.get_time(), &self.controller_tx) else {
+            return EventResult::consumed();
+        };
+        self.controller_tx
+            .send(ControllerSignal::Reschedule { letter, at })
+            .unwrap();
+        self.reopen()
+    }
+
+    fn event_unschedule(&mut self) -> EventResult {
+        let Some(letter) = self.get_selected_letter() else {
+            no_selection_info(&self.controller_tx, "отмены", "рассылку");
+            return EventResult::consumed();
+        };
+        self.controller_tx
+            .send(ControllerSignal::Unschedule(letter))
+            .unwrap();
+        self.reopen()
+    }
+
+    /// Closes the form and asks for it again to show the changed schedule.
+    fn reopen(&self) -> EventResult {
+        self.controller_tx
+            .send(ControllerSignal::OpenSchedule)
+            .unwrap();
+        dismiss()
+    }
+
+    fn get_selected_letter(&self) -> Option<Letter> {
+        get_view_from_dialog::<ScrollView<SelectView<Letter>>>(&self.view, Self::LIST_INDEX)
+            .get_inner()
+            .selection()
+            .map(|letter| letter.as_ref().clone())
+    }
+
+    fn get_time(&self) -> String {
+        get_view_from_dialog::<LinearLayout>(&self.view, Self::TIME_LINE_INDEX)
+            .get_child(2)
+            .unwrap()
+            .downcast_ref::<ResizedView<EditView>>()
+            .unwrap()
+            .get_inner()
+            .get_content()
+            .to_string()
+    }
+}
+
+impl ViewWrapper for ScheduleForm {
+    wrap_impl!(self.view: Dialog);
+
+    fn wrap_on_event(&mut self, event: Event) -> EventResult {
+        match event {
+            Event::Mouse {
+                offset: _,
+                position: _,
+                event: MouseEvent::Press(btn),
+            } => {
+                if btn == MouseButton::Left {
+                    self.with_view_mut(|v| v.on_event(event))
+                        .unwrap_or(EventResult::Ignored);
+                    match self.view.focus() {
+                        DialogFocus::Button(n) => self.button_event(n),
+                        _ => EventResult::Ignored,
+                    }
+                } else {
+                    EventResult::Ignored
+                }
+            }
+            Event::Key(Key::Enter) => match self.view.focus() {
+                DialogFocus::Button(n) => self.button_event(n),
+                _ => self
+                    .with_view_mut(|v| v.on_event(event))
+                    .unwrap_or(EventResult::Ignored),
+            },
+            Event::Key(Key::Esc) => dismiss(),
+            _ => self
+                .with_view_mut(|v| v.on_event(event))
+                .unwrap_or(EventResult::Ignored),
+        }
+    }
+}
+
+fn init_view(letters: Vec<Letter>) -> Dialog {
+    let mut select = SelectView::<Letter>::new();
+    for letter in letters {
+        let label = schedule_line(&make_ref(&letter));
+        select.add_item(label, letter);
+    }
+    let time_line = LinearLayout::horizontal()
+        .child(TextView::new("Новое время:"))
+        .child(DummyView)
+        .child(EditView::new().fixed_width(18));
+    Dialog::around(
+        LinearLayout::vertical()
+            .child(TextView::new("Запланированные рассылки:"))
+            .child(select.scrollable())
+            .child(time_line),
+    )
+    .title("Расписание")
+    .button("Close", |_| {})
+    .button("Reschedule", |_| {})
+    .button("Unschedule", |_| {})
+}
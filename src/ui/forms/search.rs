@@ -0,0 +1,162 @@
+use std::sync::mpsc::{self, Sender};
+
+use cursive::{
+    event::{Event, EventResult, Key, MouseButton, MouseEvent},
+    view::{Finder, Nameable, Resizable, Scrollable, ViewWrapper},
+    views::{Dialog, DialogFocus, DummyView, EditView, LinearLayout, SelectView, TextView},
+    wrap_impl, View,
+};
+
+use crate::{
+    controller::ControllerSignal,
+    data_handler::{letter::Letter, make_ref, search::SearchIndex},
+    ui::{
+        forms::selectletter::letter_line,
+        utils::{dismiss, no_selection_info},
+    },
+};
+
+/// Finds letters as the query is typed; Open or Enter on a result edits it.
+pub struct SearchForm {
+    view: Dialog,
+    index: SearchIndex,
+    /// The query the results were found for.
+    query: String,
+    controller_tx: Sender<ControllerSignal>,
+}
+
+impl SearchForm {
+    const QUERY: &'static str = "search-query";
+    const RESULTS: &'static str = "search-results";
+    const INFO: &'static str = "search-info";
+
+    pub fn new(index: SearchIndex, controller_tx: &mpsc::Sender<ControllerSignal>) -> Self {
+        Self {
+            view: init_view(&index, controller_tx),
+            index,
+            query: String::new(),
+            controller_tx: controller_tx.clone(),
+        }
+    }
+
+    fn button_event(&mut self, index: usize) -> EventResult {
+        match index {
+            0 => dismiss(),
+            1 => self.event_open(),
+            _ => EventResult::consumed(),
+        }
+    }
+
+    fn event_open(&mut self) -> EventResult {
+        let selected = self
+            .view
+            .call_on_name(Self::RESULTS, |v: &mut SelectView<Letter>| {
+                v.selection().map(|letter| letter.as_ref().clone())
+            })
+            .flatten();
+        let Some(letter) = selected else {
+            no_selection_info(&self.controller_tx, "открытия", "письмо");
+            return EventResult::consumed();
+        };
+        self.controller_tx
+            .send(ControllerSignal::EditLetter(letter))
+            .unwrap();
+        dismiss()
+    }
+
+    fn update_results(&mut self) {
+        let query = self
+            .view
+            .call_on_name(Self::QUERY, |v: &mut EditView| v.get_content())
+            .unwrap()
+            .to_string();
+        if query == self.query {
+            return;
+        }
+        let found = self.index.search(&query);
+        let info = if query.trim().is_empty() {
+            search_hint(&self.index)
+        } else {
+            format!("Найдено писем: {}", found.len())
+        };
+        self.view
+            .call_on_name(Self::INFO, |v: &mut TextView| v.set_content(info));
+        self.view
+            .call_on_name(Self::RESULTS, |v: &mut SelectView<Letter>| {
+                v.clear();
+                for letter in found {
+                    let label = letter_line(&make_ref(&letter));
+                    v.add_item(label, letter);
+                }
+            });
+        self.query = query;
+    }
+}
+
+impl ViewWrapper for SearchForm {
+    wrap_impl!(self.view: Dialog);
+
+    fn wrap_on_event(&mut self, event: Event) -> EventResult {
+        match event {
+            Event::Mouse {
+                offset: _,
+                position: _,
+                event: MouseEvent::Press(btn),
+            } => {
+                if btn == MouseButton::Left {
+                    self.with_view_mut(|v| v.on_event(event))
+                        .unwrap_or(EventResult::Ignored);
+                    match self.view.focus() {
+                        DialogFocus::Button(n) => self.button_event(n),
+                        _ => EventResult::Ignored,
+                    }
+                } else {
+                    EventResult::Ignored
+                }
+            }
+            Event::Key(Key::Enter) => match self.view.focus() {
+                DialogFocus::Button(n) => self.button_event(n),
+                _ => self
+                    .with_view_mut(|v| v.on_event(event))
+                    .unwrap_or(EventResult::Ignored),
+            },
+            Event::Key(Key::Esc) => dismiss(),
+            _ => {
+                let result = self
+                    .with_view_mut(|v| v.on_event(event))
+                    .unwrap_or(EventResult::Ignored);
+                self.update_results();
+                result
+            }
+        }
+    }
+}
+
+fn init_view(index: &SearchIndex, controller_tx: &mpsc::Sender<ControllerSignal>) -> Dialog {
+    let query_line = LinearLayout::horizontal()
+        .child(TextView::new("Найти:"))
+        .child(DummyView)
+        .child(EditView::new().with_name(SearchForm::QUERY).full_width());
+    let tx = controller_tx.clone();
+    let results = SelectView::<Letter>::new().on_submit(move |c, letter: &Letter| {
+        tx.send(ControllerSignal::EditLetter(letter.clone()))
+            .unwrap();
+        c.pop_layer();
+    });
+    Dialog::around(
+        LinearLayout::vertical()
+            .child(query_line)
+            .child(TextView::new(search_hint(index)).with_name(SearchForm::INFO))
+            .child(results.with_name(SearchForm::RESULTS).scrollable()),
+    )
+    .title("Поиск писем")
+    .button("Close", |_| {})
+    .button("Open", |_| {})
+}
+
+fn search_hint(index: &SearchIndex) -> String {
+    format!(
+        "Слова из темы, текста, имён вложений или адресатов; писем: {}",
+        index.len()
+    )
+}
@@ -0,0 +1,234 @@
+use std::sync::mpsc::{self, Sender};
+
+use cursive::{
+    event::{Event, EventResult, Key, MouseButton, MouseEvent},
+    view::{Resizable, Scrollable, ViewWrapper},
+    views::{
+        Dialog, DialogFocus, DummyView, EditView, LinearLayout, ResizedView, ScrollView,
+        SelectView, TextView,
+    },
+    wrap_impl, View,
+};
+
+use crate::{
+    controller::ControllerSignal,
+    data_handler::{
+        letter::{parse_date, Letter, LetterFilter, LetterRepr, LetterState, DATE_FORMAT},
+        make_ref,
+    },
+    ui::utils::{dismiss, get_view_from_dialog, no_selection_info, TIME_FORMAT},
+};
+
+/// The letters with their states; Filter reopens the list with the state
+/// and the dates chosen under it.
+pub struct SelectLetterForm {
+    view: Dialog,
+    controller_tx: Sender<ControllerSignal>,
+}
+
+impl SelectLetterForm {
+    const LIST_INDEX: usize = 1;
+    const FILTER_LINE_INDEX: usize = 2;
+    const STATE_INDEX: usize = 2;
+    const SINCE_INDEX: usize = 6;
+    const UNTIL_INDEX: usize = 10;
+
+    pub fn new(
+        letters: Vec<Letter>,
+        filter: LetterFilter,
+        controller_tx: &mpsc::Sender<ControllerSignal>,
+    ) -> Self {
+        Self {
+            view: init_view(letters, filter),
+            controller_tx: controller_tx.clone(),
+        }
+    }
+
+    fn button_event(&mut self, index: usize) -> EventResult {
+        match index {
+            0 => dismiss(),
+            1 => self.event_with_selected("редактирования", ControllerSignal::EditLetter),
+            2 => self.event_with_selected("отправки", ControllerSignal::OpenLetterToSend),
+            3 => self.event_with_selected("удаления", ControllerSignal::RemoveLetter),
+            4 => self.event_filter(),
+            _ => EventResult::consumed(),
+        }
+    }
+
+    fn event_with_selected(
+        &mut self,
+        action: &str,
+        signal: fn(Letter) -> ControllerSignal,
+    ) -> EventResult {
+        if let Some(selected) = self.get_selected_letter() {
+            self.controller_tx.send(signal(selected)).unwrap();
+            dismiss()
+        } else {
+            no_selection_info(&self.controller_tx, action, "письмо");
+            EventResult::consumed()
+        }
+    }
+
+    fn event_filter(&mut self) -> EventResult {
+        let (Some(since), Some(until)) = (
+            self.get_date(Self::SINCE_INDEX),
+            self.get_date(Self::UNTIL_INDEX),
+        ) else {
+            return EventResult::consumed();
+        };
+        let filter = LetterFilter {
+            state: self.get_state(),
+            since,
+            until,
+        };
+        self.controller_tx
+            .send(ControllerSignal::SelectLetter(filter))
+            .unwrap();
+        dismiss()
+    }
+
+    fn get_selected_letter(&self) -> Option<Letter> {
+        get_view_from_dialog::<ScrollView<SelectView<Letter>>>(&self.view, Self::LIST_INDEX)
+            .get_inner()
+            .selection()
+            .map(|letter| letter.as_ref().clone())
+    }
+
+    fn get_filter_line(&self) -> &LinearLayout {
+        get_view_from_dialog::<LinearLayout>(&self.view, Self::FILTER_LINE_INDEX)
+    }
+
+    fn get_state(&self) -> Option<LetterState> {
+        self.get_filter_line()
+            .get_child(Self::STATE_INDEX)
+            .unwrap()
+            .downcast_ref::<SelectView<Option<LetterState>>>()
+            .unwrap()
+            .selection()
+            .and_then(|state| *state)
+    }
+
+    /// `Some(None)` for an empty field; logs a date it cannot read and
+    /// returns `None`.
+    fn get_date(&self, index: usize) -> Option<Option<chrono::NaiveDate>> {
+        let text = self
+            .get_filter_line()
+            .get_child(index)
+            .unwrap()
+            .downcast_ref::<ResizedView<EditView>>()
+            .unwrap()
+            .get_inner()
+            .get_content();
+        if text.trim().is_empty() {
+            return Some(None);
+        }
+        let date = parse_date(&text);
+        if date.is_none() {
+            self.controller_tx
+                .send(ControllerSignal::Log(format!(
+                    "Дата {:?} не понята: пишите ДД.ММ.ГГГГ.",
+                    text.trim()
+                )))
+                .unwrap();
+            return None;
+        }
+        Some(date)
+    }
+}
+
+impl ViewWrapper for SelectLetterForm {
+    wrap_impl!(self.view: Dialog);
+
+    fn wrap_on_event(&mut self, event: Event) -> EventResult {
+        match event {
+            Event::Mouse {
+                offset: _,
+                position: _,
+                event: MouseEvent::Press(btn),
+            } => {
+                if btn == MouseButton::Left {
+                    self.with_view_mut(|v| v.on_event(event))
+                        .unwrap_or(EventResult::Ignored);
+                    match self.view.focus() {
+                        DialogFocus::Button(n) => self.button_event(n),
+                        _ => EventResult::Ignored,
+                    }
+                } else {
+                    EventResult::Ignored
+                }
+            }
+            Event::Key(Key::Enter) => match self.view.focus() {
+                DialogFocus::Button(n) => self.button_event(n),
+                _ => self
+                    .with_view_mut(|v| v.on_event(event))
+                    .unwrap_or(EventResult::Ignored),
+            },
+            Event::Key(Key::Esc) => dismiss(),
+            _ => self
+                .with_view_mut(|v| v.on_event(event))
+                .unwrap_or(EventResult::Ignored),
+        }
+    }
+}
+
+fn init_view(letters: Vec<Letter>, filter: LetterFilter) -> Dialog {
+    let mut select = SelectView::<Letter>::new();
+    for letter in letters {
+        let label = letter_line(&make_ref(&letter));
+        select.add_item(label, letter);
+    }
+    Dialog::around(
+        LinearLayout::vertical()
+            .child(TextView::new("Письма:"))
+            .child(select.scrollable())
+            .child(init_filter_line(&filter)),
+    )
+    .title("Письма")
+    .button("Close", |_| {})
+    .button("Edit", |_| {})
+    .button("Send", |_| {})
+    .button("Remove", |_| {})
+    .button("Filter", |_| {})
+}
+
+fn init_filter_line(filter: &LetterFilter) -> impl View {
+    let mut state = SelectView::<Option<LetterState>>::new().popup();
+    state.add_item("(все)", None);
+    for (index, item) in LetterState::ALL.into_iter().enumerate() {
+        state.add_item(item.to_string(), Some(item));
+        if filter.state == Some(item) {
+            state.set_selection(index + 1);
+        }
+    }
+    let date = |date: Option<chrono::NaiveDate>| {
+        let text = date
+            .map(|date| date.format(DATE_FORMAT).to_string())
+            .unwrap_or_default();
+        EditView::new().content(text).fixed_width(12)
+    };
+    LinearLayout::horizontal()
+        .child(TextView::new("Состояние:"))
+        .child(DummyView)
+        .child(state)
+        .child(DummyView)
+        .child(TextView::new("с"))
+        .child(DummyView)
+        .child(date(filter.since))
+        .child(DummyView)
+        .child(TextView::new("по"))
+        .child(DummyView)
+        .child(date(filter.until))
+}
+
+pub fn letter_line(letter: &LetterRepr) -> String {
+    let topic = match letter.get_topic() {
+        "" => "(без темы)",
+        topic => topic,
+    };
+    format!(
+        "{}  [{}]  {}",
+        letter.get_time().format(TIME_FORMAT),
+        letter.state(),
+        topic
+    )
+}
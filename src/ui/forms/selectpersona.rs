@@ -2,35 +2,56 @@ use std::sync::mpsc;
 
 use cursive::{
     event::{Event, EventResult, Key, MouseButton, MouseEvent},
-    view::ViewWrapper,
-    views::{Dialog, DialogFocus, LinearLayout, SelectView, TextView},
+    view::{Scrollable, ViewWrapper},
+    views::{
+        Checkbox, Dialog, DialogFocus, DummyView, LinearLayout, ListChild, ListView, ScrollView,
+        SelectView, TextView,
+    },
     wrap_impl, View,
 };
 
 use crate::{
     controller::ControllerSignal,
-    data_handler::{make_ref, persona::Persona, Represent},
+    data_handler::{make_ref, persona::Persona, tag::Tag, Identity, Represent},
     ui::utils::{dismiss, get_view_from_dialog, no_selection_info},
 };
 
+/// The persona list: Edit acts on the persona under the cursor, the other
+/// actions on every checked one.
 pub struct SelectPersonaForm {
     view: Dialog,
+    /// Personas in the order of the rows.
+    people: Vec<Persona>,
     controller_tx: mpsc::Sender<ControllerSignal>,
 }
 
 impl SelectPersonaForm {
-    pub fn new(persona: Vec<Persona>, ui_tx: &mpsc::Sender<ControllerSignal>) -> Self {
+    const LIST_INDEX: usize = 1;
+    const TAG_LINE_INDEX: usize = 2;
+
+    pub fn new(
+        persona: Vec<Persona>,
+        tags: Vec<Tag>,
+        ui_tx: &mpsc::Sender<ControllerSignal>,
+    ) -> Self {
         Self {
-            view: init_dialog(persona),
+            view: init_dialog(&persona, tags),
+            people: persona,
             controller_tx: ui_tx.clone(),
         }
     }
 
-    fn button_event(&self, n: usize) -> EventResult {
+    fn button_event(&mut self, n: usize) -> EventResult {
         match n {
             0 => dismiss(),
             1 => self.event_edit(),
             2 => self.event_remove(),
+            3 => self.event_check(|_| true),
+            4 => self.event_check(|checked| !checked),
+            5 => self.event_tag(true),
+            6 => self.event_tag(false),
+            7 => self.event_export(),
+            8 => self.event_write_letter(),
             _ => EventResult::Ignored,
         }
     }
@@ -48,15 +69,71 @@ impl SelectPersonaForm {
     }
 
     fn event_remove(&self) -> EventResult {
-        if let Some(selected_persona) = self.get_selected_persona() {
-            self.controller_tx
-                .send(ControllerSignal::RemovePersonaAlert(selected_persona))
-                .unwrap();
-            dismiss()
+        let checked = self.get_checked_persona();
+        if checked.is_empty() {
+            no_selection_info(&self.controller_tx, "удаления", "персон");
+            return EventResult::consumed();
+        }
+        self.controller_tx
+            .send(ControllerSignal::RemovePersonaAlert(checked))
+            .unwrap();
+        dismiss()
+    }
+
+    /// Sets every checkbox to `check(was_checked)`.
+    fn event_check(&mut self, check: impl Fn(bool) -> bool) -> EventResult {
+        let list = self.get_list_view_mut();
+        for row in 0..list.len() {
+            if let ListChild::Row(_, view) = list.row_mut(row) {
+                if let Some(checkbox) = view.downcast_mut::<Checkbox>() {
+                    checkbox.set_checked(check(checkbox.is_checked()));
+                }
+            }
+        }
+        EventResult::consumed()
+    }
+
+    fn event_tag(&self, add: bool) -> EventResult {
+        let people = self.get_checked_identities();
+        let label = self
+            .get_tag_view()
+            .selection()
+            .map(|label| (*label).clone());
+        let (Some(label), false) = (label, people.is_empty()) else {
+            no_selection_info(&self.controller_tx, "этого", "персон и метку");
+            return EventResult::consumed();
+        };
+        let signal = if add {
+            ControllerSignal::AddToTag { label, people }
         } else {
-            no_selection_info(&self.controller_tx, "удаления", "персону");
-            EventResult::consumed()
+            ControllerSignal::RemoveFromTag { label, people }
+        };
+        self.controller_tx.send(signal).unwrap();
+        EventResult::consumed()
+    }
+
+    fn event_export(&self) -> EventResult {
+        let checked = self.get_checked_persona();
+        if checked.is_empty() {
+            no_selection_info(&self.controller_tx, "выгрузки", "персон");
+            return EventResult::consumed();
         }
+        self.controller_tx
+            .send(ControllerSignal::ExportPersona(checked))
+            .unwrap();
+        EventResult::consumed()
+    }
+
+    fn event_write_letter(&self) -> EventResult {
+        let people = self.get_checked_identities();
+        if people.is_empty() {
+            no_selection_info(&self.controller_tx, "письма", "адресатов");
+            return EventResult::consumed();
+        }
+        self.controller_tx
+            .send(ControllerSignal::WriteLetterTo(people))
+            .unwrap();
+        dismiss()
     }
 
     fn event_close(&self) -> EventResult {
@@ -64,14 +141,59 @@ impl SelectPersonaForm {
     }
 
     fn get_selected_persona(&self) -> Option<Persona> {
-        self.get_select_view()
-            .selection()
-            .map(|p| p.as_ref().clone())
+        let list = self.get_list_view();
+        if list.is_empty() {
+            return None;
+        }
+        self.people.get(list.focus()).cloned()
+    }
+
+    fn get_checked_persona(&self) -> Vec<Persona> {
+        let checks = self
+            .get_list_view()
+            .children()
+            .iter()
+            .filter_map(|child| match child {
+                ListChild::Row(_, view) => view.downcast_ref::<Checkbox>(),
+                ListChild::Delimiter => None,
+            });
+        self.people
+            .iter()
+            .zip(checks)
+            .filter(|(_, check)| check.is_checked())
+            .map(|(persona, _)| persona.clone())
+            .collect()
+    }
+
+    fn get_checked_identities(&self) -> Vec<Identity> {
+        self.get_checked_persona()
+            .iter()
+            .map(|persona| make_ref(persona).identity())
+            .collect()
     }
 
-    fn get_select_view(&self) -> &SelectView<Persona> {
-        const SELECT_VIEW_INDEX: usize = 1;
-        get_view_from_dialog(&self.view, SELECT_VIEW_INDEX)
+    fn get_list_view(&self) -> &ListView {
+        get_view_from_dialog::<ScrollView<ListView>>(&self.view, Self::LIST_INDEX).get_inner()
+    }
+
+    fn get_list_view_mut(&mut self) -> &mut ListView {
+        self.view
+            .get_content_mut()
+            .downcast_mut::<LinearLayout>()
+            .unwrap()
+            .get_child_mut(Self::LIST_INDEX)
+            .unwrap()
+            .downcast_mut::<ScrollView<ListView>>()
+            .unwrap()
+            .get_inner_mut()
+    }
+
+    fn get_tag_view(&self) -> &SelectView<Identity> {
+        get_view_from_dialog::<LinearLayout>(&self.view, Self::TAG_LINE_INDEX)
+            .get_child(2)
+            .unwrap()
+            .downcast_ref::<SelectView<Identity>>()
+            .unwrap()
     }
 }
 
@@ -110,21 +232,39 @@ impl ViewWrapper for SelectPersonaForm {
     }
 }
 
-fn init_dialog(persona: Vec<Persona>) -> Dialog {
-    Dialog::around(init_view(persona))
+fn init_dialog(persona: &[Persona], tags: Vec<Tag>) -> Dialog {
+    Dialog::around(init_view(persona, tags))
         .button("Close", |_| {})
         .button("Edit", |_| {})
         .button("Remove", |_| {})
+        .button("All", |_| {})
+        .button("Invert", |_| {})
+        .button("Tag+", |_| {})
+        .button("Tag-", |_| {})
+        .button("Export", |_| {})
+        .button("Letter", |_| {})
 }
 
-fn init_view(persona_list: Vec<Persona>) -> impl View {
-    let mut select = SelectView::new().popup();
-    select.set_autojump(true);
+fn init_view(persona_list: &[Persona], tags: Vec<Tag>) -> impl View {
+    let mut list = ListView::new();
     for persona in persona_list {
-        let label = make_ref(&persona).identity();
-        select.add_item(label, persona);
+        let label = make_ref(persona).identity();
+        list.add_child(&label, Checkbox::new());
     }
     LinearLayout::vertical()
-        .child(TextView::new("Выберите персону:"))
+        .child(TextView::new("Отметьте персон:"))
+        .child(list.scrollable())
+        .child(init_tag_line(tags))
+}
+
+fn init_tag_line(tags: Vec<Tag>) -> impl View {
+    let mut select = SelectView::new().popup();
+    for tag in tags {
+        let label = make_ref(&tag).identity();
+        select.add_item(label.to_owned(), label);
+    }
+    LinearLayout::horizontal()
+        .child(TextView::new("Метка для Tag+/Tag-:"))
+        .child(DummyView)
         .child(select)
 }
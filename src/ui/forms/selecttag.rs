@@ -2,8 +2,8 @@ use std::sync::mpsc::{self, Sender};
 
 use cursive::{
     event::{Event, EventResult, Key, MouseButton, MouseEvent},
-    view::ViewWrapper,
-    views::{Dialog, DialogFocus, LinearLayout, SelectView, TextView},
+    view::{Scrollable, ViewWrapper},
+    views::{Dialog, DialogFocus, LinearLayout, ScrollView, SelectView, TextView},
     wrap_impl, View,
 };
 
@@ -19,9 +19,12 @@ pub struct SelectTagForm {
 }
 
 impl SelectTagForm {
-    pub fn new(tags: Vec<Tag>, controller_tx: &mpsc::Sender<ControllerSignal>) -> Self {
+    pub fn new(
+        tree: Vec<(usize, Tag, usize)>,
+        controller_tx: &mpsc::Sender<ControllerSignal>,
+    ) -> Self {
         Self {
-            view: init_view(tags),
+            view: init_view(tree),
             controller_tx: controller_tx.clone(),
         }
     }
@@ -75,7 +78,8 @@ impl SelectTagForm {
 
     fn get_select_view(&self) -> &SelectView<Tag> {
         const SELECT_VIEW_INDEX: usize = 1;
-        get_view_from_dialog(&self.view, SELECT_VIEW_INDEX)
+        get_view_from_dialog::<ScrollView<SelectView<Tag>>>(&self.view, SELECT_VIEW_INDEX)
+            .get_inner()
     }
 }
 
@@ -114,20 +118,30 @@ impl ViewWrapper for SelectTagForm {
     }
 }
 
-fn init_view(tags: Vec<Tag>) -> Dialog {
-    Dialog::around(init_dialog(tags))
+fn init_view(tree: Vec<(usize, Tag, usize)>) -> Dialog {
+    Dialog::around(init_dialog(tree))
         .button("Close", |_| {})
         .button("Edit", |_| {})
         .button("Remove", |_| {})
 }
 
-fn init_dialog(tags: Vec<Tag>) -> impl View {
-    let mut select = SelectView::<Tag>::new().popup();
-    for tag in tags {
-        let label = make_ref(&tag).label();
+/// Nested tags are indented under their parent; smart tags are marked with `*`.
+fn init_dialog(tree: Vec<(usize, Tag, usize)>) -> impl View {
+    let mut select = SelectView::<Tag>::new();
+    for (depth, tag, members) in tree {
+        let label = {
+            let tag = make_ref(&tag);
+            format!(
+                "{}{}{} ({})",
+                "  ".repeat(depth),
+                tag.label(),
+                if tag.rule().is_some() { "*" } else { "" },
+                members
+            )
+        };
         select.add_item(label, tag);
     }
     LinearLayout::vertical()
         .child(TextView::new("Выберите метку:"))
-        .child(select)
+        .child(select.scrollable())
 }
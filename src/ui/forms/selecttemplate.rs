@@ -0,0 +1,133 @@
+use std::sync::mpsc::{self, Sender};
+
+use cursive::{
+    event::{Event, EventResult, Key, MouseButton, MouseEvent},
+    view::{Scrollable, ViewWrapper},
+    views::{Dialog, DialogFocus, LinearLayout, ScrollView, SelectView, TextView},
+    wrap_impl, View,
+};
+
+use crate::{
+    controller::ControllerSignal,
+    data_handler::{make_ref, template::Template},
+    ui::utils::{dismiss, get_view_from_dialog, no_selection_info},
+};
+
+pub struct SelectTemplateForm {
+    view: Dialog,
+    controller_tx: Sender<ControllerSignal>,
+}
+
+impl SelectTemplateForm {
+    pub fn new(templates: Vec<Template>, controller_tx: &mpsc::Sender<ControllerSignal>) -> Self {
+        Self {
+            view: init_view(templates),
+            controller_tx: controller_tx.clone(),
+        }
+    }
+}
+
+impl SelectTemplateForm {
+    fn button_event(&mut self, index: usize) -> EventResult {
+        match index {
+            0 => dismiss(),
+            1 => self.event_with_selected("письма", ControllerSignal::NewLetterFromTemplate),
+            2 => self.event_with_selected("редактирования", ControllerSignal::EditTemplate),
+            3 => self.event_with_selected("удаления", ControllerSignal::RemoveTemplate),
+            _ => EventResult::consumed(),
+        }
+    }
+
+    fn event_with_selected(
+        &mut self,
+        action: &str,
+        signal: fn(Template) -> ControllerSignal,
+    ) -> EventResult {
+        if let Some(selected) = self.get_selected_template() {
+            self.controller_tx.send(signal(selected)).unwrap();
+            dismiss()
+        } else {
+            no_selection_info(&self.controller_tx, action, "шаблон");
+            EventResult::consumed()
+        }
+    }
+
+    fn event_cancel(&mut self) -> EventResult {
+        dismiss()
+    }
+}
+
+impl SelectTemplateForm {
+    fn get_selected_template(&self) -> Option<Template> {
+        self.get_select_view()
+            .selection()
+            .map(|t| t.as_ref().clone())
+    }
+
+    fn get_select_view(&self) -> &SelectView<Template> {
+        const SELECT_VIEW_INDEX: usize = 1;
+        get_view_from_dialog::<ScrollView<SelectView<Template>>>(&self.view, SELECT_VIEW_INDEX)
+            .get_inner()
+    }
+}
+
+impl ViewWrapper for SelectTemplateForm {
+    wrap_impl!(self.view: Dialog);
+
+    fn wrap_on_event(&mut self, event: Event) -> EventResult {
+        match event {
+            Event::Mouse {
+                offset: _,
+                position: _,
+                event: MouseEvent::Press(btn),
+            } => {
+                if btn == MouseButton::Left {
+                    self.with_view_mut(|v| v.on_event(event))
+                        .unwrap_or(EventResult::Ignored);
+                    match self.view.focus() {
+                        DialogFocus::Button(n) => self.button_event(n),
+                        _ => EventResult::Ignored,
+                    }
+                } else {
+                    EventResult::Ignored
+                }
+            }
+            Event::Key(Key::Enter) => match self.view.focus() {
+                DialogFocus::Button(n) => self.button_event(n),
+                _ => self
+                    .with_view_mut(|v| v.on_event(event))
+                    .unwrap_or(EventResult::Ignored),
+            },
+            Event::Key(Key::Esc) => self.event_cancel(),
+            _ => self
+                .with_view_mut(|v| v.on_event(event))
+                .unwrap_or(EventResult::Ignored),
+        }
+    }
+}
+
+fn init_view(templates: Vec<Template>) -> Dialog {
+    Dialog::around(init_dialog(templates))
+        .button("Close", |_| {})
+        .button("Write", |_| {})
+        .button("Edit", |_| {})
+        .button("Remove", |_| {})
+}
+
+/// The default recipients tag follows the name.
+fn init_dialog(templates: Vec<Template>) -> impl View {
+    let mut select = SelectView::<Template>::new();
+    for template in templates {
+        let label = {
+            let template = make_ref(&template);
+            match template.tag() {
+                Some(tag) => format!("{} → {}", template.name(), tag),
+                None => template.name().to_owned(),
+            }
+        };
+        select.add_item(label, template);
+    }
+    LinearLayout::vertical()
+        .child(TextView::new("Выберите шаблон:"))
+        .child(select.scrollable())
+}
@@ -2,18 +2,25 @@ use std::sync::mpsc;
 
 use cursive::{
     event::{Event, EventResult, Key, MouseButton, MouseEvent},
-    view::{Scrollable, ViewWrapper},
+    view::{Finder, Nameable, Resizable, Scrollable, ViewWrapper},
     views::{
-        Checkbox, Dialog, DialogFocus, LinearLayout, ListChild, ListView, Panel, ResizedView,
-        ScrollView, TextArea,
+        Dialog, DialogFocus, DummyView, EditView, LinearLayout, ListChild, ListView, Panel,
+        RadioButton, RadioGroup, ResizedView, ScrollView, SelectView, TextArea, TextView,
     },
     wrap_impl, View,
 };
 
 use crate::{
-    controller::ControllerSignal,
-    data_handler::{letter::Letter, make_mut, make_ref, persona::Persona, Represent},
-    ui::utils::dismiss,
+    controller::{mailer::Recipients, ControllerSignal},
+    data_handler::{
+        letter::Letter,
+        make_mut, make_ref,
+        persona::{Persona, PersonaContainer},
+        selection::Selection,
+        tag::{Membership, Tag, TagContainer},
+        Identity, Represent,
+    },
+    ui::utils::{dismiss, future_time},
 };
 
 use super::letter::letter_view;
@@ -21,20 +28,49 @@ use super::letter::letter_view;
 pub struct SendLetterForm {
     view: Dialog,
     people: Vec<Persona>,
+    /// The same personas and all the tags, to resolve the selection.
+    directory: (PersonaContainer, TagContainer),
+    /// The selection as last resolved, `None` while it is empty or wrong.
+    selection: (String, Option<Vec<Identity>>),
     letter: Letter,
     controller_tx: mpsc::Sender<ControllerSignal>,
 }
 
 impl SendLetterForm {
+    const NONE_COLUMN: usize = 0;
+    const TO_COLUMN: usize = 2;
+    const CC_COLUMN: usize = 4;
+    const BCC_COLUMN: usize = 6;
+    const PROFILE: &'static str = "sendletter_profile";
+    const SELECTION: &'static str = "sendletter_selection";
+    const SELECTION_COLUMN: &'static str = "sendletter_selection_column";
+    const SELECTION_INFO: &'static str = "sendletter_selection_info";
+    const SEND_AT: &'static str = "sendletter_send_at";
+    /// How many resolved names are listed under the selection.
+    const LISTED: usize = 20;
+
     pub fn new(
         letter: Letter,
         people: Vec<Persona>,
+        tags: Vec<Tag>,
+        profiles: Vec<String>,
+        default_profile: String,
+        to: Vec<Identity>,
         controller_tx: &mpsc::Sender<ControllerSignal>,
     ) -> Self {
         let controller_tx = controller_tx.clone();
+        let mut directory = (PersonaContainer::new(), TagContainer::new());
+        for persona in people.iter() {
+            directory.0.insert_or_update(persona.clone());
+        }
+        for tag in tags {
+            directory.1.insert_or_update(tag);
+        }
         Self {
-            view: init_dialog(&letter, &people),
+            view: init_dialog(&letter, &people, profiles, &default_profile, &to),
             people,
+            directory,
+            selection: Default::default(),
             letter,
             controller_tx,
         }
@@ -44,18 +80,119 @@ impl SendLetterForm {
         match n {
             0 => self.do_send(),
             1 => self.event_cancel(),
+            2 => self.event_apply_selection(),
             _ => EventResult::Ignored,
         }
     }
 
-    fn do_send(&mut self) -> EventResult {
-        self.update_letter();
-        self.controller_tx
-            .send(ControllerSignal::SendEmail {
-                letter: self.letter.clone(),
-                to: self.get_chosen_addresses(),
+    /// Resolves the selection whenever it changes and shows the result.
+    fn update_selection(&mut self) {
+        let text = self
+            .view
+            .call_on_name(Self::SELECTION, |v: &mut EditView| v.get_content())
+            .unwrap()
+            .to_string();
+        if text == self.selection.0 {
+            return;
+        }
+        let (people, tags) = &self.directory;
+        if text.trim().is_empty() {
+            self.view
+                .call_on_name(Self::SELECTION_INFO, |v: &mut TextView| {
+                    v.set_content(selection_hint())
+                });
+            self.selection = (text, None);
+            return;
+        }
+        let resolved = Selection::parse(&text)
+            .and_then(|selection| selection.resolve(&mut Membership::new(tags, people)));
+        let info = match resolved {
+            Ok(ref chosen) => describe_selection(chosen, Self::LISTED),
+            Err(ref e) => e.to_string(),
+        };
+        self.view
+            .call_on_name(Self::SELECTION_INFO, |v: &mut TextView| v.set_content(info));
+        self.selection = (text, resolved.ok());
+    }
+
+    /// Makes the chosen column hold exactly the resolved personas.
+    fn event_apply_selection(&mut self) -> EventResult {
+        let column = self
+            .view
+            .call_on_name(Self::SELECTION_COLUMN, |v: &mut SelectView<usize>| {
+                v.selection().map(|column| *column)
             })
+            .flatten()
+            .unwrap_or(Self::TO_COLUMN);
+        let Some(chosen) = self.selection.1.clone() else {
+            return EventResult::consumed();
+        };
+        let people = self
+            .people
+            .iter()
+            .map(|persona| make_ref(persona).identity())
+            .collect::<Vec<_>>();
+        let list_view = self.get_list_view_mut();
+        let mut people = people.iter();
+        for row in 0..list_view.len() {
+            let Some(columns) = (match list_view.row_mut(row) {
+                ListChild::Row(_, columns) => columns.downcast_mut::<LinearLayout>(),
+                _ => None,
+            }) else {
+                continue;
+            };
+            let Some(identity) = people.next() else {
+                break;
+            };
+            let is_chosen = chosen.contains(identity);
+            let target = if is_chosen {
+                column
+            } else if Self::get_checked_column(columns) == Some(column) {
+                Self::NONE_COLUMN
+            } else {
+                continue;
+            };
+            if let Some(button) = columns
+                .get_child_mut(target)
+                .and_then(|button| button.downcast_mut::<RadioButton<usize>>())
+            {
+                button.select();
+            }
+        }
+        EventResult::consumed()
+    }
+
+    /// Sends now, or schedules the mailing if a time is given.
+    fn do_send(&mut self) -> EventResult {
+        let send_at = self
+            .view
+            .call_on_name(Self::SEND_AT, |v: &mut EditView| v.get_content())
             .unwrap();
+        let at = match send_at.trim() {
+            "" => None,
+            text => match future_time(text, &self.controller_tx) {
+                Some(at) => Some(at),
+                None => return EventResult::consumed(),
+            },
+        };
+        self.update_letter();
+        let letter = self.letter.clone();
+        let recipients = self.get_chosen_recipients();
+        let profile = self.get_chosen_profile();
+        let signal = match at {
+            None => ControllerSignal::SendEmail {
+                letter,
+                recipients,
+                profile,
+            },
+            Some(at) => ControllerSignal::ScheduleEmail {
+                letter,
+                recipients,
+                profile,
+                at,
+            },
+        };
+        self.controller_tx.send(signal).unwrap();
         dismiss()
     }
 
@@ -71,24 +208,49 @@ impl SendLetterForm {
         letter.set_text(text);
     }
 
-    fn get_chosen_addresses(&self) -> Vec<String> {
-        let mut chosen = vec![];
-        let list_view = self.get_list_view();
-        for (index, list_entry) in list_view.children().iter().enumerate() {
-            if let ListChild::Row(_, check) = list_entry {
-                if let Some(check) = check.downcast_ref::<Checkbox>() {
-                    if check.is_checked() {
-                        let email_string = make_ref(self.people.get(index).unwrap())
-                            .get_email()
-                            .to_string();
-                        chosen.push(email_string);
-                    }
-                }
+    fn get_chosen_recipients(&self) -> Recipients {
+        let mut chosen = Recipients::default();
+        let rows =
+            self.get_list_view()
+                .children()
+                .iter()
+                .filter_map(|list_entry| match list_entry {
+                    ListChild::Row(_, columns) => columns.downcast_ref::<LinearLayout>(),
+                    _ => None,
+                });
+        for (persona, columns) in self.people.iter().zip(rows) {
+            let email_string = make_ref(persona).get_email().to_string();
+            match Self::get_checked_column(columns) {
+                Some(Self::TO_COLUMN) => chosen.to.push(email_string),
+                Some(Self::CC_COLUMN) => chosen.cc.push(email_string),
+                Some(Self::BCC_COLUMN) => chosen.bcc.push(email_string),
+                _ => {}
             }
         }
         chosen
     }
 
+    fn get_checked_column(columns: &LinearLayout) -> Option<usize> {
+        [Self::TO_COLUMN, Self::CC_COLUMN, Self::BCC_COLUMN]
+            .into_iter()
+            .find(|&column| {
+                columns
+                    .get_child(column)
+                    .and_then(|button| button.downcast_ref::<RadioButton<usize>>())
+                    .map(|button| button.is_selected())
+                    .unwrap_or(false)
+            })
+    }
+
+    fn get_chosen_profile(&mut self) -> String {
+        self.view
+            .call_on_name(Self::PROFILE, |v: &mut SelectView| {
+                v.selection().map(|profile| profile.as_ref().to_owned())
+            })
+            .flatten()
+            .unwrap_or_default()
+    }
+
     fn get_list_view(&self) -> &ListView {
         self.get_panel(0)
             .downcast_ref::<Panel<ScrollView<ListView>>>()
@@ -97,6 +259,23 @@ impl SendLetterForm {
             .get_inner()
     }
 
+    fn get_list_view_mut(&mut self) -> &mut ListView {
+        self.view
+            .get_content_mut()
+            .downcast_mut::<LinearLayout>()
+            .unwrap()
+            .get_child_mut(PANELS_INDEX)
+            .unwrap()
+            .downcast_mut::<LinearLayout>()
+            .unwrap()
+            .get_child_mut(0)
+            .unwrap()
+            .downcast_mut::<Panel<ScrollView<ListView>>>()
+            .unwrap()
+            .get_inner_mut()
+            .get_inner_mut()
+    }
+
     fn get_letter_area(&self, n: usize) -> &TextArea {
         self.get_panel(1)
             .downcast_ref::<Panel<ScrollView<LinearLayout>>>()
@@ -119,6 +298,10 @@ impl SendLetterForm {
             .get_content()
             .downcast_ref::<LinearLayout>()
             .unwrap()
+            .get_child(PANELS_INDEX)
+            .unwrap()
+            .downcast_ref::<LinearLayout>()
+            .unwrap()
             .get_child(panel_index)
             .unwrap()
     }
@@ -152,36 +335,138 @@ impl ViewWrapper for SendLetterForm {
                     .unwrap_or(EventResult::Ignored),
             },
             Event::Key(Key::Esc) => self.event_cancel(),
-            _ => self
-                .with_view_mut(|v| v.on_event(event))
-                .unwrap_or(EventResult::Ignored),
+            _ => {
+                let result = self
+                    .with_view_mut(|v| v.on_event(event))
+                    .unwrap_or(EventResult::Ignored);
+                self.update_selection();
+                result
+            }
         }
     }
 }
 
-fn init_dialog(letter: &Letter, people: &[Persona]) -> Dialog {
-    Dialog::around(init_view(letter, people))
+/// The address panels follow the profile and selection lines.
+const PANELS_INDEX: usize = 3;
+
+fn init_dialog(
+    letter: &Letter,
+    people: &[Persona],
+    profiles: Vec<String>,
+    default_profile: &str,
+    to: &[Identity],
+) -> Dialog {
+    Dialog::around(init_view(letter, people, profiles, default_profile, to))
         .title("Отправка письма")
         .button("SEND", |_| {})
         .button("Cancel", |_| {})
+        .button("Apply", |_| {})
 }
 
-fn init_view(letter: &Letter, people: &[Persona]) -> impl View {
+fn init_view(
+    letter: &Letter,
+    people: &[Persona],
+    profiles: Vec<String>,
+    default_profile: &str,
+    to: &[Identity],
+) -> impl View {
+    LinearLayout::vertical()
+        .child(init_profile_line(profiles, default_profile))
+        .child(init_selection_line())
+        .child(TextView::new(selection_hint()).with_name(SendLetterForm::SELECTION_INFO))
+        .child(
+            LinearLayout::horizontal()
+                .child(Panel::new(init_address_panel(people, to)))
+                .child(Panel::new(init_letter_panel(letter))),
+        )
+}
+
+fn init_profile_line(profiles: Vec<String>, default_profile: &str) -> impl View {
+    let mut select = SelectView::new().popup();
+    let default_index = profiles.iter().position(|name| name == default_profile);
+    select.add_all_str(profiles);
+    if let Some(index) = default_index {
+        select.set_selection(index);
+    }
     LinearLayout::horizontal()
-        .child(Panel::new(init_address_panel(people)))
-        .child(Panel::new(init_letter_panel(letter)))
+        .child(TextView::new("Отправитель:"))
+        .child(DummyView)
+        .child(select.with_name(SendLetterForm::PROFILE))
+        .child(DummyView)
+        .child(TextView::new("Отправить в:"))
+        .child(DummyView)
+        .child(
+            EditView::new()
+                .with_name(SendLetterForm::SEND_AT)
+                .fixed_width(18),
+        )
+        .child(DummyView)
+        .child(TextView::new("(пусто — сейчас)"))
+}
+
+/// The selection and the column "Apply" puts the resolved personas in.
+fn init_selection_line() -> impl View {
+    let mut column = SelectView::new().popup();
+    column.add_item("To", SendLetterForm::TO_COLUMN);
+    column.add_item("Cc", SendLetterForm::CC_COLUMN);
+    column.add_item("Bcc", SendLetterForm::BCC_COLUMN);
+    LinearLayout::horizontal()
+        .child(TextView::new("Адресаты:"))
+        .child(DummyView)
+        .child(
+            EditView::new()
+                .with_name(SendLetterForm::SELECTION)
+                .full_width(),
+        )
+        .child(DummyView)
+        .child(column.with_name(SendLetterForm::SELECTION_COLUMN))
+}
+
+fn selection_hint() -> String {
+    "Метки, персоны и e-mail персон: | — или, & — и, ! — кроме, скобки группируют.".into()
 }
 
-fn init_address_panel(people: &[Persona]) -> impl View {
+fn describe_selection(chosen: &[Identity], listed: usize) -> String {
+    let mut info = format!("Выбрано: {}", chosen.len());
+    if !chosen.is_empty() {
+        info.push_str(&format!(
+            " — {}",
+            chosen[..chosen.len().min(listed)].join(", ")
+        ));
+    }
+    if chosen.len() > listed {
+        info.push_str(&format!(" и ещё {}", chosen.len() - listed));
+    }
+    info
+}
+
+fn init_address_panel(people: &[Persona], to: &[Identity]) -> impl View {
     let mut select = ListView::new();
+    select.add_child("", TextView::new("—   To  Cc  Bcc"));
     for persona in people {
-        let checkbox = Checkbox::new().unchecked();
         let label = make_ref(persona).identity();
-        select.add_child(&label, checkbox);
+        let is_to = to.contains(&label);
+        select.add_child(&label, init_address_columns(is_to));
     }
     select.scrollable()
 }
 
+/// One group per persona, so a persona is in To, Cc, Bcc or none of them.
+fn init_address_columns(is_to: bool) -> LinearLayout {
+    let mut group = RadioGroup::new();
+    let none = group.button(SendLetterForm::NONE_COLUMN, "");
+    let to = group.button(SendLetterForm::TO_COLUMN, "");
+    let to = if is_to { to.selected() } else { to };
+    LinearLayout::horizontal()
+        .child(none)
+        .child(DummyView)
+        .child(to)
+        .child(DummyView)
+        .child(group.button(SendLetterForm::CC_COLUMN, ""))
+        .child(DummyView)
+        .child(group.button(SendLetterForm::BCC_COLUMN, ""))
+}
+
 fn init_letter_panel(letter: &Letter) -> impl View {
     letter_view(letter)
 }
@@ -3,16 +3,20 @@ use std::sync::mpsc;
 use cursive::{
     event::{Event, EventResult, Key, MouseButton, MouseEvent},
     view::ViewWrapper,
-    views::{Dialog, DialogFocus, LinearLayout, ResizedView, ScrollView, TextArea},
+    views::{
+        Dialog, DialogFocus, LinearLayout, ResizedView, ScrollView, SelectView, TextArea, TextView,
+    },
     wrap_impl, View,
 };
 
 use crate::{
     controller::{settings::Settings, ControllerSignal},
     data_handler::{make_mut, make_ref},
-    ui::utils::{dismiss, form_view},
+    ui::utils::{dismiss, form_view, get_view_from_dialog, no_selection_info},
 };
 
+use super::profile::ProfileForm;
+
 pub struct SettingsForm {
     view: Dialog,
     settings: Settings,
@@ -20,16 +24,51 @@ pub struct SettingsForm {
 }
 
 impl SettingsForm {
+    pub const NAME: &str = "settings_form";
+
     pub fn new(settings: Settings, controller_tx: &mpsc::Sender<ControllerSignal>) -> Self {
-        Self {
+        let mut form = Self {
             view: init_dialog(&settings),
             settings,
             controller_tx: controller_tx.clone(),
+        };
+        form.update_profiles();
+        form
+    }
+
+    pub fn update_profiles(&mut self) {
+        let settings = self.settings.clone();
+        let settings = make_ref(&settings);
+        let select = self.get_profile_select_mut();
+        let selected = select.selected_id();
+        select.clear();
+        for profile in settings.profiles.iter() {
+            let label = if profile.name == settings.default_profile {
+                format!("{} (по умолчанию)", profile.name)
+            } else {
+                profile.name.to_owned()
+            };
+            select.add_item(label, profile.name.to_owned());
+        }
+        if let Some(selected) = selected.filter(|&n| n < select.len()) {
+            select.set_selection(selected);
         }
     }
 }
 
 impl SettingsForm {
+    fn button_event(&mut self, n: usize) -> EventResult {
+        match n {
+            0 => self.event_submit(),
+            1 => self.event_cancel(),
+            2 => self.event_new_profile(),
+            3 => self.event_edit_profile(),
+            4 => self.event_remove_profile(),
+            5 => self.event_make_default(),
+            _ => EventResult::Ignored,
+        }
+    }
+
     fn event_submit(&mut self) -> EventResult {
         self.update_settings();
         self.controller_tx
@@ -41,50 +80,81 @@ impl SettingsForm {
     fn event_cancel(&mut self) -> EventResult {
         dismiss()
     }
-}
 
-impl SettingsForm {
-    const SMTP_RELAY_I: usize = 0;
-    const SMTP_USER_I: usize = 1;
-    const SMTP_PASSWORD_I: usize = 2;
-    const LETTER_FROM_I: usize = 3;
-    const PLURAL_TITLE_I: usize = 4;
-    const SINGLE_GREET_I: usize = 5;
-    const SIGNATURE_I: usize = 6;
-
-    fn update_settings(&mut self) {
-        self.update_smtp_relay();
-        self.update_smtp_user();
-        self.update_smtp_password();
-        self.update_letter_from();
-        self.update_plural_title();
-        self.update_single_greet();
-        self.update_signature();
+    fn event_new_profile(&mut self) -> EventResult {
+        self.open_profile_form(String::new())
     }
 
-    fn update_smtp_relay(&mut self) {
-        make_mut(&self.settings).smtp_relay = self.get_data(Self::SMTP_RELAY_I);
+    fn event_edit_profile(&mut self) -> EventResult {
+        match self.get_selected_profile() {
+            Some(name) => self.open_profile_form(name),
+            None => {
+                no_selection_info(&self.controller_tx, "редактирования", "профиль");
+                EventResult::consumed()
+            }
+        }
     }
 
-    fn update_smtp_user(&mut self) {
-        make_mut(&self.settings).smtp_user = self.get_data(Self::SMTP_USER_I);
+    fn event_remove_profile(&mut self) -> EventResult {
+        match self.get_selected_profile() {
+            Some(_) if make_ref(&self.settings).profiles.len() < 2 => {
+                self.controller_tx
+                    .send(ControllerSignal::Log(
+                        "Нельзя удалить единственный профиль отправителя.".into(),
+                    ))
+                    .unwrap();
+            }
+            Some(name) => {
+                make_mut(&self.settings).remove_profile(name);
+                self.update_profiles();
+                self.controller_tx
+                    .send(ControllerSignal::SaveSettings)
+                    .unwrap();
+            }
+            None => no_selection_info(&self.controller_tx, "удаления", "профиль"),
+        }
+        EventResult::consumed()
     }
 
-    fn update_smtp_password(&mut self) {
-        make_mut(&self.settings).smtp_password = self.get_data(Self::SMTP_PASSWORD_I);
+    fn event_make_default(&mut self) -> EventResult {
+        match self.get_selected_profile() {
+            Some(name) => {
+                make_mut(&self.settings).default_profile = name;
+                self.update_profiles();
+                self.controller_tx
+                    .send(ControllerSignal::SaveSettings)
+                    .unwrap();
+            }
+            None => no_selection_info(&self.controller_tx, "выбора по умолчанию", "профиль"),
+        }
+        EventResult::consumed()
     }
-    fn update_letter_from(&mut self) {
-        make_mut(&self.settings).letter_from = self.get_data(Self::LETTER_FROM_I);
+
+    fn open_profile_form(&self, key: String) -> EventResult {
+        let form = ProfileForm::new(key, self.settings.clone(), &self.controller_tx);
+        EventResult::with_cb_once(move |c| c.add_layer(form))
     }
-    fn update_plural_title(&mut self) {
-        make_mut(&self.settings).plural_title = self.get_data(Self::PLURAL_TITLE_I);
+}
+
+impl SettingsForm {
+    const FORM_I: usize = 0;
+    const PROFILES_I: usize = 2;
+    const SINGLE_GREET_I: usize = 0;
+    const BCC_THRESHOLD_I: usize = 1;
+
+    fn update_settings(&mut self) {
+        self.update_single_greet();
+        self.update_bcc_threshold();
     }
+
     fn update_single_greet(&mut self) {
         make_mut(&self.settings).single_greet = self.get_data(Self::SINGLE_GREET_I);
     }
 
-    fn update_signature(&mut self) {
-        make_mut(&self.settings).letter_signature = self.get_data(Self::SIGNATURE_I);
+    fn update_bcc_threshold(&mut self) {
+        if let Ok(threshold) = self.get_data(Self::BCC_THRESHOLD_I).trim().parse() {
+            make_mut(&self.settings).bcc_threshold = threshold;
+        }
     }
 
     fn get_data(&self, index: usize) -> String {
@@ -92,11 +162,7 @@ impl SettingsForm {
     }
 
     fn get_area(&self, index: usize) -> &TextArea {
-        let scroll = self
-            .view
-            .get_content()
-            .downcast_ref::<ScrollView<LinearLayout>>()
-            .unwrap();
+        let scroll = get_view_from_dialog::<ScrollView<LinearLayout>>(&self.view, Self::FORM_I);
         let entry = scroll
             .get_inner()
             .get_child(index)
@@ -111,6 +177,23 @@ impl SettingsForm {
             .get_inner();
         widget
     }
+
+    fn get_selected_profile(&self) -> Option<String> {
+        get_view_from_dialog::<SelectView>(&self.view, Self::PROFILES_I)
+            .selection()
+            .map(|name| name.as_ref().to_owned())
+    }
+
+    fn get_profile_select_mut(&mut self) -> &mut SelectView {
+        self.view
+            .get_content_mut()
+            .downcast_mut::<LinearLayout>()
+            .unwrap()
+            .get_child_mut(Self::PROFILES_I)
+            .unwrap()
+            .downcast_mut::<SelectView>()
+            .unwrap()
+    }
 }
 
 impl ViewWrapper for SettingsForm {
@@ -127,8 +210,7 @@ impl ViewWrapper for SettingsForm {
                     self.with_view_mut(|v| v.on_event(event))
                         .unwrap_or(EventResult::Ignored);
                     match self.view.focus() {
-                        DialogFocus::Button(0) => self.event_submit(),
-                        DialogFocus::Button(1) => self.event_cancel(),
+                        DialogFocus::Button(n) => self.button_event(n),
                         _ => EventResult::Ignored,
                     }
                 } else {
@@ -136,8 +218,7 @@ impl ViewWrapper for SettingsForm {
                 }
             }
             Event::Key(Key::Enter) => match self.view.focus() {
-                DialogFocus::Button(0) => self.event_submit(),
-                DialogFocus::Button(1) => self.event_cancel(),
+                DialogFocus::Button(n) => self.button_event(n),
                 _ => self
                     .with_view_mut(|v| v.on_event(event))
                     .unwrap_or(EventResult::Ignored),
@@ -154,17 +235,20 @@ fn init_dialog(settings: &Settings) -> Dialog {
     Dialog::around(init_form(settings))
         .button("OK", |_| {})
         .button("Cancel", |_| {})
+        .button("New profile", |_| {})
+        .button("Edit profile", |_| {})
+        .button("Remove profile", |_| {})
+        .button("Make default", |_| {})
 }
 
 fn init_form(settings: &Settings) -> impl View {
     let settings = make_ref(settings);
-    form_view(vec![
-        ("SMTP-сервер:", &settings.smtp_relay),
-        ("SMTP-пользователь:", &settings.smtp_user),
-        ("SMTP-пароль:", &settings.smtp_password),
-        ("Отправитель:", &settings.letter_from),
-        ("Обращение:", &settings.plural_title),
-        ("Приветствие:", &settings.single_greet),
-        ("Подпись:", &settings.letter_signature),
-    ])
+    let bcc_threshold = settings.bcc_threshold.to_string();
+    LinearLayout::vertical()
+        .child(form_view(vec![
+            ("Приветствие:", &settings.single_greet),
+            ("Порог BCC:", &bcc_threshold),
+        ]))
+        .child(TextView::new("Профили отправителя:"))
+        .child(SelectView::<String>::new())
 }
@@ -1,26 +1,33 @@
-use std::{mem::take, sync::mpsc};
+use std::{collections::HashMap, mem::take, sync::mpsc};
 
 use cursive::{
     event::{Event, EventResult, Key, MouseButton, MouseEvent},
     reexports::ahash::HashSet,
     view::{Scrollable, ViewWrapper},
     views::{
-        Checkbox, Dialog, DialogFocus, LinearLayout, ListChild, ListView, ResizedView, ScrollView,
-        TextArea,
+        Checkbox, Dialog, DialogFocus, DummyView, LinearLayout, ListChild, ListView, ResizedView,
+        ScrollView, SelectView, TextArea, TextView,
     },
     wrap_impl, View,
 };
 
 use crate::{
     controller::ControllerSignal,
-    data_handler::{make_mut, make_ref, tag::Tag, Identity, Represent},
-    ui::utils::{dismiss, text_entry_full_width},
+    data_handler::{
+        make_mut, make_ref,
+        rule::{Rule, RuleError},
+        tag::{creates_cycle, Tag},
+        Identity, Represent,
+    },
+    ui::utils::{dismiss, get_from_layout, text_entry_full_width},
 };
 
 pub struct TagForm {
     view: Dialog,
     key: Identity,
     tag: Tag,
+    /// The parent of every tag as it is stored now.
+    parents: HashMap<Identity, Option<Identity>>,
     controller_tx: mpsc::Sender<ControllerSignal>,
 }
 
@@ -29,12 +36,27 @@ impl TagForm {
         key: Identity,
         tag: Tag,
         persona_list: &[Identity],
+        tags: Vec<Tag>,
         controller_tx: &mpsc::Sender<ControllerSignal>,
     ) -> Self {
+        let parents = tags
+            .iter()
+            .map(|tag| {
+                let tag = make_ref(tag);
+                (tag.identity(), tag.parent().map(str::to_owned))
+            })
+            .collect::<HashMap<_, _>>();
+        let mut labels = parents
+            .keys()
+            .filter(|label| **label != key)
+            .cloned()
+            .collect::<Vec<_>>();
+        labels.sort();
         Self {
-            view: init_dialog(&tag, persona_list),
+            view: init_dialog(&tag, persona_list, labels),
             key,
             tag,
+            parents,
             controller_tx: controller_tx.clone(),
         }
     }
@@ -43,9 +65,33 @@ impl TagForm {
 impl TagForm {
     fn event_submit(&mut self) -> EventResult {
         let label = self.get_tag_label();
+        let parent = self.get_parent();
+        if let Some(ref parent) = parent {
+            let parent_of = |tag: &str| self.parents.get(tag).cloned().flatten();
+            if *parent == label || creates_cycle(&self.key, parent, parent_of) {
+                self.controller_tx
+                    .send(ControllerSignal::Log(format!(
+                        "Метка {:?} не может входить в {:?}: получится цикл.",
+                        label, parent
+                    )))
+                    .unwrap();
+                return EventResult::consumed();
+            }
+        }
+        let rule = match self.get_rule() {
+            Ok(rule) => rule,
+            Err(e) => {
+                self.controller_tx
+                    .send(ControllerSignal::Log(format!("Правило не принято: {}.", e)))
+                    .unwrap();
+                return EventResult::consumed();
+            }
+        };
         let checked_persona = self.get_checked_persona();
         let mut tag = make_mut(&self.tag);
         tag.set_label(label);
+        tag.set_parent(parent);
+        tag.set_rule(rule);
         tag.set_persona_ids(checked_persona);
         self.controller_tx
             .send(ControllerSignal::CompleteEditTag {
@@ -72,6 +118,41 @@ impl TagForm {
         self.get_label_area().get_content().to_owned()
     }
 
+    fn get_parent(&self) -> Option<Identity> {
+        self.get_layout()
+            .get_child(1)
+            .unwrap()
+            .downcast_ref::<LinearLayout>()
+            .unwrap()
+            .get_child(2)
+            .unwrap()
+            .downcast_ref::<SelectView<Option<Identity>>>()
+            .unwrap()
+            .selection()
+            .and_then(|parent| parent.as_ref().clone())
+    }
+
+    /// An empty rule makes an ordinary tag.
+    fn get_rule(&self) -> Result<Option<Rule>, RuleError> {
+        let text = get_from_layout::<LinearLayout>(self.get_layout(), 2);
+        let text = get_from_layout::<ResizedView<TextArea>>(text, 2)
+            .get_inner()
+            .get_content()
+            .trim();
+        if text.is_empty() {
+            Ok(None)
+        } else {
+            text.parse().map(Some)
+        }
+    }
+
+    fn get_layout(&self) -> &LinearLayout {
+        self.view
+            .get_content()
+            .downcast_ref::<LinearLayout>()
+            .unwrap()
+    }
+
     fn get_checked_persona(&self) -> Vec<Identity> {
         let mut result = vec![];
         for item in self.get_persona_list_view().children() {
@@ -87,10 +168,7 @@ impl TagForm {
     }
 
     fn get_label_area(&self) -> &TextArea {
-        self.view
-            .get_content()
-            .downcast_ref::<LinearLayout>()
-            .unwrap()
+        self.get_layout()
             .get_child(0)
             .unwrap()
             .downcast_ref::<LinearLayout>()
@@ -103,11 +181,8 @@ impl TagForm {
     }
 
     fn get_persona_list_view(&self) -> &ListView {
-        self.view
-            .get_content()
-            .downcast_ref::<LinearLayout>()
-            .unwrap()
-            .get_child(1)
+        self.get_layout()
+            .get_child(3)
             .unwrap()
             .downcast_ref::<ScrollView<ListView>>()
             .unwrap()
@@ -149,19 +224,47 @@ impl ViewWrapper for TagForm {
     }
 }
 
-fn init_dialog(tag: &Tag, persona_list: &[Identity]) -> Dialog {
-    Dialog::around(init_view(tag, persona_list))
+fn init_dialog(tag: &Tag, persona_list: &[Identity], labels: Vec<Identity>) -> Dialog {
+    Dialog::around(init_view(tag, persona_list, labels))
         .title(format!("Редактируем метку {}", make_ref(tag).identity()))
         .button("Ok", |_| {})
         .button("Cancel", |_| {})
 }
 
-fn init_view(tag: &Tag, persona_list: &[Identity]) -> impl View {
+fn init_view(tag: &Tag, persona_list: &[Identity], labels: Vec<Identity>) -> impl View {
     LinearLayout::vertical()
         .child(text_entry_full_width("Метка:", &make_ref(tag).identity()))
+        .child(init_parent_line(tag, labels))
+        .child(text_entry_full_width(
+            "Правило:",
+            &make_ref(tag)
+                .rule()
+                .map(|rule| rule.to_string())
+                .unwrap_or_default(),
+        ))
         .child(init_list(tag, persona_list))
 }
 
+fn init_parent_line(tag: &Tag, labels: Vec<Identity>) -> impl View {
+    let parent = make_ref(tag).parent().map(str::to_owned);
+    let mut select = SelectView::<Option<Identity>>::new().popup();
+    select.add_item("(нет)", None);
+    let selected = labels
+        .iter()
+        .position(|label| Some(label) == parent.as_ref())
+        .map(|index| index + 1);
+    for label in labels {
+        select.add_item(label.to_owned(), Some(label));
+    }
+    if let Some(index) = selected {
+        select.set_selection(index);
+    }
+    LinearLayout::horizontal()
+        .child(TextView::new("Входит в:"))
+        .child(DummyView)
+        .child(select)
+}
+
 fn init_list(tag: &Tag, persona_list: &[Identity]) -> impl View {
     let mut view = ListView::new();
     let tag = make_ref(tag);
@@ -169,7 +272,7 @@ fn init_list(tag: &Tag, persona_list: &[Identity]) -> impl View {
     for persona in persona_list {
         let check_box = Checkbox::new();
         view.add_child(
-            &persona,
+            persona,
             if need_to_check.contains(persona) {
                 check_box.checked()
             } else {
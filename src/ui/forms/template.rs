@@ -0,0 +1,200 @@
+use std::{mem::take, sync::mpsc};
+
+use cursive::{
+    event::{Event, EventResult, Key, MouseButton, MouseEvent},
+    view::{Scrollable, ViewWrapper},
+    views::{Dialog, DialogFocus, DummyView, LinearLayout, ScrollView, SelectView, TextView},
+    wrap_impl, View,
+};
+
+use crate::{
+    controller::ControllerSignal,
+    data_handler::{make_mut, make_ref, template::Template, Identity},
+    ui::utils::{dismiss, get_from_layout, get_text_from_form_entry, linear_layout_form},
+};
+
+/// Name, topic and text of a template, the tag its letters are addressed to
+/// and the attachments it was saved with.
+pub struct TemplateForm {
+    view: Dialog,
+    key: Identity,
+    template: Template,
+    controller_tx: mpsc::Sender<ControllerSignal>,
+}
+
+impl TemplateForm {
+    const NAME_INDEX: usize = 0;
+    const TOPIC_INDEX: usize = 1;
+    const TEXT_INDEX: usize = 2;
+    const TAG_INDEX: usize = 3;
+    const ATTACHMENT_INDEX: usize = 4;
+
+    pub fn new(
+        key: Identity,
+        template: Template,
+        tags: Vec<Identity>,
+        controller_tx: &mpsc::Sender<ControllerSignal>,
+    ) -> Self {
+        Self {
+            view: init_dialog(&template, tags),
+            key,
+            template,
+            controller_tx: controller_tx.clone(),
+        }
+    }
+
+    fn button_event(&mut self, index: usize) -> EventResult {
+        match index {
+            0 => self.event_submit(),
+            1 => self.event_cancel(),
+            2 => self.event_clear_attachment(),
+            _ => EventResult::Ignored,
+        }
+    }
+
+    fn event_submit(&mut self) -> EventResult {
+        let name = get_text_from_form_entry(&self.view, Self::NAME_INDEX).trim();
+        if name.is_empty() {
+            self.controller_tx
+                .send(ControllerSignal::Log("У шаблона должно быть имя.".into()))
+                .unwrap();
+            return EventResult::consumed();
+        }
+        let mut template = make_mut(&self.template);
+        template.set_name(name);
+        template.set_topic(get_text_from_form_entry(&self.view, Self::TOPIC_INDEX));
+        template.set_text(get_text_from_form_entry(&self.view, Self::TEXT_INDEX));
+        template.set_tag(self.get_tag());
+        drop(template);
+        self.controller_tx
+            .send(ControllerSignal::CompleteEditTemplate {
+                key: take(&mut self.key),
+                template: self.template.clone(),
+            })
+            .unwrap();
+        dismiss()
+    }
+
+    fn event_cancel(&self) -> EventResult {
+        dismiss()
+    }
+
+    fn event_clear_attachment(&mut self) -> EventResult {
+        make_mut(&self.template).clear_attachment();
+        let info = attachment_line(&self.template);
+        self.get_layout_mut()
+            .get_child_mut(Self::ATTACHMENT_INDEX)
+            .unwrap()
+            .downcast_mut::<TextView>()
+            .unwrap()
+            .set_content(info);
+        EventResult::consumed()
+    }
+
+    fn get_tag(&self) -> Option<Identity> {
+        get_from_layout::<LinearLayout>(self.get_layout(), Self::TAG_INDEX)
+            .get_child(2)
+            .unwrap()
+            .downcast_ref::<SelectView<Option<Identity>>>()
+            .unwrap()
+            .selection()
+            .and_then(|tag| tag.as_ref().clone())
+    }
+
+    fn get_layout(&self) -> &LinearLayout {
+        self.view
+            .get_content()
+            .downcast_ref::<ScrollView<LinearLayout>>()
+            .unwrap()
+            .get_inner()
+    }
+
+    fn get_layout_mut(&mut self) -> &mut LinearLayout {
+        self.view
+            .get_content_mut()
+            .downcast_mut::<ScrollView<LinearLayout>>()
+            .unwrap()
+            .get_inner_mut()
+    }
+}
+
+impl ViewWrapper for TemplateForm {
+    wrap_impl!(self.view: Dialog);
+
+    fn wrap_on_event(&mut self, event: Event) -> EventResult {
+        match event {
+            Event::Mouse {
+                offset: _,
+                position: _,
+                event: MouseEvent::Press(btn),
+            } => {
+                if btn == MouseButton::Left {
+                    self.with_view_mut(|v| v.on_event(event))
+                        .unwrap_or(EventResult::Ignored);
+                    match self.view.focus() {
+                        DialogFocus::Button(n) => self.button_event(n),
+                        _ => EventResult::Ignored,
+                    }
+                } else {
+                    EventResult::Ignored
+                }
+            }
+            Event::Key(Key::Enter) => match self.view.focus() {
+                DialogFocus::Button(n) => self.button_event(n),
+                _ => self
+                    .with_view_mut(|v| v.on_event(event))
+                    .unwrap_or(EventResult::Ignored),
+            },
+            Event::Key(Key::Esc) => self.event_cancel(),
+            _ => self
+                .with_view_mut(|v| v.on_event(event))
+                .unwrap_or(EventResult::Ignored),
+        }
+    }
+}
+
+fn init_dialog(template: &Template, tags: Vec<Identity>) -> Dialog {
+    Dialog::around(init_view(template, tags))
+        .title(format!("Шаблон {}", make_ref(template).name()))
+        .button("OK", |_| {})
+        .button("Cancel", |_| {})
+        .button("Remove ALL files", |_| {})
+}
+
+fn init_view(template: &Template, tags: Vec<Identity>) -> impl View {
+    let tag_line = init_tag_line(template, tags);
+    let attachment = attachment_line(template);
+    let template = make_ref(template);
+    linear_layout_form(vec![
+        ("Шаблон:", template.name()),
+        ("Тема:", template.topic()),
+        ("Сообщение:", template.text()),
+    ])
+    .child(tag_line)
+    .child(TextView::new(attachment))
+    .scrollable()
+}
+
+fn init_tag_line(template: &Template, tags: Vec<Identity>) -> impl View {
+    let current = make_ref(template).tag().map(str::to_owned);
+    let mut select = SelectView::<Option<Identity>>::new().popup();
+    select.add_item("(нет)", None);
+    let selected = tags
+        .iter()
+        .position(|tag| Some(tag) == current.as_ref())
+        .map(|index| index + 1);
+    for tag in tags {
+        select.add_item(tag.to_owned(), Some(tag));
+    }
+    if let Some(index) = selected {
+        select.set_selection(index);
+    }
+    LinearLayout::horizontal()
+        .child(TextView::new("Кому:"))
+        .child(DummyView)
+        .child(select)
+}
+
+fn attachment_line(template: &Template) -> String {
+    format!("Вложения: {}", make_ref(template).attachment_info())
+}
@@ -2,18 +2,49 @@ use std::sync::mpsc;
 
 use cursive::menu::Tree;
 
-use crate::controller::ControllerSignal;
+use crate::{controller::ControllerSignal, data_handler::letter::LetterFilter};
 
 pub fn email_menu(controller_tx: &mpsc::Sender<ControllerSignal>) -> Tree {
     use ControllerSignal::*;
     let new_letter_tx = controller_tx.clone();
+    let letters_tx = controller_tx.clone();
+    let search_tx = controller_tx.clone();
+    let template_tx = controller_tx.clone();
+    let schedule_tx = controller_tx.clone();
     let tree = Tree::new()
         .leaf("New Letter", move |_| {
             new_letter_tx.send(NewLetter).unwrap();
         })
+        .leaf("Letters...", move |_| {
+            letters_tx
+                .send(SelectLetter(LetterFilter::default()))
+                .unwrap();
+        })
+        .leaf("Search...", move |_| {
+            search_tx.send(SearchLetters).unwrap();
+        })
+        .leaf("New letter from template...", move |_| {
+            template_tx.send(SelectTemplate).unwrap();
+        })
+        .leaf("Schedule...", move |_| {
+            schedule_tx.send(OpenSchedule).unwrap();
+        })
         .delimiter();
     let settings_tx = controller_tx.clone();
+    let backups_tx = controller_tx.clone();
+    let undo_tx = controller_tx.clone();
+    let trash_tx = controller_tx.clone();
     tree.leaf("Settings...", move |_| {
         settings_tx.send(OpenSettings).unwrap();
     })
+    .leaf("Restore from backup...", move |_| {
+        backups_tx.send(OpenBackups).unwrap();
+    })
+    .delimiter()
+    .leaf("Undo removal", move |_| {
+        undo_tx.send(Undo).unwrap();
+    })
+    .leaf("Trash...", move |_| {
+        trash_tx.send(OpenTrash).unwrap();
+    })
 }
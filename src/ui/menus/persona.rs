@@ -1,12 +1,8 @@
-use std::{
-    fs,
-    io::{self, BufRead},
-    sync::mpsc,
-};
+use std::sync::mpsc;
 
 use cursive::menu::Tree;
 
-use crate::{controller::ControllerSignal, data_handler::persona::import_persona};
+use crate::{controller::ControllerSignal, data_handler::persona::import_persona_file};
 
 pub fn persona_menu(controller_tx: &mpsc::Sender<ControllerSignal>) -> Tree {
     let mut tree = Tree::new();
@@ -14,24 +10,22 @@ pub fn persona_menu(controller_tx: &mpsc::Sender<ControllerSignal>) -> Tree {
     tree.add_leaf("Select...", move |_| {
         select_tx.send(ControllerSignal::SelectPersona).unwrap()
     });
+    let report_tx = controller_tx.clone();
+    tree.add_leaf("Contact report...", move |_| {
+        report_tx.send(ControllerSignal::OpenReport).unwrap()
+    });
     let import_tx = controller_tx.clone();
     tree.add_delimiter();
     tree.add_leaf("Import 'persona.tsv'", move |_| {
-        if let Ok(f) = fs::File::open("persona.tsv") {
-            let import = io::BufReader::new(f)
-                .lines()
-                .skip(1)
-                .filter_map(|x| x.ok())
-                .filter_map(|data| import_persona(&data))
-                .collect::<Vec<_>>();
+        if let Ok(import) = import_persona_file("persona.tsv") {
             import_tx
                 .send(ControllerSignal::ImportPersona(import))
                 .unwrap();
         } else {
             import_tx
-                .send(ControllerSignal::Log(format!(
-                    "Открытие файла 'persona.tsv' завершилось провалом!"
-                )))
+                .send(ControllerSignal::Log(
+                    "Открытие файла 'persona.tsv' завершилось провалом!".into(),
+                ))
                 .unwrap();
         }
     });
@@ -7,7 +7,10 @@ use cursive::{
     View,
 };
 
-use crate::controller::ControllerSignal;
+use crate::{controller::ControllerSignal, data_handler::letter::DateTime};
+
+/// How dates and times are typed in forms.
+pub const TIME_FORMAT: &str = "%d.%m.%Y %H:%M";
 
 pub fn text_entry_full_width(label: &str, content: &str) -> LinearLayout {
     LinearLayout::horizontal()
@@ -43,7 +46,7 @@ pub fn get_area_from_form(dialog: &Dialog, entry_index: usize) -> &TextArea {
         .downcast_ref::<ScrollView<LinearLayout>>()
         .unwrap()
         .get_inner();
-    let entry_layout = get_from_layout::<LinearLayout>(&main_layout, entry_index);
+    let entry_layout = get_from_layout::<LinearLayout>(main_layout, entry_index);
     get_from_layout::<ResizedView<TextArea>>(entry_layout, TEXT_AREA_IN_ENTRY).get_inner()
 }
 
@@ -70,6 +73,28 @@ pub fn no_selection_info(
         .unwrap();
 }
 
+/// Reads a local time written as [`TIME_FORMAT`].
+pub fn parse_time(text: &str) -> Option<DateTime> {
+    chrono::NaiveDateTime::parse_from_str(text.trim(), TIME_FORMAT)
+        .ok()?
+        .and_local_timezone(chrono::Local)
+        .earliest()
+}
+
+/// Logs why `text` is not a time in the future; `None` if it is not.
+pub fn future_time(text: &str, controller_tx: &mpsc::Sender<ControllerSignal>) -> Option<DateTime> {
+    let error = match parse_time(text) {
+        Some(at) if at > chrono::Local::now() => return Some(at),
+        Some(_) => "Это время уже прошло.".to_owned(),
+        None => format!(
+            "Время {:?} не понято: пишите ДД.ММ.ГГГГ ЧЧ:ММ.",
+            text.trim()
+        ),
+    };
+    controller_tx.send(ControllerSignal::Log(error)).unwrap();
+    None
+}
+
 pub fn dismiss() -> EventResult {
     EventResult::with_cb(|c| {
         c.pop_layer();